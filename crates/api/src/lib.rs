@@ -7,8 +7,13 @@ pub type VideoId = Uuid<Video>;
 pub type UserId = Uuid<User>;
 pub type UserSessionId = Uuid<UserSession>;
 pub type OidcMappingId = Uuid<OidcMapping>;
+pub type ApiTokenId = Uuid<ApiToken>;
 pub type CrowdId = Uuid<CrowdState>;
 pub type CrowdQueueId = Uuid<CrowdQueueEntry>;
+pub type CrowdParticipantId = Uuid<CrowdParticipant>;
+pub type CrowdHistoryEntryId = Uuid<CrowdHistoryEntry>;
+pub type CrowdAuditEntryId = Uuid<CrowdAuditEntry>;
+pub type PlaylistId = Uuid<Playlist>;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Download {
@@ -67,6 +72,9 @@ pub struct UserSession {
     pub csrf_token: Option<String>,
     pub nonce: Option<String>,
     pub pkce_code_verifier: Option<String>,
+    pub auth_provider_id: Option<String>,
+    pub user_agent: Option<String>,
+    pub login_started_at: Option<OffsetDateTime>,
     //
     // // Only set after logged in
     pub user_id: Option<UserId>,
@@ -82,6 +90,159 @@ pub struct OidcMapping {
     pub user_id: UserId,
 }
 
+/// A logged in session of the user, e.g. one per browser.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SessionInfo {
+    pub user_session_id: UserSessionId,
+    pub user_agent: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub last_active_at: OffsetDateTime,
+    /// The session making the request.
+    pub current: bool,
+}
+
+/// A login identity linked to a user.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LinkedIdentity {
+    pub oidc_mapping_id: OidcMappingId,
+    pub oidc_issuer_url: String,
+    /// Name of the configured provider for the issuer, if there still is one.
+    pub provider_name: Option<String>,
+    pub created_at: OffsetDateTime,
+}
+
+/// What a personal API token may be used for. Browser sessions are not
+/// limited by scopes.
+#[derive(
+    strum::Display,
+    strum::EnumString,
+    strum::VariantArray,
+    Clone,
+    Copy,
+    Debug,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+)]
+pub enum ApiTokenScope {
+    #[serde(rename = "videos:read")]
+    #[strum(serialize = "videos:read")]
+    VideosRead,
+    #[serde(rename = "playlists:read")]
+    #[strum(serialize = "playlists:read")]
+    PlaylistsRead,
+    #[serde(rename = "downloads:read")]
+    #[strum(serialize = "downloads:read")]
+    DownloadsRead,
+    /// Adding videos to the download queue.
+    #[serde(rename = "downloads:write")]
+    #[strum(serialize = "downloads:write")]
+    DownloadsWrite,
+    /// Joining and controlling crowds as the user.
+    #[serde(rename = "crowd:control")]
+    #[strum(serialize = "crowd:control")]
+    CrowdControl,
+}
+
+/// A personal API token, for scripts and bots. The token itself is only
+/// shown when it is created.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ApiToken {
+    pub api_token_id: ApiTokenId,
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    pub created_at: OffsetDateTime,
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ApiTokenCreateRequest {
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ApiTokenCreated {
+    pub api_token: ApiToken,
+    /// Sent as `Authorization: Bearer <token>`. It can't be retrieved again.
+    pub token: String,
+}
+
+/// Changes an admin makes to a user. Fields left out are not changed.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct AdminUserUpdate {
+    pub is_approved: Option<bool>,
+    pub is_admin: Option<bool>,
+}
+
+/// A crowd as recorded in the database, kept after the crowd has ended.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Crowd {
+    pub crowd_id: CrowdId,
+    pub name: String,
+    pub owner_id: Option<UserId>,
+    pub started_at: OffsetDateTime,
+    pub ended_at: Option<OffsetDateTime>,
+    /// Only the owner can see the history of private crowds.
+    pub private: bool,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+/// One video played in a crowd.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdHistoryEntry {
+    pub crowd_history_id: CrowdHistoryEntryId,
+    pub crowd_id: CrowdId,
+    /// `None` for YouTube videos that were not downloaded.
+    pub video_id: Option<VideoId>,
+    /// Set for videos played from YouTube.
+    pub youtube_id: Option<String>,
+    /// User who queued the video, `None` for guests, autofill and deleted users.
+    pub added_by: Option<UserId>,
+    /// Handle of who queued the video when it played, `None` for autofill.
+    pub added_by_handle: Option<String>,
+    pub started_at: OffsetDateTime,
+    /// Unset while the video is playing, or if the crowd ended abruptly.
+    pub ended_at: Option<OffsetDateTime>,
+    /// Whether the video was skipped before it finished.
+    pub skipped: bool,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdHistory {
+    pub crowd: Crowd,
+    /// Played entries, oldest first.
+    pub entries: Vec<CrowdHistoryEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Playlist {
+    pub playlist_id: PlaylistId,
+    pub name: String,
+    pub user_id: UserId,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PlaylistWithVideos {
+    pub playlist: Playlist,
+    pub video_ids: Vec<VideoId>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PlaylistFromCrowdRequest {
+    /// Defaults to the name of the crowd.
+    pub name: Option<String>,
+    /// Leave out videos that were skipped.
+    #[serde(default)]
+    pub exclude_skipped: bool,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct AuthContext {
     // pub user: User,
@@ -95,6 +256,9 @@ pub enum ApiError {
     CsrfFailure,
     NotAuthorized,
     AuthorizationPending,
+    /// The request conflicts with the current state, e.g. removing the last
+    /// login identity.
+    Conflict(String),
     InternalServerError,
     Unknown(String),
 }
@@ -104,6 +268,19 @@ pub struct DownloadRequest {
     pub url: String,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AuthProvider {
+    pub id: String,
+    /// Shown on the login button.
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AuthUrlRequest {
+    /// Id of the provider to log in with.
+    pub provider: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct AuthUrlResponse {
     pub url: String,
@@ -121,22 +298,230 @@ pub struct CrowdListEntry {
     pub crowd_id: CrowdId,
     pub name: String,
     pub participant_count: usize,
+    pub join_code: String,
+    pub requires_password: bool,
+    /// Private crowds are only listed for their owner.
+    pub private: bool,
+    /// Handle of the user who started the crowd.
+    pub owner: Option<String>,
+    pub current_video_title: Option<String>,
+    pub queue_length: usize,
+    /// Playback starts at this time, `None` once the crowd is running.
+    pub scheduled_start: Option<time::UtcDateTime>,
+}
+
+/// Counters for crowd connections since the server started.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct CrowdMetrics {
+    /// How often a participant fell behind the update broadcast.
+    pub lagged_participants: u64,
+    /// Total number of updates skipped by lagging participants.
+    pub skipped_updates: u64,
+    /// Full state snapshots sent to participants after lagging.
+    pub resync_snapshots: u64,
+}
+
+/// Version of the crowd websocket protocol. Bump on any incompatible change to
+/// the crowd message types.
+pub const CROWD_PROTOCOL_VERSION: u32 = 5;
+
+/// First message on both crowd websockets. Always sent as JSON text so that
+/// mismatched versions can still understand each other.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdHandshake {
+    pub protocol_version: u32,
+    pub encoding: CrowdEncoding,
+}
+
+/// Reply to a [`CrowdHandshake`], also sent as JSON text. All following
+/// messages use the accepted encoding.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum CrowdHandshakeResponse {
+    Accepted { encoding: CrowdEncoding },
+    Rejected { server_version: u32, reason: String },
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CrowdEncoding {
+    /// JSON in text frames
+    #[default]
+    Json,
+    /// bincode (serde, standard config) in binary frames
+    Bincode,
+}
+
+/// Initial message sent by a player to create a new crowd.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdCreateRequest {
+    pub name: String,
+    pub password: Option<String>,
+    /// Hides the crowd from the crowd list. It can still be joined with the join code.
+    #[serde(default)]
+    pub private: bool,
+    /// How long the join code stays valid. The code never expires if unset.
+    pub join_code_lifetime_minutes: Option<u32>,
+    #[serde(default)]
+    pub settings: CrowdSettings,
+    /// Holds playback until this time, participants can prepare the queue
+    /// in the meantime.
+    #[serde(default)]
+    pub scheduled_start: Option<time::UtcDateTime>,
+    /// Fills the queue with one of the owner's playlists.
+    #[serde(default)]
+    pub playlist_id: Option<PlaylistId>,
+}
+
+/// Reply to a [`CrowdCreateRequest`], sent to the player before any commands.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdCreated {
+    pub crowd_id: CrowdId,
+    pub join_code: String,
+}
+
+/// Initial message sent by a participant to join an existing crowd.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdJoinRequest {
+    pub crowd_id: CrowdId,
+    pub password: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdJoinInfo {
+    pub crowd_id: CrowdId,
+    pub name: String,
+    pub requires_password: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct CrowdState {
     pub playback_time: f64,
+    /// Length of the currently playing video in seconds.
+    pub duration: f64,
     pub is_paused: bool,
     pub speed: f64,
     pub currently_playing: Option<CrowdQueueId>,
     pub queue: CrowdQueue,
+    pub settings: CrowdSettings,
+    pub skip_votes: usize,
+    /// The most recent chat messages and reactions, oldest first.
+    pub chat: std::collections::VecDeque<CrowdChatMessage>,
+    /// Connected participants in the order they joined.
+    pub participants: Vec<CrowdParticipant>,
+    /// The most recent crowd control commands, oldest first.
+    pub audit_log: std::collections::VecDeque<CrowdAuditEntry>,
+    /// Playback starts at this time, `None` once the crowd is running.
+    pub scheduled_start: Option<time::UtcDateTime>,
+    /// Only logged in users can join.
+    pub guests_blocked: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdSettings {
+    pub queue_mode: CrowdQueueMode,
+    /// Fraction of the participants that have to vote to skip the current entry.
+    pub skip_threshold: f64,
+    /// How many upcoming entries a single participant may have in the queue.
+    /// Counts all connections of a logged in user together.
+    #[serde(default)]
+    pub max_entries_per_participant: Option<usize>,
+    #[serde(default)]
+    pub repeat: CrowdRepeatMode,
+    /// Play the upcoming entries in random order.
+    #[serde(default)]
+    pub shuffle: bool,
+    /// Where to get videos from when the queue runs empty.
+    #[serde(default)]
+    pub autofill: CrowdAutofillMode,
+}
+
+impl Default for CrowdSettings {
+    fn default() -> Self {
+        Self {
+            queue_mode: CrowdQueueMode::Manual,
+            skip_threshold: 0.5,
+            max_entries_per_participant: None,
+            repeat: CrowdRepeatMode::Off,
+            shuffle: false,
+            autofill: CrowdAutofillMode::Off,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CrowdQueueMode {
+    /// The queue is only reordered through explicit `MoveInQueue` commands.
+    #[default]
+    Manual,
+    /// Upcoming entries are ordered by their votes, ties are broken by insertion time.
+    Voting,
+    /// Upcoming entries are interleaved so every participant gets a turn. All
+    /// connections of a logged in user share their turns.
+    RoundRobin,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CrowdRepeatMode {
+    #[default]
+    Off,
+    /// Replay the current entry when it ends.
+    One,
+    /// Start over from the beginning of the queue when the last entry ends.
+    All,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CrowdAutofillMode {
+    #[default]
+    Off,
+    /// Pick a random video from the library.
+    Random,
+    /// Pick the video from the library that this crowd played least recently.
+    LeastRecentlyPlayed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdParticipant {
+    pub participant_id: CrowdParticipantId,
+    /// The logged in user, `None` for guests.
+    pub user_id: Option<UserId>,
+    pub handle: String,
+    pub profile_picture_url: Option<String>,
+    pub joined_at: time::UtcDateTime,
+    /// The participant has the crowd page in the background.
+    pub idle: bool,
+    /// Muted participants can not chat or react.
+    pub muted: bool,
+    /// The owner started the crowd and can kick and mute participants.
+    pub is_owner: bool,
 }
 
 pub type CrowdQueue = indexmap::IndexMap<CrowdQueueId, CrowdQueueEntry>;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct CrowdQueueEntry {
-    pub video_id: VideoId,
+    pub video: CrowdVideo,
+    /// The participant that queued the entry, `None` for entries added by autofill.
+    pub added_by: Option<CrowdParticipantId>,
+    pub added_at: time::UtcDateTime,
+    /// Upvotes minus downvotes
+    pub score: i64,
+}
+
+/// What a crowd queue entry plays.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum CrowdVideo {
+    /// A downloaded video from the library.
+    Local(VideoId),
+    /// A YouTube video that is not downloaded, played through the embedded
+    /// YouTube player.
+    Youtube(String),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CrowdVote {
+    Up,
+    Down,
+    Clear,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -147,11 +532,93 @@ pub enum CrowdParticipantCommand {
     SetSpeed(f64),
     GoTo(CrowdQueueId),
     AddToQueue(VideoId),
+    /// Queues a YouTube video by link or id. Videos that are already
+    /// downloaded are queued from the library instead.
+    AddYoutubeToQueue(String),
     MoveInQueue {
         entry_to_move: CrowdQueueId,
         position: CrowdQueuePosition,
     },
     DeleteFromQueue(CrowdQueueId),
+    Vote {
+        entry: CrowdQueueId,
+        vote: CrowdVote,
+    },
+    VoteSkip,
+    Chat(String),
+    React(CrowdReaction),
+    SetIdle(bool),
+    /// Removes a participant from the crowd, kicked users can not join again.
+    /// Guests can not be told apart, so kicking one also blocks guests. Only
+    /// allowed for the owner.
+    Kick(CrowdParticipantId),
+    /// Removes all guests and keeps new ones out, or lets them join again.
+    /// Only allowed for the owner.
+    BlockGuests(bool),
+    /// Only allowed for the owner.
+    Mute {
+        participant: CrowdParticipantId,
+        muted: bool,
+    },
+    /// Reverts the queue change recorded in the given audit log entry.
+    Undo(CrowdAuditEntryId),
+}
+
+/// How many applied commands are kept in the audit log.
+pub const CROWD_AUDIT_LOG_LENGTH: usize = 100;
+
+/// A crowd control command that was applied, and who sent it.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdAuditEntry {
+    pub audit_entry_id: CrowdAuditEntryId,
+    pub participant_id: CrowdParticipantId,
+    /// Handle of the participant when the command was applied.
+    pub handle: String,
+    pub applied_at: time::UtcDateTime,
+    pub command: CrowdParticipantCommand,
+    /// Adding, moving and deleting queue entries can be undone once.
+    pub undoable: bool,
+    pub undone: bool,
+}
+
+/// How many chat messages and reactions are kept for participants that join later.
+pub const CROWD_CHAT_HISTORY_LENGTH: usize = 50;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct CrowdChatMessage {
+    pub participant_id: CrowdParticipantId,
+    pub sent_at: time::UtcDateTime,
+    pub content: CrowdChatContent,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum CrowdChatContent {
+    Message(String),
+    Reaction(CrowdReaction),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CrowdReaction {
+    Laugh,
+    Love,
+    Wow,
+    Clap,
+    Fire,
+}
+
+impl CrowdReaction {
+    pub const ALL: [CrowdReaction; 5] =
+        [Self::Laugh, Self::Love, Self::Wow, Self::Clap, Self::Fire];
+
+    pub fn emoji(self) -> &'static str {
+        match self {
+            Self::Laugh => "😂",
+            Self::Love => "❤️",
+            Self::Wow => "😮",
+            Self::Clap => "👏",
+            Self::Fire => "🔥",
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -161,14 +628,61 @@ pub enum CrowdQueuePosition {
     After(CrowdQueueId),
 }
 
+/// Commands sent from the server to the crowd player.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum CrowdPlayerCommand {
+    Ping,
+    SetPlaybackPosition(f64),
+    SetIsPaused(bool),
+    SetSpeed(f64),
+    /// The server owns the queue, the player should play `currently_playing`.
+    Queue {
+        currently_playing: Option<CrowdQueueId>,
+        queue: CrowdQueue,
+    },
+    Chat(CrowdChatMessage),
+    Participants(Vec<CrowdParticipant>),
+    /// The scheduled start time was reached, play the current entry from the
+    /// beginning as of the given time.
+    Start(time::UtcDateTime),
+    GuestsBlocked(bool),
+    /// The server could not handle a message from the player.
+    Error(String),
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum CrowdPlayerUpdate {
     Ping,
     PlaybackPosition(f64),
+    Duration(f64),
     IsPaused(bool),
     Speed(f64),
     Queue {
-        currently_playing: CrowdQueueId,
+        currently_playing: Option<CrowdQueueId>,
         queue: CrowdQueue,
     },
+    Settings(CrowdSettings),
+    /// The given entry finished playing.
+    Ended(CrowdQueueId),
+    SkipVotes {
+        votes: usize,
+        needed: usize,
+    },
+    /// Full state of the crowd, sent to participants when they join.
+    State(CrowdState),
+    Chat(CrowdChatMessage),
+    Participants(Vec<CrowdParticipant>),
+    /// Sent to a participant after joining, tells it its own id.
+    Identity(CrowdParticipantId),
+    /// The participant was removed from the crowd by the owner.
+    Kicked(CrowdParticipantId),
+    /// A new or changed entry of the audit log.
+    Audit(CrowdAuditEntry),
+    /// The scheduled start time was reached and playback started.
+    Started(time::UtcDateTime),
+    GuestsBlocked(bool),
+    /// Sent by the player to kick, mute or block guests, it acts as the owner.
+    Moderate(CrowdParticipantCommand),
+    /// The server could not handle a message from the participant.
+    Error(String),
 }
@@ -0,0 +1,29 @@
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+use sha2::{Digest, Sha256};
+
+/// Makes tokens recognizable, e.g. for secret scanners.
+const TOKEN_PREFIX: &str = "listen_pat_";
+
+/// A new random personal access token.
+pub fn generate() -> String {
+    format!("{TOKEN_PREFIX}{}", hex::encode(rand::random::<[u8; 32]>()))
+}
+
+/// Only the hash of a token is stored. The tokens are random, so a plain hash
+/// is enough, and they can be looked up by it.
+pub fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let authorization = headers.get(AUTHORIZATION)?.to_str().ok()?;
+
+    let (left, right) = authorization.split_once(' ')?;
+
+    if left != "Bearer" {
+        return None;
+    }
+
+    Some(right.trim())
+}
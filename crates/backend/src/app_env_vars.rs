@@ -1,21 +1,38 @@
 use anyhow::{Context, Result};
 
+/// How a variable is read, `Option` for variables that may be unset.
+trait FromEnvVar: Sized {
+    fn from_env_var(var: &str) -> Result<Self>;
+}
+
+impl FromEnvVar for String {
+    fn from_env_var(var: &str) -> Result<Self> {
+        std::env::var(var).context(format!("Missing env variable {var}"))
+    }
+}
+
+impl FromEnvVar for Option<String> {
+    fn from_env_var(var: &str) -> Result<Self> {
+        Ok(std::env::var(var).ok())
+    }
+}
+
 macro_rules! app_env_vars {
     (
         $(
-            $name:ident: $var:expr
+            $name:ident: $ty:ty = $var:expr
         ),* $(,)?
     ) => {
         pub struct AppEnvVars {
             $(
-                pub $name: String,
+                pub $name: $ty,
             )*
         }
         impl AppEnvVars {
             pub fn load_from_env() -> Result<Self> {
                 Ok(Self {
                     $(
-                        $name: std::env::var($var).context(format!("Missing env variable {}", $var))?,
+                        $name: FromEnvVar::from_env_var($var)?,
                     )*
                 })
             }
@@ -24,8 +41,12 @@ macro_rules! app_env_vars {
 }
 
 app_env_vars!(
-    database_url: "DATABASE_URL",
-    jwt_secret: "JWT_SECRET",
-    google_oidc_client_id: "GOOGLE_OIDC_CLIENT_ID",
-    google_oidc_client_secret: "GOOGLE_OIDC_CLIENT_SECRET",
+    database_url: String = "DATABASE_URL",
+    jwt_secret: String = "JWT_SECRET",
+    public_url: Option<String> = "PUBLIC_URL",
+    bind_address: Option<String> = "BIND_ADDRESS",
+    tls_cert_file: Option<String> = "TLS_CERT_FILE",
+    tls_key_file: Option<String> = "TLS_KEY_FILE",
+    crowd_command_channel_size: Option<String> = "CROWD_COMMAND_CHANNEL_SIZE",
+    crowd_update_channel_size: Option<String> = "CROWD_UPDATE_CHANNEL_SIZE",
 );
@@ -0,0 +1,488 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{bail, Context};
+use oauth2::{AuthorizationCode, ClientId, ClientSecret, PkceCodeVerifier, RedirectUrl};
+use openidconnect::{IssuerUrl, Nonce};
+use serde::Deserialize;
+
+use crate::{
+    error::Result,
+    github::GithubClient,
+    oidc::{AuthUrl, OidcClaims, OidcClient, METADATA_REFRESH_INTERVAL},
+    server_state::ServerConfig,
+};
+
+const DEFAULT_CONFIG_FILE: &str = "auth_providers.toml";
+
+/// Providers users can log in with, in the order they are shown on the login
+/// page.
+#[derive(Clone)]
+pub struct AuthProviders(Arc<Vec<AuthProvider>>);
+
+pub struct AuthProvider {
+    pub id: String,
+    pub name: String,
+    client: AuthClient,
+}
+
+enum AuthClient {
+    Oidc(OidcClient),
+    Github(GithubClient),
+    #[cfg(feature = "dev-login")]
+    Dev(crate::dev_login::DevClient),
+}
+
+#[derive(Deserialize)]
+struct AuthProvidersConfig {
+    #[serde(rename = "provider", default)]
+    providers: Vec<AuthProviderConfig>,
+}
+
+#[derive(Deserialize)]
+struct AuthProviderConfig {
+    id: String,
+    name: String,
+    #[serde(flatten)]
+    kind: AuthProviderKind,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    /// Read the secret from this environment variable instead, to keep it out
+    /// of the config file.
+    client_secret_env: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum AuthProviderKind {
+    /// Any OpenID Connect issuer supporting discovery, e.g. Google, GitLab or
+    /// a Keycloak realm.
+    Oidc {
+        issuer_url: String,
+    },
+    Github,
+    /// Fixed test users, only available with the `dev-login` feature.
+    Dev {
+        #[serde(default)]
+        #[cfg_attr(not(feature = "dev-login"), allow(dead_code))]
+        users: Vec<String>,
+    },
+}
+
+impl AuthProviders {
+    /// Loads the providers from the file in `AUTH_PROVIDERS_FILE`, or
+    /// `auth_providers.toml` if it exists. Without a config file, Google is set
+    /// up from `GOOGLE_OIDC_CLIENT_ID` and `GOOGLE_OIDC_CLIENT_SECRET`, or the
+    /// test users are used when built with the `dev-login` feature and
+    /// `DEV_LOGIN=1` is set. Test users from the config file need `DEV_LOGIN=1`
+    /// as well.
+    pub fn load(server_config: &ServerConfig) -> anyhow::Result<Self> {
+        // Test users have to be asked for, so a dev build can't end up with them by accident
+        let dev_login =
+            std::env::var("DEV_LOGIN").is_ok_and(|value| value == "1" || value == "true");
+        let config = match std::env::var("AUTH_PROVIDERS_FILE") {
+            Ok(path) => Some(read_config(Path::new(&path))?),
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Some(read_config(Path::new(DEFAULT_CONFIG_FILE))?)
+            }
+            Err(_) => None,
+        };
+        let config = match config {
+            Some(config) => config,
+            None => default_config(dev_login)?,
+        };
+        Self::from_config(config, dev_login, server_config)
+    }
+
+    fn from_config(
+        config: AuthProvidersConfig,
+        dev_login: bool,
+        server_config: &ServerConfig,
+    ) -> anyhow::Result<Self> {
+        let redirect_url = RedirectUrl::from_url(server_config.url("auth/callback"));
+        let mut providers: Vec<AuthProvider> = Vec::new();
+        for provider in config.providers {
+            if providers.iter().any(|p| p.id == provider.id) {
+                bail!("Duplicate auth provider {}", provider.id);
+            }
+            let client = match provider.kind {
+                AuthProviderKind::Oidc { ref issuer_url } => {
+                    let (client_id, client_secret) = credentials(&provider)?;
+                    AuthClient::Oidc(OidcClient::new(
+                        IssuerUrl::new(issuer_url.clone()).context("Invalid issuer url")?,
+                        client_id,
+                        client_secret,
+                        redirect_url.clone(),
+                    ))
+                }
+                AuthProviderKind::Github => {
+                    let (client_id, client_secret) = credentials(&provider)?;
+                    AuthClient::Github(GithubClient::new(
+                        client_id,
+                        client_secret,
+                        redirect_url.clone(),
+                    ))
+                }
+                AuthProviderKind::Dev { .. } if !dev_login => bail!(
+                    "Auth provider {} offers test users and requires DEV_LOGIN=1",
+                    provider.id
+                ),
+                #[cfg(feature = "dev-login")]
+                AuthProviderKind::Dev { ref users } => {
+                    tracing::warn!("Test users can log in through {}", provider.id);
+                    let users = if users.is_empty() {
+                        crate::dev_login::DEFAULT_USERS.map(String::from).to_vec()
+                    } else {
+                        users.clone()
+                    };
+                    AuthClient::Dev(crate::dev_login::DevClient::new(
+                        users,
+                        server_config.url("api/auth/dev/authorize"),
+                        redirect_url.url().clone(),
+                    ))
+                }
+                #[cfg(not(feature = "dev-login"))]
+                AuthProviderKind::Dev { .. } => bail!(
+                    "Auth provider {} requires building with the dev-login feature",
+                    provider.id
+                ),
+            };
+            providers.push(AuthProvider {
+                id: provider.id,
+                name: provider.name,
+                client,
+            });
+        }
+        if providers.is_empty() {
+            bail!("No auth providers configured");
+        }
+
+        Ok(Self(Arc::new(providers)))
+    }
+
+    /// Runs discovery for all OIDC providers, so misconfigured providers are
+    /// reported at startup instead of on the first login.
+    pub async fn discover(&self) -> anyhow::Result<()> {
+        for provider in self.0.iter() {
+            if let AuthClient::Oidc(client) = &provider.client {
+                client
+                    .refresh_metadata()
+                    .await
+                    .with_context(|| format!("Auth provider {} is unavailable", provider.id))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the cached OIDC metadata and signing keys fresh. Failures are
+    /// logged and the previous metadata stays in use.
+    pub async fn refresh_periodically(self) {
+        loop {
+            tokio::time::sleep(METADATA_REFRESH_INTERVAL).await;
+            for provider in self.0.iter() {
+                if let AuthClient::Oidc(client) = &provider.client {
+                    if let Err(e) = client.refresh_metadata().await {
+                        tracing::warn!("Refreshing auth provider {}: {e:?}", provider.id);
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "dev-login")]
+    pub fn dev_client(&self) -> Option<&crate::dev_login::DevClient> {
+        self.0.iter().find_map(|provider| match &provider.client {
+            AuthClient::Dev(client) => Some(client),
+            _ => None,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&AuthProvider> {
+        self.0.iter().find(|provider| provider.id == id)
+    }
+
+    /// The provider logging in through the issuer, to name linked identities.
+    pub fn get_by_issuer_url(&self, issuer_url: &str) -> Option<&AuthProvider> {
+        self.0
+            .iter()
+            .find(|provider| provider.issuer_url().as_str() == issuer_url)
+    }
+
+    pub fn list(&self) -> Vec<api::AuthProvider> {
+        self.0
+            .iter()
+            .map(|provider| api::AuthProvider {
+                id: provider.id.clone(),
+                name: provider.name.clone(),
+            })
+            .collect()
+    }
+}
+
+impl AuthProvider {
+    pub fn issuer_url(&self) -> IssuerUrl {
+        match &self.client {
+            AuthClient::Oidc(client) => client.issuer_url().clone(),
+            AuthClient::Github(_) => GithubClient::issuer_url(),
+            #[cfg(feature = "dev-login")]
+            AuthClient::Dev(_) => crate::dev_login::DevClient::issuer_url(),
+        }
+    }
+
+    pub async fn auth_url(&self) -> Result<AuthUrl> {
+        match &self.client {
+            AuthClient::Oidc(client) => client.auth_url().await,
+            AuthClient::Github(client) => Ok(client.auth_url()),
+            #[cfg(feature = "dev-login")]
+            AuthClient::Dev(client) => Ok(client.auth_url()),
+        }
+    }
+
+    pub async fn auth_verify(
+        &self,
+        code: AuthorizationCode,
+        pkce_code_verifier: PkceCodeVerifier,
+        nonce: Nonce,
+        issuer_url: IssuerUrl,
+    ) -> Result<OidcClaims> {
+        match &self.client {
+            AuthClient::Oidc(client) => {
+                client
+                    .auth_verify(code, pkce_code_verifier, nonce, issuer_url)
+                    .await
+            }
+            AuthClient::Github(client) => client.auth_verify(code, pkce_code_verifier).await,
+            #[cfg(feature = "dev-login")]
+            AuthClient::Dev(client) => client.auth_verify(code),
+        }
+    }
+}
+
+fn read_config(path: &Path) -> anyhow::Result<AuthProvidersConfig> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read auth providers from {}", path.display()))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("Invalid auth providers in {}", path.display()))
+}
+
+fn credentials(provider: &AuthProviderConfig) -> anyhow::Result<(ClientId, ClientSecret)> {
+    let Some(client_id) = &provider.client_id else {
+        bail!("Auth provider {} needs a client_id", provider.id);
+    };
+    let client_secret = match (&provider.client_secret, &provider.client_secret_env) {
+        (Some(secret), None) => secret.clone(),
+        (None, Some(var)) => std::env::var(var).with_context(|| {
+            format!(
+                "Missing env variable {var} for auth provider {}",
+                provider.id
+            )
+        })?,
+        _ => bail!(
+            "Auth provider {} needs exactly one of client_secret and client_secret_env",
+            provider.id
+        ),
+    };
+    Ok((
+        ClientId::new(client_id.clone()),
+        ClientSecret::new(client_secret),
+    ))
+}
+
+fn default_config(dev_login: bool) -> anyhow::Result<AuthProvidersConfig> {
+    let client_id = match std::env::var("GOOGLE_OIDC_CLIENT_ID") {
+        Ok(client_id) => client_id,
+        Err(_) if dev_login && cfg!(feature = "dev-login") => {
+            return Ok(AuthProvidersConfig {
+                providers: vec![AuthProviderConfig {
+                    id: "dev".to_string(),
+                    name: "Test user".to_string(),
+                    kind: AuthProviderKind::Dev { users: Vec::new() },
+                    client_id: None,
+                    client_secret: None,
+                    client_secret_env: None,
+                }],
+            });
+        }
+        Err(_) if dev_login => bail!("DEV_LOGIN requires building with the dev-login feature"),
+        Err(_) => bail!(
+            "No auth providers file and missing env variable GOOGLE_OIDC_CLIENT_ID, \
+             set DEV_LOGIN=1 to use the test users of the dev-login feature"
+        ),
+    };
+    Ok(AuthProvidersConfig {
+        providers: vec![AuthProviderConfig {
+            id: "google".to_string(),
+            name: "Google".to_string(),
+            kind: AuthProviderKind::Oidc {
+                issuer_url: "https://accounts.google.com".to_string(),
+            },
+            client_id: Some(client_id),
+            client_secret: None,
+            client_secret_env: Some("GOOGLE_OIDC_CLIENT_SECRET".to_string()),
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_config() -> ServerConfig {
+        ServerConfig {
+            public_url: url::Url::parse("https://listen.example.com/").unwrap(),
+            bind_address: "127.0.0.1:3000".parse().unwrap(),
+            tls: None,
+        }
+    }
+
+    fn from_toml(config: &str, dev_login: bool) -> anyhow::Result<AuthProviders> {
+        AuthProviders::from_config(toml::from_str(config).unwrap(), dev_login, &server_config())
+    }
+
+    #[test]
+    fn providers_keep_the_order_of_the_config_file() {
+        let providers = from_toml(
+            r#"
+            [[provider]]
+            id = "github"
+            name = "GitHub"
+            kind = "github"
+            client_id = "id"
+            client_secret = "secret"
+
+            [[provider]]
+            id = "gitlab"
+            name = "GitLab"
+            kind = "oidc"
+            issuer_url = "https://gitlab.com"
+            client_id = "id"
+            client_secret = "secret"
+            "#,
+            false,
+        )
+        .unwrap();
+        let ids: Vec<_> = providers.list().into_iter().map(|p| p.id).collect();
+        assert_eq!(ids, ["github", "gitlab"]);
+    }
+
+    #[test]
+    fn duplicate_provider_ids_are_rejected() {
+        let provider = r#"
+            [[provider]]
+            id = "github"
+            name = "GitHub"
+            kind = "github"
+            client_id = "id"
+            client_secret = "secret"
+            "#;
+        assert!(from_toml(&provider.repeat(2), false).is_err());
+    }
+
+    #[test]
+    fn dev_provider_in_config_requires_dev_login() {
+        let config = r#"
+            [[provider]]
+            id = "dev"
+            name = "Test user"
+            kind = "dev"
+            "#;
+        assert!(from_toml(config, false).is_err());
+        assert_eq!(from_toml(config, true).is_ok(), cfg!(feature = "dev-login"));
+    }
+
+    #[cfg(feature = "dev-login")]
+    mod dev {
+        use axum::extract::{Query, State};
+        use oauth2::{AuthorizationCode, RedirectUrl};
+
+        use super::*;
+        use crate::dev_login::{self, DevClient};
+
+        fn dev_providers() -> AuthProviders {
+            let base = url::Url::parse("https://listen.example.com/").unwrap();
+            AuthProviders(Arc::new(vec![AuthProvider {
+                id: "dev".to_string(),
+                name: "Test user".to_string(),
+                client: AuthClient::Dev(DevClient::new(
+                    vec!["alice".to_string(), "bob".to_string()],
+                    base.join("api/auth/dev/authorize").unwrap(),
+                    RedirectUrl::from_url(base.join("auth/callback").unwrap())
+                        .url()
+                        .clone(),
+                )),
+            }]))
+        }
+
+        /// Follows the link for `user` on the authorize page, like a browser would.
+        fn callback_for(page: &str, user: &str) -> url::Url {
+            let link = page
+                .split("<a href=\"")
+                .skip(1)
+                .find(|link| link.contains(&format!(">{user}</a>")))
+                .and_then(|link| link.split('"').next())
+                .expect("authorize page should link the user");
+            url::Url::parse(&link.replace("&amp;", "&")).unwrap()
+        }
+
+        fn query_param(url: &url::Url, key: &str) -> String {
+            url.query_pairs()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value.into_owned())
+                .unwrap()
+        }
+
+        #[tokio::test]
+        async fn dev_login_flow() {
+            let providers = dev_providers();
+            let provider = providers.get("dev").unwrap();
+
+            let auth_url = provider.auth_url().await.unwrap();
+            assert_eq!(auth_url.auth_url.path(), "/api/auth/dev/authorize");
+
+            let uri = auth_url.auth_url.as_str().parse().unwrap();
+            let page =
+                dev_login::authorize(State(providers.clone()), Query::try_from_uri(&uri).unwrap())
+                    .await
+                    .unwrap()
+                    .0;
+            let callback = callback_for(&page, "alice");
+            assert_eq!(callback.path(), "/auth/callback");
+            assert_eq!(
+                query_param(&callback, "state"),
+                *auth_url.csrf_token.secret()
+            );
+
+            let claims = provider
+                .auth_verify(
+                    AuthorizationCode::new(query_param(&callback, "code")),
+                    auth_url.pkce_code_verifier,
+                    auth_url.nonce,
+                    auth_url.issuer_url,
+                )
+                .await
+                .unwrap();
+            assert_eq!(claims.oidc_id.as_str(), "alice");
+            assert_eq!(claims.oidc_issuer_url, DevClient::issuer_url());
+            assert_eq!(
+                claims.preferred_username.map(|name| name.to_string()),
+                Some("alice".to_string())
+            );
+        }
+
+        #[tokio::test]
+        async fn dev_login_rejects_unknown_users() {
+            let providers = dev_providers();
+            let provider = providers.get("dev").unwrap();
+            let auth_url = provider.auth_url().await.unwrap();
+
+            let result = provider
+                .auth_verify(
+                    AuthorizationCode::new("mallory".to_string()),
+                    auth_url.pkce_code_verifier,
+                    auth_url.nonce,
+                    auth_url.issuer_url,
+                )
+                .await;
+            assert!(result.is_err());
+        }
+    }
+}
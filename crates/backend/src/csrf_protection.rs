@@ -1,19 +1,27 @@
-use std::str::FromStr;
-
 use api::ApiError;
-use axum::extract::{Host, Request};
-use hyper::{HeaderMap, Method, Uri};
-use leptos::prelude::*;
+use axum::extract::{Request, State};
+use hyper::{HeaderMap, Method};
+use url::{Origin, Url};
 
-use crate::error::ListenError;
+use crate::{api_tokens, error::ListenError, server_state::ServerConfig};
 
 pub async fn csrf_layer(
+    State(server_config): State<ServerConfig>,
     headers: HeaderMap,
-    host: Host,
     request: Request,
 ) -> Result<Request, ListenError> {
+    // Browsers never send the token on their own, so requests authenticated by
+    // it can't be forged
+    if api_tokens::bearer_token(&headers).is_some() {
+        return Ok(request);
+    }
+
     validate_csrf_header(&headers)?;
-    validate_origin_header(request.method(), &headers, &host)?;
+    validate_origin_header(
+        request.method(),
+        &headers,
+        &server_config.public_url.origin(),
+    )?;
 
     Ok(request)
 }
@@ -34,7 +42,7 @@ fn validate_csrf_header(headers: &HeaderMap) -> Result<(), ListenError> {
 fn validate_origin_header(
     method: &Method,
     headers: &HeaderMap,
-    host: &Host,
+    expected_origin: &Origin,
 ) -> Result<(), ListenError> {
     let Some(origin) = headers.get(hyper::http::header::ORIGIN) else {
         if method == Method::GET || method == Method::HEAD {
@@ -52,31 +60,23 @@ fn validate_origin_header(
         return Err(ApiError::CsrfFailure.into());
     };
 
-    let origin_uri = match Uri::from_str(origin) {
-        Ok(origin_uri) => origin_uri,
+    let origin = match Url::parse(origin) {
+        Ok(origin) => origin.origin(),
         Err(e) => {
             tracing::warn!("API request with an invalid `Origin` header: {e:?}");
             return Err(ApiError::CsrfFailure.into());
         }
     };
 
-    if !origin_uri.host().is_some_and(|h| h == host.0) {
-        tracing::warn!("API request where the `Origin` did not match the host header");
-        return Err(ApiError::CsrfFailure.into());
-    }
-
-    // TODO: Do we also want to validate the source port and protocol matched?
-    /*
-    if !origin_uri.scheme().is_some_and(|s| s == &Scheme::HTTPS) {
-        tracing::warn!("API request where the `Origin` header was not a https address");
-        return Err(ApiError::CsrfFailure.into());
-    }
-
-    if !origin_uri.port().is_some_and(|p| p == PORT) {
-        tracing::warn!("API request where the `Origin` port did not match the expected port");
+    // Scheme, host and port all have to match the site
+    if &origin != expected_origin {
+        tracing::warn!(
+            "API request where the `Origin` {} did not match {}",
+            origin.ascii_serialization(),
+            expected_origin.ascii_serialization()
+        );
         return Err(ApiError::CsrfFailure.into());
     }
-     */
 
     tracing::debug!("Origin header valid");
 
@@ -0,0 +1,118 @@
+use axum::{
+    extract::{Query, State},
+    response::Html,
+};
+use oauth2::{AuthorizationCode, CsrfToken, PkceCodeVerifier};
+use openidconnect::{EndUserEmail, EndUserUsername, IssuerUrl, Nonce, SubjectIdentifier};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    auth_providers::AuthProviders,
+    error::Result,
+    oidc::{AuthUrl, OidcClaims},
+};
+
+/// Issuer of the test identities. Never resolves, so it can't collide with a
+/// real provider.
+const ISSUER_URL: &str = "https://dev-login.invalid";
+pub const DEFAULT_USERS: [&str; 2] = ["alice", "bob"];
+
+/// Built-in provider for local development and tests. The authorization page
+/// lists fixed test users and sends the picked one back to the callback, with
+/// the user name as the code.
+#[derive(Clone)]
+pub struct DevClient {
+    users: Vec<String>,
+    authorize_url: Url,
+    redirect_url: Url,
+}
+
+#[derive(Deserialize)]
+pub struct AuthorizeParams {
+    state: String,
+}
+
+impl DevClient {
+    pub fn new(users: Vec<String>, authorize_url: Url, redirect_url: Url) -> Self {
+        Self {
+            users,
+            authorize_url,
+            redirect_url,
+        }
+    }
+
+    pub fn issuer_url() -> IssuerUrl {
+        IssuerUrl::new(ISSUER_URL.to_string()).expect("valid dev login issuer url")
+    }
+
+    pub fn auth_url(&self) -> AuthUrl {
+        let csrf_token = CsrfToken::new_random();
+        let mut auth_url = self.authorize_url.clone();
+        auth_url
+            .query_pairs_mut()
+            .append_pair("state", csrf_token.secret());
+
+        AuthUrl {
+            issuer_url: Self::issuer_url(),
+            auth_url,
+            csrf_token,
+            nonce: Nonce::new_random(),
+            pkce_code_verifier: PkceCodeVerifier::new(CsrfToken::new_random().secret().clone()),
+        }
+    }
+
+    pub fn auth_verify(&self, code: AuthorizationCode) -> Result<OidcClaims> {
+        let user = code.secret();
+        if !self.users.contains(user) {
+            tracing::warn!("Dev login for unknown test user {user}");
+            return Err(api::ApiError::NotAuthorized.into());
+        }
+
+        Ok(OidcClaims {
+            oidc_id: SubjectIdentifier::new(user.clone()),
+            oidc_issuer_url: Self::issuer_url(),
+            preferred_username: Some(EndUserUsername::new(user.clone())),
+            email: EndUserEmail::new(format!("{user}@example.com")),
+            picture_url: None,
+        })
+    }
+}
+
+/// Stands in for the provider's login page.
+pub async fn authorize(
+    State(auth_providers): State<AuthProviders>,
+    Query(params): Query<AuthorizeParams>,
+) -> Result<Html<String>> {
+    let Some(client) = auth_providers.dev_client() else {
+        return Err(api::ApiError::NotFound.into());
+    };
+
+    let links = client
+        .users
+        .iter()
+        .map(|user| {
+            let mut callback = client.redirect_url.clone();
+            callback
+                .query_pairs_mut()
+                .append_pair("code", user)
+                .append_pair("state", &params.state);
+            format!(
+                r#"<li><a href="{}">{}</a></li>"#,
+                escape(callback.as_str()),
+                escape(user)
+            )
+        })
+        .collect::<String>();
+
+    Ok(Html(format!(
+        "<!DOCTYPE html><title>Dev login</title><p>Log in as a test user:</p><ul>{links}</ul>"
+    )))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
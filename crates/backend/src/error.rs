@@ -45,6 +45,7 @@ impl IntoResponse for ListenError {
                 ApiError::CsrfFailure => StatusCode::BAD_REQUEST,
                 ApiError::NotAuthorized => StatusCode::UNAUTHORIZED,
                 ApiError::AuthorizationPending => StatusCode::FORBIDDEN,
+                ApiError::Conflict(_) => StatusCode::CONFLICT,
                 ApiError::InternalServerError => {
                     tracing::error!("Internal server error: {:?}\n{}", self.inner, self.context);
                     StatusCode::INTERNAL_SERVER_ERROR
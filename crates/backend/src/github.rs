@@ -0,0 +1,158 @@
+use oauth2::{
+    basic::BasicClient, reqwest, AuthorizationCode, ClientId, ClientSecret, CsrfToken,
+    EndpointNotSet, EndpointSet, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
+    TokenResponse, TokenUrl,
+};
+use openidconnect::{
+    EndUserEmail, EndUserPictureUrl, EndUserUsername, IssuerUrl, Nonce, SubjectIdentifier,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    error::Result,
+    oidc::{AuthUrl, OidcClaims},
+};
+
+const AUTHORIZE_URL: &str = "https://github.com/login/oauth/authorize";
+const TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const API_URL: &str = "https://api.github.com";
+/// GitHub is not an OIDC provider, but users are still mapped by issuer and
+/// subject. This is used as the issuer.
+const ISSUER_URL: &str = "https://github.com";
+
+/// Logs in through GitHub's OAuth2 apps. The user is looked up through the
+/// REST API after the code exchange, as GitHub does not issue id tokens.
+#[derive(Clone)]
+pub struct GithubClient {
+    client_id: ClientId,
+    client_secret: ClientSecret,
+    redirect_url: RedirectUrl,
+    reqwest_client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct GithubUser {
+    id: u64,
+    login: String,
+    avatar_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GithubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+impl GithubClient {
+    pub fn new(
+        client_id: ClientId,
+        client_secret: ClientSecret,
+        redirect_url: RedirectUrl,
+    ) -> Self {
+        Self {
+            client_id,
+            client_secret,
+            redirect_url,
+            reqwest_client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn issuer_url() -> IssuerUrl {
+        IssuerUrl::new(ISSUER_URL.to_string()).expect("valid GitHub issuer url")
+    }
+
+    fn get_client(
+        &self,
+    ) -> BasicClient<EndpointSet, EndpointNotSet, EndpointNotSet, EndpointNotSet, EndpointSet> {
+        BasicClient::new(self.client_id.clone())
+            .set_client_secret(self.client_secret.clone())
+            .set_auth_uri(oauth2::AuthUrl::new(AUTHORIZE_URL.to_string()).unwrap())
+            .set_token_uri(TokenUrl::new(TOKEN_URL.to_string()).unwrap())
+            .set_redirect_uri(self.redirect_url.clone())
+    }
+
+    pub fn auth_url(&self) -> AuthUrl {
+        let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
+
+        let (auth_url, csrf_token) = self
+            .get_client()
+            .authorize_url(CsrfToken::new_random)
+            .add_scope(Scope::new("read:user".to_string()))
+            .add_scope(Scope::new("user:email".to_string()))
+            .set_pkce_challenge(pkce_code_challenge)
+            .url();
+
+        AuthUrl {
+            issuer_url: Self::issuer_url(),
+            auth_url,
+            csrf_token,
+            // Not used by OAuth2, but stored with the session like for OIDC logins
+            nonce: Nonce::new_random(),
+            pkce_code_verifier,
+        }
+    }
+
+    pub async fn auth_verify(
+        &self,
+        code: AuthorizationCode,
+        pkce_code_verifier: PkceCodeVerifier,
+    ) -> Result<OidcClaims> {
+        let token_response = self
+            .get_client()
+            .exchange_code(code)
+            .set_pkce_verifier(pkce_code_verifier)
+            .request_async(&self.reqwest_client)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error exchanging code with GitHub: {e:?}");
+                api::ApiError::InternalServerError
+            })?;
+        let access_token = token_response.access_token().secret();
+
+        let user: GithubUser = self.get(access_token, "/user").await?;
+        let emails: Vec<GithubEmail> = self.get(access_token, "/user/emails").await?;
+        let email = emails
+            .into_iter()
+            .find(|email| email.primary && email.verified)
+            .ok_or_else(|| {
+                tracing::error!("GitHub user {} has no verified primary email", user.login);
+                api::ApiError::InternalServerError
+            })?;
+
+        Ok(OidcClaims {
+            oidc_id: SubjectIdentifier::new(user.id.to_string()),
+            oidc_issuer_url: Self::issuer_url(),
+            preferred_username: Some(EndUserUsername::new(user.login)),
+            email: EndUserEmail::new(email.email),
+            picture_url: user.avatar_url.map(EndUserPictureUrl::new),
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, access_token: &str, path: &str) -> Result<T> {
+        let body = self
+            .reqwest_client
+            .get(format!("{API_URL}{path}"))
+            .bearer_auth(access_token)
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            // GitHub rejects API requests without a user agent
+            .header(reqwest::header::USER_AGENT, "listen")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| {
+                tracing::error!("Error requesting {path} from GitHub: {e:?}");
+                api::ApiError::InternalServerError
+            })?
+            .bytes()
+            .await
+            .map_err(|e| {
+                tracing::error!("Error reading {path} from GitHub: {e:?}");
+                api::ApiError::InternalServerError
+            })?;
+        serde_json::from_slice(&body).map_err(|e| {
+            tracing::error!("Unexpected response for {path} from GitHub: {e:?}");
+            api::ApiError::InternalServerError.into()
+        })
+    }
+}
@@ -0,0 +1,105 @@
+use api::UserId;
+use axum::{
+    extract::{Path, Request, State},
+    Extension, Json,
+};
+
+use crate::{
+    error::{ListenErrorExt, Result},
+    PgPool,
+};
+
+/// Requires the user to be an admin. Has to run after `auth_required_layer`.
+pub async fn admin_required_layer(
+    Extension(user): Extension<database::models::User>,
+    request: Request,
+) -> Result<Request> {
+    if user.is_admin {
+        Ok(request)
+    } else {
+        tracing::warn!("Admin request by non-admin user {}", user.user_id);
+        Err(api::ApiError::NotAuthorized.into())
+    }
+}
+
+/// All users, pending users first, then by when they signed up.
+pub async fn list_users(State(pool): State<PgPool>) -> Result<Json<Vec<api::User>>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    let mut users = database::models::User::list(&mut conn)
+        .await
+        .with_internal_server_error()?;
+    users.sort_by_key(|user| (user.is_approved, user.created_at));
+
+    Ok(Json(users.into_iter().map(Into::into).collect()))
+}
+
+/// Approves or rejects a user and promotes or demotes admins. Admins can't
+/// change themselves, and the last approved admin can't be demoted or
+/// rejected.
+pub async fn update_user(
+    Extension(admin): Extension<database::models::User>,
+    State(pool): State<PgPool>,
+    Path(user_id): Path<UserId>,
+    Json(request): Json<api::AdminUserUpdate>,
+) -> Result<Json<api::User>> {
+    if user_id == admin.user_id {
+        return Err(
+            api::ApiError::Conflict("Admins can't change their own account".to_string()).into(),
+        );
+    }
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    let change = database::models::User::update_by_admin(
+        &mut conn,
+        user_id,
+        request.is_approved,
+        request.is_admin,
+    )
+    .await
+    .with_internal_server_error()?;
+    check_admin_change(change)?;
+    tracing::info!(
+        "Admin {} updated user {user_id}: {request:?}",
+        admin.user_id
+    );
+
+    let Some(user) = database::models::User::get_by_id(&mut conn, user_id)
+        .await
+        .with_internal_server_error()?
+    else {
+        return Err(api::ApiError::NotFound.into());
+    };
+    Ok(Json(user.into()))
+}
+
+/// Deletes a user with their sessions, identities and playlists. The last
+/// approved admin can't be deleted.
+pub async fn delete_user(
+    Extension(admin): Extension<database::models::User>,
+    State(pool): State<PgPool>,
+    Path(user_id): Path<UserId>,
+) -> Result<Json<()>> {
+    if user_id == admin.user_id {
+        return Err(
+            api::ApiError::Conflict("Admins can't delete their own account".to_string()).into(),
+        );
+    }
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    let change = database::models::User::delete_by_admin(&mut conn, user_id)
+        .await
+        .with_internal_server_error()?;
+    check_admin_change(change)?;
+    tracing::info!("Admin {} deleted user {user_id}", admin.user_id);
+
+    Ok(Json(()))
+}
+
+fn check_admin_change(change: database::models::AdminChange) -> Result<()> {
+    match change {
+        database::models::AdminChange::Changed => Ok(()),
+        database::models::AdminChange::NotFound => Err(api::ApiError::NotFound.into()),
+        database::models::AdminChange::LastAdmin => Err(api::ApiError::Conflict(
+            "There has to be an approved admin left".to_string(),
+        )
+        .into()),
+    }
+}
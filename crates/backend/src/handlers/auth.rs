@@ -1,7 +1,9 @@
 use api::UserSessionId;
 use axum::{
     extract::{Request, State},
-    response::IntoResponse,
+    http::{header::USER_AGENT, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
     Extension,
 };
 use axum_extra::extract::{cookie::Cookie, CookieJar};
@@ -10,8 +12,11 @@ use openidconnect::{IssuerUrl, Nonce};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    api_tokens,
+    auth_providers::AuthProviders,
     error::{ListenErrorExt, Result},
-    oidc::OidcClient,
+    jwt_keys::JwtKeys,
+    server_state::ServerConfig,
     PgPool,
 };
 
@@ -22,6 +27,27 @@ struct Claims {
 }
 
 const USER_COOKIE_NAME: &str = "__Host-user_token";
+/// Browsers reject `__Host-` cookies that are not secure, so plain http
+/// deployments use this name instead.
+const INSECURE_USER_COOKIE_NAME: &str = "user_token";
+
+/// Sessions that are not used for this long expire.
+pub(crate) const SESSION_IDLE_TIMEOUT: time::Duration = time::Duration::days(30);
+/// The expiry of a used session is extended at most this often, so requests
+/// don't all write to the database.
+const SESSION_REFRESH_INTERVAL: time::Duration = time::Duration::days(1);
+/// Logins have to be completed within this time after they were started.
+const LOGIN_ATTEMPT_TIMEOUT: time::Duration = time::Duration::minutes(10);
+/// How often the last use of an API token is recorded.
+const API_TOKEN_TOUCH_INTERVAL: time::Duration = time::Duration::minutes(5);
+
+fn user_cookie_name(server_config: &ServerConfig) -> &'static str {
+    if server_config.secure_cookies() {
+        USER_COOKIE_NAME
+    } else {
+        INSECURE_USER_COOKIE_NAME
+    }
+}
 
 #[derive(Clone, Debug)]
 pub enum SessionState {
@@ -33,27 +59,64 @@ pub enum SessionState {
         user_session: database::models::UserSession,
         user: database::models::User,
     },
+    /// Request with a personal API token, limited to the token's scopes.
+    ApiToken {
+        api_token: database::models::ApiToken,
+        user: database::models::User,
+    },
 }
 
 impl SessionState {
+    /// The user of a browser session, or of an API token with the scope.
+    pub fn user_with_scope(&self, scope: api::ApiTokenScope) -> Option<&database::models::User> {
+        match self {
+            Self::Authenticated { user, .. } => Some(user),
+            Self::ApiToken { api_token, user } if api_token.has_scope(scope) => Some(user),
+            Self::ApiToken { .. } | Self::None | Self::Unauthenticated { .. } => None,
+        }
+    }
+
+    async fn lookup_api_token(pool: &PgPool, token: &str) -> Result<Self> {
+        let mut conn = pool.get().await.with_internal_server_error()?;
+        let Some((api_token, user)) =
+            database::models::ApiToken::get_by_hash(&mut conn, &api_tokens::hash(token))
+                .await
+                .with_internal_server_error()?
+        else {
+            tracing::debug!("Request with an unknown API token");
+            return Err(api::ApiError::NotAuthorized.into());
+        };
+
+        let now = time::OffsetDateTime::now_utc();
+        if api_token
+            .last_used_at
+            .is_none_or(|last_used_at| last_used_at + API_TOKEN_TOUCH_INTERVAL < now)
+        {
+            database::models::ApiToken::touch(&mut conn, api_token.api_token_id)
+                .await
+                .with_internal_server_error()?;
+        }
+
+        Ok(Self::ApiToken { api_token, user })
+    }
+
     async fn lookup(
         pool: &PgPool,
-        jwt_decoding_key: &jsonwebtoken::DecodingKey,
+        jwt_keys: &JwtKeys,
+        cookie_name: &str,
         cookie_jar: &CookieJar,
     ) -> Result<Self> {
         let mut conn = pool.get().await.with_internal_server_error()?;
-        let Some(token) = cookie_jar.get(USER_COOKIE_NAME) else {
+        let Some(token) = cookie_jar.get(cookie_name) else {
             return Ok(Self::None);
         };
-        let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
-        let claims =
-            match jsonwebtoken::decode::<Claims>(&token.value(), &jwt_decoding_key, &validation) {
-                Ok(claims) => claims.claims,
-                Err(err) => {
-                    tracing::error!("Unable to validate jwt: {err:?}");
-                    return Ok(Self::None);
-                }
-            };
+        let claims = match jwt_keys.decode::<Claims>(token.value()) {
+            Ok(claims) => claims,
+            Err(err) => {
+                tracing::error!("Unable to validate jwt: {err:?}");
+                return Ok(Self::None);
+            }
+        };
 
         let Some(user_session) = database::models::UserSession::get_by_id(&mut conn, claims.sub)
             .await
@@ -62,6 +125,17 @@ impl SessionState {
             return Ok(Self::None);
         };
 
+        let now = time::OffsetDateTime::now_utc();
+        let expired = user_session.updated_at + SESSION_IDLE_TIMEOUT < now
+            || (user_session.user_id.is_none()
+                && user_session.created_at + LOGIN_ATTEMPT_TIMEOUT < now);
+        if expired {
+            database::models::UserSession::delete(&mut conn, user_session.user_session_id)
+                .await
+                .with_internal_server_error()?;
+            return Ok(Self::None);
+        }
+
         let Some(user_id) = user_session.user_id else {
             return Ok(Self::Unauthenticated { user_session });
         };
@@ -77,43 +151,167 @@ impl SessionState {
     }
 }
 
+/// Looks up the session of the request, or its API token if it has an
+/// `Authorization: Bearer` header. Sessions in use get their expiry extended,
+/// with a new cookie. Tokens signed with a previous key are reissued right
+/// away.
 pub async fn user_session_layer(
     State(pool): State<PgPool>,
-    State(jwt_decoding_key): State<jsonwebtoken::DecodingKey>,
+    State(jwt_keys): State<JwtKeys>,
+    State(server_config): State<ServerConfig>,
     cookie_jar: CookieJar,
     mut request: Request,
-) -> Result<Request> {
-    let session_state = SessionState::lookup(&pool, &jwt_decoding_key, &cookie_jar).await?;
+    next: Next,
+) -> Result<Response> {
+    let cookie_name = user_cookie_name(&server_config);
+    let session_state = match api_tokens::bearer_token(request.headers()) {
+        Some(token) => SessionState::lookup_api_token(&pool, token).await?,
+        None => SessionState::lookup(&pool, &jwt_keys, cookie_name, &cookie_jar).await?,
+    };
+
+    let old_key = cookie_jar
+        .get(cookie_name)
+        .is_some_and(|token| !jwt_keys.is_current(token.value()));
+    let refresh = match &session_state {
+        SessionState::Authenticated { user_session, .. }
+            if old_key
+                || user_session.updated_at + SESSION_REFRESH_INTERVAL
+                    < time::OffsetDateTime::now_utc() =>
+        {
+            let mut conn = pool.get().await.with_internal_server_error()?;
+            database::models::UserSession::touch(&mut conn, user_session.user_session_id)
+                .await
+                .with_internal_server_error()?;
+            Some(session_cookie(
+                &server_config,
+                &jwt_keys,
+                user_session.user_session_id,
+            )?)
+        }
+        _ => None,
+    };
 
     request.extensions_mut().insert(session_state);
+    let response = next.run(request).await;
 
-    Ok(request)
+    Ok(match refresh {
+        Some(cookie) => (cookie_jar.add(cookie), response).into_response(),
+        None => response,
+    })
 }
 
+/// Cookie with a token for the session, valid until the session expires if
+/// it is not used.
+fn session_cookie(
+    server_config: &ServerConfig,
+    jwt_keys: &JwtKeys,
+    user_session_id: UserSessionId,
+) -> Result<Cookie<'static>> {
+    let expiration = time::OffsetDateTime::now_utc().saturating_add(SESSION_IDLE_TIMEOUT);
+
+    let token = match jwt_keys.encode(&Claims {
+        exp: usize::try_from(expiration.unix_timestamp()).unwrap(),
+        sub: user_session_id,
+    }) {
+        Ok(token) => token,
+        Err(e) => {
+            tracing::error!("Unable to encode jwt token: {e:?}");
+            return Err(api::ApiError::InternalServerError.into());
+        }
+    };
+
+    Ok(Cookie::build((user_cookie_name(server_config), token))
+        .http_only(true)
+        .secure(server_config.secure_cookies())
+        .path("/")
+        .same_site(axum_extra::extract::cookie::SameSite::Strict)
+        .expires(expiration)
+        .build())
+}
+
+/// Deletes expired sessions and abandoned logins.
+pub async fn purge_expired_sessions(pool: &PgPool) -> anyhow::Result<()> {
+    let mut conn = pool.get().await?;
+    let now = time::OffsetDateTime::now_utc();
+    let deleted = database::models::UserSession::delete_expired(
+        &mut conn,
+        now - SESSION_IDLE_TIMEOUT,
+        now - LOGIN_ATTEMPT_TIMEOUT,
+    )
+    .await?;
+    if deleted > 0 {
+        tracing::info!("Purged {deleted} expired sessions");
+    }
+    Ok(())
+}
+
+/// Requires a logged in browser session. API tokens are rejected, routes
+/// usable with them have to use [`api_token_auth_layer`] instead.
 pub async fn auth_required_layer(
     Extension(session_state): Extension<SessionState>,
     mut request: Request,
 ) -> Result<Request> {
-    if let SessionState::Authenticated { user, .. } = session_state {
-        if user.is_approved {
-            request.extensions_mut().insert(user);
+    match session_state {
+        SessionState::Authenticated { user, .. } => {
+            request.extensions_mut().insert(approved(user)?);
             Ok(request)
-        } else {
-            tracing::debug!("Request with pending user");
-            Err(api::ApiError::AuthorizationPending.into())
         }
+        SessionState::ApiToken { .. } => {
+            tracing::debug!("API token used for a route without a scope");
+            Err(api::ApiError::NotAuthorized.into())
+        }
+        SessionState::None | SessionState::Unauthenticated { .. } => {
+            tracing::debug!("Request required authentication");
+            Err(api::ApiError::NotAuthorized.into())
+        }
+    }
+}
+
+/// Requires a logged in browser session or an API token with the scope.
+pub async fn api_token_auth_layer(
+    scope: api::ApiTokenScope,
+    Extension(session_state): Extension<SessionState>,
+    mut request: Request,
+) -> Result<Request> {
+    let Some(user) = session_state.user_with_scope(scope) else {
+        tracing::debug!("Request required authentication or the {scope} scope");
+        return Err(api::ApiError::NotAuthorized.into());
+    };
+    request.extensions_mut().insert(approved(user.clone())?);
+    Ok(request)
+}
+
+/// Rejects users that an admin did not approve yet.
+pub(crate) fn approved(user: database::models::User) -> Result<database::models::User> {
+    if user.is_approved {
+        Ok(user)
     } else {
-        tracing::debug!("Request required authentication");
-        Err(api::ApiError::NotAuthorized.into())
+        tracing::debug!("Request with pending user");
+        Err(api::ApiError::AuthorizationPending.into())
     }
 }
 
-pub async fn auth_logout(cookie_jar: CookieJar) -> Result<impl IntoResponse> {
+pub async fn auth_logout(
+    Extension(session_state): Extension<SessionState>,
+    State(pool): State<PgPool>,
+    State(server_config): State<ServerConfig>,
+    cookie_jar: CookieJar,
+) -> Result<impl IntoResponse> {
+    // Deleting the session invalidates the token, even if the cookie is kept
+    if let SessionState::Authenticated { user_session, .. }
+    | SessionState::Unauthenticated { user_session } = session_state
+    {
+        let mut conn = pool.get().await.with_internal_server_error()?;
+        database::models::UserSession::delete(&mut conn, user_session.user_session_id)
+            .await
+            .with_internal_server_error()?;
+    }
+
     let cookie_jar = cookie_jar.remove(
-        Cookie::build(USER_COOKIE_NAME)
+        Cookie::build(user_cookie_name(&server_config))
             .removal()
             .http_only(true)
-            .secure(true)
+            .secure(server_config.secure_cookies())
             .path("/")
             .same_site(axum_extra::extract::cookie::SameSite::Strict),
     );
@@ -121,51 +319,47 @@ pub async fn auth_logout(cookie_jar: CookieJar) -> Result<impl IntoResponse> {
     Ok((cookie_jar, axum::Json(())))
 }
 
+pub async fn list_auth_providers(
+    State(auth_providers): State<AuthProviders>,
+) -> axum::Json<Vec<api::AuthProvider>> {
+    axum::Json(auth_providers.list())
+}
+
 pub async fn auth_url(
-    State(jwt_encoding_key): State<jsonwebtoken::EncodingKey>,
+    State(jwt_keys): State<JwtKeys>,
     State(pool): State<PgPool>,
-    State(oidc_client): State<OidcClient>,
+    State(auth_providers): State<AuthProviders>,
+    State(server_config): State<ServerConfig>,
+    headers: HeaderMap,
     cookie_jar: CookieJar,
+    axum::Json(request): axum::Json<api::AuthUrlRequest>,
 ) -> Result<(CookieJar, axum::Json<api::AuthUrlResponse>)> {
     let mut conn = pool.get().await.with_internal_server_error()?;
 
-    let auth_url = oidc_client.auth_url().await?;
+    let Some(auth_provider) = auth_providers.get(&request.provider) else {
+        return Err(api::ApiError::NotFound.into());
+    };
+    let auth_url = auth_provider.auth_url().await?;
 
     let session = database::models::UserSession::create(
         &mut conn,
+        &auth_provider.id,
         &auth_url.issuer_url,
         &auth_url.csrf_token,
         &auth_url.nonce,
         &auth_url.pkce_code_verifier,
+        headers
+            .get(USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok()),
     )
     .await
     .with_internal_server_error()?;
 
-    let expiration = time::OffsetDateTime::now_utc().saturating_add(time::Duration::days(30));
-
-    let token = match jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &Claims {
-            exp: usize::try_from(expiration.unix_timestamp()).unwrap(),
-            sub: session.user_session_id,
-        },
-        &jwt_encoding_key,
-    ) {
-        Ok(token) => token,
-        Err(e) => {
-            tracing::error!("Unable to encode jwt token: {e:?}");
-            return Err(api::ApiError::InternalServerError.into());
-        }
-    };
-
-    let cookie_jar = cookie_jar.add(
-        Cookie::build((USER_COOKIE_NAME, token))
-            .http_only(true)
-            .secure(true)
-            .path("/")
-            .same_site(axum_extra::extract::cookie::SameSite::Strict)
-            .expires(expiration),
-    );
+    let cookie_jar = cookie_jar.add(session_cookie(
+        &server_config,
+        &jwt_keys,
+        session.user_session_id,
+    )?);
 
     Ok((
         cookie_jar,
@@ -178,14 +372,20 @@ pub async fn auth_url(
 pub async fn auth_verify(
     Extension(session_state): Extension<SessionState>,
     State(pool): State<PgPool>,
-    State(oidc_client): State<OidcClient>,
+    State(auth_providers): State<AuthProviders>,
     axum::Json(request): axum::Json<api::AuthVerificationRequest>,
 ) -> Result<axum::Json<bool>> {
     let mut conn = pool.get().await.with_internal_server_error()?;
-    let user_session = match session_state {
-        SessionState::None => return Err(api::ApiError::NotAuthorized.into()),
+    // A logged in session with login state is linking another identity
+    let (user_session, linking_user) = match session_state {
+        SessionState::None | SessionState::ApiToken { .. } => {
+            return Err(api::ApiError::NotAuthorized.into())
+        }
+        SessionState::Authenticated { user_session, user } if user_session.csrf_token.is_some() => {
+            (user_session, Some(user))
+        }
         SessionState::Authenticated { .. } => return Ok(axum::Json(true)),
-        SessionState::Unauthenticated { user_session } => user_session,
+        SessionState::Unauthenticated { user_session } => (user_session, None),
     };
 
     macro_rules! get {
@@ -195,10 +395,12 @@ pub async fn auth_verify(
             };
         };
     }
+    get!(auth_provider_id);
     get!(oidc_issuer_url);
     get!(csrf_token);
     get!(nonce);
     get!(pkce_code_verifier);
+    get!(login_started_at);
 
     if request.state != csrf_token {
         tracing::error!(
@@ -209,6 +411,31 @@ pub async fn auth_verify(
         return Err(api::ApiError::NotAuthorized.into());
     }
 
+    if login_started_at + LOGIN_ATTEMPT_TIMEOUT < time::OffsetDateTime::now_utc() {
+        tracing::warn!("Login attempt expired");
+        return Err(api::ApiError::NotAuthorized.into());
+    }
+
+    // The state is single use. Whether the login succeeds or not, the callback
+    // can't be replayed and a failed login has to be started again.
+    if !database::models::UserSession::consume_login_state(
+        &mut conn,
+        user_session.user_session_id,
+        &csrf_token,
+    )
+    .await
+    .with_internal_server_error()?
+    {
+        tracing::warn!("Replayed login callback");
+        return Err(api::ApiError::NotAuthorized.into());
+    }
+
+    // The provider may have been removed from the config since the login was started
+    let Some(auth_provider) = auth_providers.get(&auth_provider_id) else {
+        tracing::error!("Login with unknown auth provider {auth_provider_id}");
+        return Err(api::ApiError::NotAuthorized.into());
+    };
+
     let oidc_issuer_url = IssuerUrl::new(oidc_issuer_url.clone()).map_err(|e| {
         tracing::error!("Bad issuer url {oidc_issuer_url}: {e:?}",);
         api::ApiError::InternalServerError
@@ -216,7 +443,7 @@ pub async fn auth_verify(
     let nonce = Nonce::new(nonce);
     let pkce_code_verifier = PkceCodeVerifier::new(pkce_code_verifier);
 
-    let claims = oidc_client
+    let claims = auth_provider
         .auth_verify(
             AuthorizationCode::new(request.code),
             pkce_code_verifier,
@@ -225,25 +452,50 @@ pub async fn auth_verify(
         )
         .await?;
 
-    let (user, _oidc_mapping) = match database::models::User::get_by_oidc(
-        &mut conn,
-        &claims.oidc_issuer_url,
-        &claims.oidc_id,
-    )
-    .await
-    .with_internal_server_error()?
-    {
-        Some(data) => data,
-        None => database::models::User::create(
-            &mut conn,
-            &format!("Anonymous{:04}", rand::random::<u64>() % 1000),
-            &claims.email,
-            claims.picture_url.as_ref().map(|s| s.as_str()),
-            &claims.oidc_issuer_url,
-            &claims.oidc_id,
-        )
-        .await
-        .with_internal_server_error()?,
+    let existing =
+        database::models::User::get_by_oidc(&mut conn, &claims.oidc_issuer_url, &claims.oidc_id)
+            .await
+            .with_internal_server_error()?;
+    let user = match (linking_user, existing) {
+        (Some(user), Some((owner, _))) if owner.user_id != user.user_id => {
+            database::models::UserSession::update_after_completed_login(
+                &mut conn,
+                user_session.user_session_id,
+                user.user_id,
+            )
+            .await
+            .with_internal_server_error()?;
+            return Err(api::ApiError::Conflict(
+                "This identity is already linked to another account".to_string(),
+            )
+            .into());
+        }
+        (Some(user), Some(_)) => user,
+        (Some(user), None) => {
+            database::models::OidcMapping::create(
+                &mut conn,
+                user.user_id,
+                &claims.oidc_issuer_url,
+                &claims.oidc_id,
+            )
+            .await
+            .with_internal_server_error()?;
+            user
+        }
+        (None, Some((user, _oidc_mapping))) => user,
+        (None, None) => {
+            database::models::User::create(
+                &mut conn,
+                &format!("Anonymous{:04}", rand::random::<u64>() % 1000),
+                &claims.email,
+                claims.picture_url.as_ref().map(|s| s.as_str()),
+                &claims.oidc_issuer_url,
+                &claims.oidc_id,
+            )
+            .await
+            .with_internal_server_error()?
+            .0
+        }
     };
 
     database::models::UserSession::update_after_completed_login(
@@ -256,69 +508,3 @@ pub async fn auth_verify(
 
     Ok(axum::Json(true))
 }
-
-/*
-pub async fn validate_auth(
-    State(jwt_decoding_key): State<jsonwebtoken::DecodingKey>,
-    State(pool): State<PgPool>,
-    headers: HeaderMap,
-    mut request: Request,
-) -> Result<Request, ListenError> {
-    let mut conn = pool.get().await.with_internal_server_error()?;
-
-    tracing::info!("Running auth validator");
-    let Some(auth_context) = validate_session(&mut conn, &jwt_decoding_key, &headers).await else {
-        return Err(ApiError::NotAuthorized.into());
-    };
-
-    tracing::info!("Inserting extension with valute {auth_context:?}");
-    request.extensions_mut().insert(auth_context);
-
-    Ok(request)
-}
-
-async fn validate_session(
-    conn: &mut AsyncPgConnection,
-    jwt_decoding_key: &jsonwebtoken::DecodingKey,
-    headers: &HeaderMap,
-) -> Option<AuthContext> {
-    return Some(AuthContext {});
-    let token = get_bearer_token(&headers)?;
-
-    let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS256);
-    let claims = match jsonwebtoken::decode::<Claims>(&token, &jwt_decoding_key, &validation) {
-        Ok(claims) => claims,
-        Err(err) => {
-            tracing::error!("Unable to validate jwt: {err:?}");
-            return None;
-        }
-    };
-
-    match database::models::User::get_by_user_session_id(conn, claims.claims.sub).await {
-        Ok(Some((user, user_session))) => Some(AuthContext {
-            // user: user.into(),
-            // user_session: user_session.into(),
-        }),
-        Ok(None) => {
-            tracing::error!("Could not find user session with id {}", claims.claims.sub);
-            None
-        }
-        Err(e) => {
-            tracing::error!("Database error while trying to get user session {e:?}");
-            None
-        }
-    }
-}
-
-fn get_bearer_token(headers: &HeaderMap) -> Option<&str> {
-    let authorization = headers.get(AUTHORIZATION)?.to_str().ok()?;
-
-    let (left, right) = authorization.split_once(' ')?;
-
-    if left != "Bearer" {
-        return None;
-    }
-
-    Some(right)
-}
- */
@@ -0,0 +1,160 @@
+use api::{ApiError, CrowdHistoryEntryId, CrowdId, CrowdQueueId, UserId};
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+
+use super::state::CrowdSession;
+use crate::{
+    error::{ListenErrorExt, Result},
+    PgPool,
+};
+
+/// Records the entries a crowd plays in the database. Failures are logged and
+/// never end the crowd.
+pub struct CrowdHistoryRecorder {
+    pool: PgPool,
+    crowd_id: CrowdId,
+    /// The playing queue entry and its history row.
+    current: Option<(CrowdQueueId, CrowdHistoryEntryId)>,
+}
+
+impl CrowdHistoryRecorder {
+    pub async fn start(
+        pool: PgPool,
+        crowd_id: CrowdId,
+        name: &str,
+        owner_id: Option<UserId>,
+        private: bool,
+    ) -> Self {
+        let recorder = Self {
+            pool,
+            crowd_id,
+            current: None,
+        };
+        if let Err(e) = recorder.create_crowd(name, owner_id, private).await {
+            tracing::error!("Could not record crowd: {e:?}");
+        }
+        recorder
+    }
+
+    /// Records that the scheduled start was reached.
+    pub async fn started(&self) {
+        let result = async {
+            let mut conn = self.pool.get().await?;
+            database::models::Crowd::update_started(&mut conn, self.crowd_id).await?;
+            anyhow::Ok(())
+        };
+        if let Err(e) = result.await {
+            tracing::error!("Could not record start of crowd: {e:?}");
+        }
+    }
+
+    /// Records a change of the playing entry. `skipped` tells whether the
+    /// previous entry was cut short. Nothing plays before the scheduled start.
+    pub async fn record(&mut self, session: &CrowdSession, skipped: bool) {
+        if session.is_scheduled()
+            || self.current.map(|(entry, _)| entry) == session.state().currently_playing
+        {
+            return;
+        }
+        self.finish(skipped).await;
+        self.begin(session).await;
+    }
+
+    /// Records that the playing entry finished and started over.
+    pub async fn repeat(&mut self, session: &CrowdSession) {
+        self.finish(false).await;
+        self.begin(session).await;
+    }
+
+    /// Marks the crowd as ended. An entry that is still playing is left without
+    /// an end time.
+    pub async fn end(self) {
+        let result = async {
+            let mut conn = self.pool.get().await?;
+            database::models::Crowd::update_ended(&mut conn, self.crowd_id).await?;
+            anyhow::Ok(())
+        };
+        if let Err(e) = result.await {
+            tracing::error!("Could not record end of crowd: {e:?}");
+        }
+    }
+
+    async fn create_crowd(
+        &self,
+        name: &str,
+        owner_id: Option<UserId>,
+        private: bool,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+        database::models::Crowd::create(&mut conn, self.crowd_id, name, owner_id, private).await?;
+        Ok(())
+    }
+
+    async fn begin(&mut self, session: &CrowdSession) {
+        let state = session.state();
+        let Some(entry_id) = state.currently_playing else {
+            return;
+        };
+        let Some(entry) = state.queue.get(&entry_id) else {
+            return;
+        };
+        let added_by = session.queued_by(entry);
+        let result = async {
+            let mut conn = self.pool.get().await?;
+            let history_entry = database::models::CrowdHistoryEntry::create(
+                &mut conn,
+                self.crowd_id,
+                &entry.video,
+                added_by.and_then(|participant| participant.user_id),
+                added_by.map(|participant| participant.handle.as_str()),
+            )
+            .await?;
+            anyhow::Ok(history_entry.crowd_history_id)
+        };
+        match result.await {
+            Ok(history_id) => self.current = Some((entry_id, history_id)),
+            Err(e) => tracing::error!("Could not record played entry: {e:?}"),
+        }
+    }
+
+    async fn finish(&mut self, skipped: bool) {
+        let Some((_, history_id)) = self.current.take() else {
+            return;
+        };
+        let result = async {
+            let mut conn = self.pool.get().await?;
+            database::models::CrowdHistoryEntry::update_ended(&mut conn, history_id, skipped)
+                .await?;
+            anyhow::Ok(())
+        };
+        if let Err(e) = result.await {
+            tracing::error!("Could not record end of entry: {e:?}");
+        }
+    }
+}
+
+/// History of a crowd. Private crowds are only found for their owner.
+pub async fn get_history(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<database::models::User>,
+    Path(crowd_id): Path<CrowdId>,
+) -> Result<Json<api::CrowdHistory>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    let Some(crowd) = database::models::Crowd::get_by_id(&mut conn, crowd_id)
+        .await
+        .with_internal_server_error()?
+        .filter(|crowd| crowd.is_visible_to(user.user_id))
+    else {
+        return Err(ApiError::NotFound.into());
+    };
+    let entries = database::models::CrowdHistoryEntry::list_by_crowd_id(&mut conn, crowd_id)
+        .await
+        .with_internal_server_error()?;
+
+    Ok(Json(api::CrowdHistory {
+        crowd: crowd.into(),
+        entries: entries.into_iter().map(Into::into).collect(),
+    }))
+}
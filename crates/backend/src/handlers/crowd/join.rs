@@ -0,0 +1,99 @@
+use api::{ApiError, CrowdId};
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use qrcode::{render::svg, QrCode};
+use rand::seq::IndexedRandom;
+
+use crate::{
+    error::{ListenErrorExt, Result},
+    server_state::{CrowdMap, JoinCode, JoinCodeMap, ServerConfig},
+};
+
+/// Characters used for join codes. Easily confused characters (0/O, 1/I) are left out.
+const JOIN_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const JOIN_CODE_LENGTH: usize = 6;
+
+fn new_join_code() -> String {
+    let mut rng = rand::rng();
+    (0..JOIN_CODE_LENGTH)
+        .map(|_| *JOIN_CODE_ALPHABET.choose(&mut rng).unwrap() as char)
+        .collect()
+}
+
+/// Picks an unused join code and maps it to `crowd_id`.
+pub fn reserve_join_code(
+    join_codes: &JoinCodeMap,
+    crowd_id: CrowdId,
+    expires: Option<time::UtcDateTime>,
+) -> String {
+    loop {
+        let join_code = new_join_code();
+        let dashmap::Entry::Vacant(entry) = join_codes.entry(join_code.clone()) else {
+            continue;
+        };
+        entry.insert(JoinCode { crowd_id, expires });
+        return join_code;
+    }
+}
+
+fn normalize_join_code(code: &str) -> String {
+    code.trim().to_ascii_uppercase()
+}
+
+pub async fn join_info(
+    State(crowd_map): State<CrowdMap>,
+    State(join_codes): State<JoinCodeMap>,
+    Path(code): Path<String>,
+) -> Result<Json<api::CrowdJoinInfo>> {
+    let code = normalize_join_code(&code);
+    let crowd_id = {
+        let Some(join_code) = join_codes.get(&code) else {
+            return Err(ApiError::NotFound.into());
+        };
+        if join_code.is_expired() {
+            tracing::debug!("Tried to use expired join code {code}");
+            return Err(ApiError::NotFound.into());
+        }
+        join_code.crowd_id
+    };
+
+    let Some(crowd_state) = crowd_map.get(&crowd_id) else {
+        return Err(ApiError::NotFound.into());
+    };
+
+    Ok(Json(api::CrowdJoinInfo {
+        crowd_id,
+        name: crowd_state.name.clone(),
+        requires_password: crowd_state.password.is_some(),
+    }))
+}
+
+pub async fn join_qr(
+    State(join_codes): State<JoinCodeMap>,
+    State(server_config): State<ServerConfig>,
+    Path(code): Path<String>,
+) -> Result<impl IntoResponse> {
+    let code = normalize_join_code(&code);
+    {
+        let Some(join_code) = join_codes.get(&code) else {
+            return Err(ApiError::NotFound.into());
+        };
+        if join_code.is_expired() {
+            tracing::debug!("Tried to get QR code for expired join code {code}");
+            return Err(ApiError::NotFound.into());
+        }
+    }
+
+    let join_url = server_config.url(&format!("join/{code}"));
+    let image = QrCode::new(join_url.as_str().as_bytes())
+        .with_internal_server_error()?
+        .render::<svg::Color>()
+        .min_dimensions(256, 256)
+        .build();
+
+    Ok(([(header::CONTENT_TYPE, "image/svg+xml")], image))
+}
@@ -0,0 +1,141 @@
+use std::{collections::HashMap, convert::Infallible};
+
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    Extension, Json,
+};
+use futures::{stream, Stream};
+
+use crate::{
+    error::{ListenErrorExt, Result},
+    handlers::auth::SessionState,
+    server_state::{CrowdListChanges, CrowdMap},
+    PgPool,
+};
+
+pub async fn list(
+    State(crowd_map): State<CrowdMap>,
+    State(pool): State<PgPool>,
+    Extension(session_state): Extension<SessionState>,
+) -> Result<Json<Vec<api::CrowdListEntry>>> {
+    let entries = list_entries(&crowd_map, &pool, viewer(&session_state))
+        .await
+        .with_internal_server_error()?;
+    Ok(Json(entries))
+}
+
+/// Streams the crowd list as server-sent events, sending a new list whenever
+/// it changes.
+pub async fn list_stream(
+    State(crowd_map): State<CrowdMap>,
+    State(pool): State<PgPool>,
+    State(crowd_list_changes): State<CrowdListChanges>,
+    Extension(session_state): Extension<SessionState>,
+) -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+    let viewer = viewer(&session_state);
+    let mut changes = crowd_list_changes.subscribe();
+    changes.mark_changed();
+
+    let events = stream::unfold(
+        (changes, None::<Vec<api::CrowdListEntry>>),
+        move |(mut changes, mut last_sent)| {
+            let crowd_map = crowd_map.clone();
+            let pool = pool.clone();
+            async move {
+                loop {
+                    changes.changed().await.ok()?;
+                    let entries = match list_entries(&crowd_map, &pool, viewer).await {
+                        Ok(entries) => entries,
+                        Err(e) => {
+                            tracing::error!("Could not list crowds: {e:?}");
+                            continue;
+                        }
+                    };
+                    if last_sent.as_ref() == Some(&entries) {
+                        continue;
+                    }
+                    let event = match Event::default().json_data(&entries) {
+                        Ok(event) => event,
+                        Err(e) => {
+                            tracing::error!("Could not serialize crowd list: {e:?}");
+                            continue;
+                        }
+                    };
+                    last_sent = Some(entries);
+                    return Some((Ok(event), (changes, last_sent)));
+                }
+            }
+        },
+    );
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+fn viewer(session_state: &SessionState) -> Option<api::UserId> {
+    session_state
+        .user_with_scope(api::ApiTokenScope::CrowdControl)
+        .map(|user| user.user_id)
+}
+
+/// Public crowds and the private crowds owned by `viewer`, oldest first.
+async fn list_entries(
+    crowd_map: &CrowdMap,
+    pool: &PgPool,
+    viewer: Option<api::UserId>,
+) -> anyhow::Result<Vec<api::CrowdListEntry>> {
+    let mut entries = Vec::new();
+    let mut video_ids = Vec::new();
+    for crowd in crowd_map.iter() {
+        if crowd.private && (viewer.is_none() || crowd.owner_id != viewer) {
+            continue;
+        }
+        let snapshot = crowd.snapshot.borrow();
+        let current_video = snapshot
+            .currently_playing
+            .and_then(|entry| snapshot.queue.get(&entry))
+            .map(|entry| entry.video.clone());
+        if let Some(api::CrowdVideo::Local(video_id)) = current_video {
+            video_ids.push(video_id);
+        }
+        entries.push((
+            current_video,
+            api::CrowdListEntry {
+                started_time: crowd.started,
+                crowd_id: crowd.crowd_id,
+                name: crowd.name.clone(),
+                participant_count: snapshot.participants.len(),
+                join_code: crowd.join_code.clone(),
+                requires_password: crowd.password.is_some(),
+                private: crowd.private,
+                owner: crowd.owner.clone(),
+                current_video_title: None,
+                queue_length: snapshot.queue.len(),
+                scheduled_start: snapshot.scheduled_start,
+            },
+        ));
+    }
+
+    let titles: HashMap<_, _> = if video_ids.is_empty() {
+        HashMap::new()
+    } else {
+        let mut conn = pool.get().await?;
+        database::models::Video::get_titles(&mut conn, &video_ids)
+            .await?
+            .into_iter()
+            .collect()
+    };
+
+    let mut entries: Vec<_> = entries
+        .into_iter()
+        .map(|(current_video, mut entry)| {
+            entry.current_video_title = current_video.and_then(|video| match video {
+                api::CrowdVideo::Local(video_id) => titles.get(&video_id).cloned(),
+                api::CrowdVideo::Youtube(youtube_id) => Some(format!("YouTube video {youtube_id}")),
+            });
+            entry
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.started_time);
+    Ok(entries)
+}
@@ -1,11 +1,18 @@
-use axum::{extract::State, routing::get, Json, Router};
+use std::sync::Arc;
+
+use axum::{extract::State, middleware::map_request, routing::get, Json, Router};
 use participant::ws_handler_participant;
 use player::ws_handler_player;
 
-use crate::server_state::{CrowdMap, ServerState};
+use crate::server_state::{CrowdMetrics, ServerState};
 
+mod history;
+pub mod join;
+mod list;
 pub mod participant;
 pub mod player;
+mod protocol;
+mod state;
 
 enum ShouldContinue {
     Stop,
@@ -16,19 +23,18 @@ pub fn routes() -> Router<ServerState> {
     Router::new()
         .route("/player", get(ws_handler_player))
         .route("/participant", get(ws_handler_participant))
-        .route("/list", get(list))
+        .route("/list", get(list::list))
+        .route("/list/stream", get(list::list_stream))
+        .route(
+            "/history/:crowd_id",
+            get(history::get_history)
+                .route_layer(map_request(crate::handlers::auth::auth_required_layer)),
+        )
+        .route("/join/:code", get(join::join_info))
+        .route("/join/:code/qr", get(join::join_qr))
 }
 
-async fn list(State(crowd_map): State<CrowdMap>) -> Json<Vec<api::CrowdListEntry>> {
-    Json(
-        crowd_map
-            .iter()
-            .map(|entry| api::CrowdListEntry {
-                started_time: entry.started,
-                crowd_id: entry.crowd_id,
-                name: entry.name.clone(),
-                participant_count: entry.command_sender.strong_count().saturating_sub(1),
-            })
-            .collect(),
-    )
+/// Served under the admin routes, the numbers are not meant for participants.
+pub async fn metrics(State(crowd_metrics): State<Arc<CrowdMetrics>>) -> Json<api::CrowdMetrics> {
+    Json(crowd_metrics.snapshot())
 }
@@ -1,27 +1,38 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
 
-use crate::server_state::{CrowdMap, CrowdState};
+use crate::{
+    handlers::auth::{approved, SessionState},
+    server_state::{CrowdCommand, CrowdMap, CrowdMetrics, CrowdState},
+};
 
-use super::ShouldContinue;
+use super::{
+    protocol::{CrowdSocket, Frame},
+    ShouldContinue,
+};
 use anyhow::{bail, Context};
-use api::CrowdId;
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        ws::{WebSocket, WebSocketUpgrade},
         ConnectInfo, State,
     },
     response::IntoResponse,
+    Extension,
 };
 use axum_extra::TypedHeader;
 use tracing::{info, Instrument};
 
 struct ParticipantConnectionState {
-    command_sender: tokio::sync::mpsc::Sender<(time::UtcDateTime, api::CrowdParticipantCommand)>,
+    participant_id: api::CrowdParticipantId,
+    command_sender: tokio::sync::mpsc::Sender<CrowdCommand>,
     update_receiver: tokio::sync::broadcast::Receiver<(time::UtcDateTime, api::CrowdPlayerUpdate)>,
-    websocket: WebSocket,
+    snapshot: tokio::sync::watch::Receiver<api::CrowdState>,
+    metrics: Arc<CrowdMetrics>,
+    socket: CrowdSocket,
     interested_after: InterestedAfterTimes,
 }
 
+const CHAT_MESSAGE_MAX_LENGTH: usize = 500;
+
 // When are we next interested in updates to these state elements?
 struct InterestedAfterTimes {
     playback_position: time::UtcDateTime,
@@ -45,14 +56,14 @@ impl ParticipantConnectionState {
     async fn handle(mut self) {
         loop {
             let should_continue = tokio::select! {
-                msg = self.websocket.recv() => self.handle_websocket_message(msg).await,
+                msg = self.socket.recv() => self.handle_websocket_message(msg).await,
                 update = self.update_receiver.recv() =>self.handle_player_update(update).await,
             };
             let Ok(ShouldContinue::Continue) = should_continue else {
                 break;
             };
         }
-        let _ = self.websocket.close().await;
+        self.socket.close().await;
     }
 
     #[tracing::instrument(skip(self), err(Debug))]
@@ -63,7 +74,19 @@ impl ParticipantConnectionState {
             tokio::sync::broadcast::error::RecvError,
         >,
     ) -> anyhow::Result<ShouldContinue> {
-        let (time, update) = update.context("Broadcast died?")?;
+        let (time, update) = match update {
+            Ok(update) => update,
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("Participant lagged behind by {skipped} updates, resyncing");
+                self.metrics.record_lag(skipped);
+                self.resync().await?;
+                return Ok(ShouldContinue::Continue);
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                tracing::info!("Crowd closed, no more updates");
+                return Ok(ShouldContinue::Stop);
+            }
+        };
         let is_interested = match &update {
             api::CrowdPlayerUpdate::Ping => false,
             api::CrowdPlayerUpdate::PlaybackPosition(_) => {
@@ -72,65 +95,119 @@ impl ParticipantConnectionState {
             api::CrowdPlayerUpdate::IsPaused(_) => time >= self.interested_after.is_paused,
             api::CrowdPlayerUpdate::Speed(_) => time >= self.interested_after.speed,
             api::CrowdPlayerUpdate::Queue { .. } => time >= self.interested_after.queue,
+            api::CrowdPlayerUpdate::Ended(_)
+            | api::CrowdPlayerUpdate::Identity(_)
+            | api::CrowdPlayerUpdate::Moderate(_) => false,
+            api::CrowdPlayerUpdate::Kicked(participant_id) => {
+                if *participant_id != self.participant_id {
+                    return Ok(ShouldContinue::Continue);
+                }
+                tracing::info!("Kicked from the crowd");
+                self.socket.send(&update).await?;
+                return Ok(ShouldContinue::Stop);
+            }
+            api::CrowdPlayerUpdate::Duration(_)
+            | api::CrowdPlayerUpdate::Settings(_)
+            | api::CrowdPlayerUpdate::SkipVotes { .. }
+            | api::CrowdPlayerUpdate::State(_)
+            | api::CrowdPlayerUpdate::Chat(_)
+            | api::CrowdPlayerUpdate::Participants(_)
+            | api::CrowdPlayerUpdate::Audit(_)
+            | api::CrowdPlayerUpdate::Started(_)
+            | api::CrowdPlayerUpdate::GuestsBlocked(_)
+            | api::CrowdPlayerUpdate::Error(_) => true,
         };
 
-        let update = serde_json::to_string(&update)?;
-
         if is_interested {
-            self.websocket
-                .send(axum::extract::ws::Message::Text(update))
-                .await?;
+            self.socket.send(&update).await?;
         }
 
         Ok(ShouldContinue::Continue)
     }
 
+    /// Replaces whatever the participant missed with the latest full state.
+    async fn resync(&mut self) -> anyhow::Result<()> {
+        let state = self.snapshot.borrow_and_update().clone();
+        self.socket
+            .send(&api::CrowdPlayerUpdate::State(state))
+            .await?;
+        self.metrics.record_resync();
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self), err(Debug))]
     async fn handle_websocket_message(
         &mut self,
-        msg: Option<Result<axum::extract::ws::Message, axum::Error>>,
+        msg: anyhow::Result<Frame<api::CrowdParticipantCommand>>,
     ) -> anyhow::Result<ShouldContinue> {
-        let Some(msg) = msg else {
-            tracing::info!("No more websocket messages");
-            return Ok(ShouldContinue::Stop);
+        let mut msg = match msg? {
+            Frame::Message(msg) => msg,
+            Frame::Control => return Ok(ShouldContinue::Continue),
+            Frame::Invalid(reason) => {
+                tracing::warn!("Invalid message from participant: {reason}");
+                self.socket
+                    .send(&api::CrowdPlayerUpdate::Error(reason))
+                    .await?;
+                return Ok(ShouldContinue::Continue);
+            }
+            Frame::Closed => {
+                tracing::info!("No more websocket messages");
+                return Ok(ShouldContinue::Stop);
+            }
         };
-        let msg = msg.context("Error while receiving websocket message")?;
-        match msg {
-            axum::extract::ws::Message::Text(msg) => {
-                let msg: api::CrowdParticipantCommand = serde_json::from_str(&msg)?;
-                let now = time::UtcDateTime::now();
-                match &msg {
-                    api::CrowdParticipantCommand::Ping => {
-                        let command = serde_json::to_string(&api::CrowdPlayerUpdate::Ping)?;
-                        self.websocket.send(Message::Text(command)).await?;
-                    }
-                    api::CrowdParticipantCommand::SetPlaybackPosition(_) => {
-                        self.interested_after.playback_position = now
-                    }
-                    api::CrowdParticipantCommand::SetIsPaused(_) => {
-                        self.interested_after.is_paused = now
-                    }
-                    api::CrowdParticipantCommand::SetSpeed(_) => self.interested_after.speed = now,
-                    api::CrowdParticipantCommand::GoTo(_)
-                    | api::CrowdParticipantCommand::AddToQueue(_)
-                    | api::CrowdParticipantCommand::MoveInQueue { .. }
-                    | api::CrowdParticipantCommand::DeleteFromQueue(_) => {
-                        self.interested_after.queue = now
-                    }
+        let now = time::UtcDateTime::now();
+        match &mut msg {
+            api::CrowdParticipantCommand::Ping => {
+                self.socket.send(&api::CrowdPlayerUpdate::Ping).await?;
+            }
+            api::CrowdParticipantCommand::SetPlaybackPosition(_) => {
+                self.interested_after.playback_position = now
+            }
+            api::CrowdParticipantCommand::SetIsPaused(_) => self.interested_after.is_paused = now,
+            api::CrowdParticipantCommand::SetSpeed(_) => self.interested_after.speed = now,
+            api::CrowdParticipantCommand::GoTo(_)
+            | api::CrowdParticipantCommand::AddToQueue(_)
+            | api::CrowdParticipantCommand::MoveInQueue { .. }
+            | api::CrowdParticipantCommand::DeleteFromQueue(_)
+            | api::CrowdParticipantCommand::Vote { .. }
+            | api::CrowdParticipantCommand::Undo(_) => self.interested_after.queue = now,
+            api::CrowdParticipantCommand::AddYoutubeToQueue(video) => {
+                let Some(youtube_id) = youtube_id(video) else {
+                    self.socket
+                        .send(&api::CrowdPlayerUpdate::Error(
+                            "Not a YouTube link".to_string(),
+                        ))
+                        .await?;
+                    return Ok(ShouldContinue::Continue);
+                };
+                *video = youtube_id;
+                self.interested_after.queue = now;
+            }
+            api::CrowdParticipantCommand::VoteSkip
+            | api::CrowdParticipantCommand::SetIdle(_)
+            | api::CrowdParticipantCommand::Kick(_)
+            | api::CrowdParticipantCommand::BlockGuests(_)
+            | api::CrowdParticipantCommand::Mute { .. }
+            | api::CrowdParticipantCommand::React(_) => {}
+            api::CrowdParticipantCommand::Chat(text) => {
+                *text = text.trim().chars().take(CHAT_MESSAGE_MAX_LENGTH).collect();
+                if text.is_empty() {
+                    return Ok(ShouldContinue::Continue);
                 }
-                self.command_sender
-                    .send((now, msg))
-                    .await
-                    .context("Command sender closed, not more connection?")?;
-                Ok(ShouldContinue::Continue)
             }
-            msg => anyhow::bail!("Unexpected websocket message: {msg:?}"),
         }
+        self.command_sender
+            .send(CrowdCommand::Command(now, self.participant_id, msg))
+            .await
+            .context("Command sender closed, not more connection?")?;
+        Ok(ShouldContinue::Continue)
     }
 }
 
 pub async fn ws_handler_participant(
     State(crowd_map): State<CrowdMap>,
+    State(crowd_metrics): State<Arc<CrowdMetrics>>,
+    Extension(session_state): Extension<SessionState>,
     ws: WebSocketUpgrade,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
@@ -141,58 +218,213 @@ pub async fn ws_handler_participant(
         String::from("Unknown browser")
     };
     info!("`{user_agent}` at {addr} connected to participant websocket.");
+    // Pending users join as guests
+    let user = session_state
+        .user_with_scope(api::ApiTokenScope::CrowdControl)
+        .cloned()
+        .and_then(|user| approved(user).ok());
     ws.on_upgrade(move |socket| async move {
-        let _ = handle_participant_websocket(crowd_map, socket, addr).await;
+        let _ = handle_participant_websocket(crowd_map, crowd_metrics, user, socket, addr).await;
     })
 }
 
-#[tracing::instrument(skip(crowd_map, websocket), err(Debug))]
+#[tracing::instrument(skip(crowd_map, crowd_metrics, user, websocket), err(Debug))]
 async fn handle_participant_websocket(
     crowd_map: CrowdMap,
-    mut websocket: WebSocket,
+    crowd_metrics: Arc<CrowdMetrics>,
+    user: Option<database::models::User>,
+    websocket: WebSocket,
     addr: SocketAddr,
 ) -> anyhow::Result<()> {
-    let Some(msg) = websocket.recv().await else {
-        bail!("Could not receive initial message");
-    };
-
-    let crowd_id = match msg {
-        Ok(Message::Text(crowd_id)) => crowd_id,
-        Ok(msg) => {
-            let _ = websocket.close().await;
-            bail!("Unexpected initial message: {msg:?}");
+    let mut socket = CrowdSocket::accept(websocket).await?;
+    let join_request = socket
+        .recv_initial::<api::CrowdJoinRequest, api::CrowdParticipantCommand>(|command| {
+            matches!(command, api::CrowdParticipantCommand::Ping)
+        })
+        .await;
+    let crowd_state = join_request.and_then(|api::CrowdJoinRequest { crowd_id, password }| {
+        let Some(crowd_state) = crowd_map.get(&crowd_id) else {
+            bail!("Could not find crowd with id {crowd_id}");
+        };
+        if !crowd_state.check_password(password.as_deref()) {
+            bail!("Wrong password for crowd {crowd_id}");
+        }
+        if user.is_none() && crowd_state.snapshot.borrow().guests_blocked {
+            bail!("Guests can not join this crowd right now, please log in");
         }
+        Ok(crowd_state)
+    });
+    let crowd_state = match crowd_state {
+        Ok(crowd_state) => crowd_state,
         Err(e) => {
-            let _ = websocket.close().await;
-            bail!("Could not receive initial message: {e:?}");
+            let _ = socket
+                .send(&api::CrowdPlayerUpdate::Error(format!("{e:#}")))
+                .await;
+            socket.close().await;
+            return Err(e);
         }
     };
-    let crowd_id: CrowdId = crowd_id.trim().parse()?;
-    let Some(crowd_state) = crowd_map.get(&crowd_id) else {
-        bail!("Could not find crown with id {crowd_id}");
-    };
+    let crowd_id = crowd_state.crowd_id;
     let CrowdState {
         name,
+        owner_id,
         command_sender,
         update_receiver,
+        snapshot,
         ..
     } = &*crowd_state;
+    let participant_id = api::CrowdParticipantId::new_random();
+    let participant = match user {
+        Some(user) => api::CrowdParticipant {
+            participant_id,
+            user_id: Some(user.user_id),
+            is_owner: *owner_id == Some(user.user_id),
+            handle: user.handle,
+            profile_picture_url: user.profile_picture_url,
+            joined_at: time::UtcDateTime::now(),
+            idle: false,
+            muted: false,
+        },
+        None => api::CrowdParticipant {
+            participant_id,
+            user_id: None,
+            is_owner: false,
+            handle: format!("Guest {}", &participant_id.to_string()[..4]),
+            profile_picture_url: None,
+            joined_at: time::UtcDateTime::now(),
+            idle: false,
+            muted: false,
+        },
+    };
+    let mut snapshot = snapshot.clone();
+    let initial_state = api::CrowdPlayerUpdate::State(snapshot.borrow_and_update().clone());
 
-    let span = tracing::info_span!("crowd participant", %crowd_id, %name);
+    let span = tracing::info_span!("crowd participant", %crowd_id, %name, %participant_id);
 
     {
         let _span = span.enter();
         tracing::info!("New crowd participant");
     }
 
-    let participant_state = ParticipantConnectionState {
+    let mut participant_state = ParticipantConnectionState {
+        participant_id,
         command_sender: command_sender.clone(),
         update_receiver: update_receiver.resubscribe(),
-        websocket,
+        snapshot,
+        metrics: crowd_metrics,
+        socket,
         interested_after: InterestedAfterTimes::default(),
     };
+    drop(crowd_state);
 
-    participant_state.handle().instrument(span).await;
+    let command_sender = participant_state.command_sender.clone();
+    command_sender
+        .send(CrowdCommand::Join(participant))
+        .await
+        .context("Crowd closed while joining")?;
 
-    Ok(())
+    let result = async {
+        participant_state
+            .socket
+            .send(&api::CrowdPlayerUpdate::Identity(participant_id))
+            .await?;
+        participant_state.socket.send(&initial_state).await
+    }
+    .await;
+    if result.is_ok() {
+        participant_state.handle().instrument(span).await;
+    }
+    let _ = command_sender
+        .send(CrowdCommand::Leave(participant_id))
+        .await;
+    result
+}
+
+/// Extracts the video id from a YouTube link. Bare ids are accepted as well.
+fn youtube_id(input: &str) -> Option<String> {
+    let is_id = |id: &str| {
+        id.len() == 11
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+    let input = input.trim();
+    if is_id(input) {
+        return Some(input.to_string());
+    }
+    let url = url::Url::parse(input)
+        .or_else(|_| url::Url::parse(&format!("https://{input}")))
+        .ok()?;
+    let host = url.host_str()?;
+    let host = ["www.", "m.", "music."]
+        .iter()
+        .find_map(|prefix| host.strip_prefix(prefix))
+        .unwrap_or(host);
+    let mut segments = url.path_segments()?;
+    let id = match host {
+        "youtu.be" => segments.next()?.to_string(),
+        "youtube.com" | "youtube-nocookie.com" => match segments.next()? {
+            "watch" => url
+                .query_pairs()
+                .find(|(key, _)| key == "v")?
+                .1
+                .into_owned(),
+            "shorts" | "embed" | "live" => segments.next()?.to_string(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    is_id(&id).then_some(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::youtube_id;
+
+    #[test]
+    fn youtube_id_accepts_bare_ids() {
+        assert_eq!(youtube_id("dQw4w9WgXcQ").as_deref(), Some("dQw4w9WgXcQ"));
+        assert_eq!(
+            youtube_id("  a-b_c1234XY \n").as_deref(),
+            Some("a-b_c1234XY")
+        );
+    }
+
+    #[test]
+    fn youtube_id_parses_links() {
+        for link in [
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://youtube.com/watch?feature=share&v=dQw4w9WgXcQ&t=42",
+            "https://m.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://music.youtube.com/watch?v=dQw4w9WgXcQ&list=RDdQw4w9WgXcQ",
+            "https://youtu.be/dQw4w9WgXcQ",
+            "https://youtu.be/dQw4w9WgXcQ?si=abcdef",
+            "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+            "https://www.youtube.com/embed/dQw4w9WgXcQ",
+            "https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ",
+            "https://www.youtube.com/live/dQw4w9WgXcQ",
+            "www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "youtu.be/dQw4w9WgXcQ",
+        ] {
+            assert_eq!(youtube_id(link).as_deref(), Some("dQw4w9WgXcQ"), "{link}");
+        }
+    }
+
+    #[test]
+    fn youtube_id_rejects_other_input() {
+        for input in [
+            "",
+            "dQw4w9WgXc",
+            "dQw4w9WgXcQQ",
+            "dQw4w9WgX!Q",
+            "https://example.com/watch?v=dQw4w9WgXcQ",
+            "https://www.youtube.com/watch",
+            "https://www.youtube.com/watch?v=short",
+            "https://www.youtube.com/channel/dQw4w9WgXcQ",
+            "https://youtu.be/",
+            "https://notyoutube.com/watch?v=dQw4w9WgXcQ",
+        ] {
+            assert_eq!(youtube_id(input), None, "{input}");
+        }
+    }
 }
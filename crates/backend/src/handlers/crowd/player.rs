@@ -1,33 +1,50 @@
 use std::net::SocketAddr;
 
-use crate::server_state::CrowdMap;
+use crate::{
+    handlers::auth::{approved, SessionState},
+    server_state::{CrowdCommand, CrowdListChanges, ServerState},
+    PgPool,
+};
 
-use super::ShouldContinue;
+use super::{
+    history::CrowdHistoryRecorder,
+    join::reserve_join_code,
+    protocol::{CrowdSocket, Frame},
+    state::{CrowdSession, Ended},
+    ShouldContinue,
+};
 use anyhow::{bail, Context};
 use api::CrowdId;
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        ws::{WebSocket, WebSocketUpgrade},
         ConnectInfo, State,
     },
     response::IntoResponse,
+    Extension,
 };
 use axum_extra::TypedHeader;
 use tracing::{info, Instrument};
 
 struct PlayerConnectionState {
-    command_receiver:
-        tokio::sync::mpsc::Receiver<(time::UtcDateTime, api::CrowdParticipantCommand)>,
+    command_receiver: tokio::sync::mpsc::Receiver<CrowdCommand>,
     update_publisher: tokio::sync::broadcast::Sender<(time::UtcDateTime, api::CrowdPlayerUpdate)>,
-    websocket: WebSocket,
+    snapshot_publisher: tokio::sync::watch::Sender<api::CrowdState>,
+    crowd_list_changes: CrowdListChanges,
+    session: CrowdSession,
+    history: CrowdHistoryRecorder,
+    pool: PgPool,
+    socket: CrowdSocket,
 }
 
 impl PlayerConnectionState {
     async fn handle(mut self) {
         loop {
+            let scheduled_start = self.session.state().scheduled_start;
             let should_continue = tokio::select! {
-                msg = self.websocket.recv() => self.handle_websocket_message(msg).await,
+                msg = self.socket.recv() => self.handle_websocket_message(msg).await,
                 command = self.command_receiver.recv() =>self.handle_participant_command(command).await,
+                _ = sleep_until(scheduled_start) => self.start().await,
                 _ = tokio::time::sleep(tokio::time::Duration::from_secs(60)) => {
                     tracing::warn!("Player connection closed");
                     Ok(ShouldContinue::Stop)
@@ -36,56 +53,385 @@ impl PlayerConnectionState {
             let Ok(ShouldContinue::Continue) = should_continue else {
                 break;
             };
+            // Natural ends are recorded when they happen, any other change cuts the entry short
+            self.history.record(&self.session, true).await;
+            let state = self.session.state();
+            let previous = self.snapshot_publisher.send_replace(state.clone());
+            if previous.currently_playing != state.currently_playing
+                || previous.queue.len() != state.queue.len()
+                || previous.participants.len() != state.participants.len()
+                || previous.scheduled_start != state.scheduled_start
+            {
+                self.crowd_list_changes.send_replace(());
+            }
+        }
+        self.socket.close().await;
+        self.history.end().await;
+    }
+
+    fn participant_count(&self) -> usize {
+        self.session.participant_count()
+    }
+
+    fn publish(&self, update: api::CrowdPlayerUpdate) -> anyhow::Result<()> {
+        self.update_publisher
+            .send((time::UtcDateTime::now(), update))
+            .context("Crowd closed, no more receivers?")?;
+        Ok(())
+    }
+
+    async fn send_to_player(&mut self, command: api::CrowdPlayerCommand) -> anyhow::Result<()> {
+        self.socket.send(&(time::UtcDateTime::now(), command)).await
+    }
+
+    async fn publish_queue(&mut self) -> anyhow::Result<()> {
+        if self.session.needs_autofill() {
+            if let Err(e) = self.autofill().await {
+                tracing::error!("Could not autofill queue: {e:?}");
+            }
+        }
+        self.send_to_player(self.session.queue_command()).await?;
+        self.publish(self.session.queue_update())?;
+        self.publish_skip_votes()
+    }
+
+    async fn publish_chat(&mut self, message: api::CrowdChatMessage) -> anyhow::Result<()> {
+        self.send_to_player(api::CrowdPlayerCommand::Chat(message.clone()))
+            .await?;
+        self.publish(api::CrowdPlayerUpdate::Chat(message))
+    }
+
+    async fn publish_participants(&mut self) -> anyhow::Result<()> {
+        let participants = self.session.state().participants.clone();
+        self.send_to_player(api::CrowdPlayerCommand::Participants(participants.clone()))
+            .await?;
+        self.publish(api::CrowdPlayerUpdate::Participants(participants))?;
+        // The number of votes needed to skip depends on the participant count
+        self.publish_skip_votes()
+    }
+
+    fn publish_audit(
+        &mut self,
+        participant_id: api::CrowdParticipantId,
+        command: api::CrowdParticipantCommand,
+    ) -> anyhow::Result<()> {
+        let entry = self.session.audit(participant_id, command);
+        self.publish(api::CrowdPlayerUpdate::Audit(entry))
+    }
+
+    async fn autofill(&mut self) -> anyhow::Result<()> {
+        let mut conn = self.pool.get().await?;
+        let candidates = database::models::Video::list_playable_ids(&mut conn).await?;
+        if !self.session.autofill(&candidates) {
+            tracing::debug!("No videos to autofill with");
+        }
+        Ok(())
+    }
+
+    fn publish_skip_votes(&self) -> anyhow::Result<()> {
+        self.publish(api::CrowdPlayerUpdate::SkipVotes {
+            votes: self.session.state().skip_votes,
+            needed: self.session.skip_votes_needed(self.participant_count()),
+        })
+    }
+
+    /// Kicks, mutes or blocks guests on behalf of the owner or the player.
+    async fn moderate(
+        &mut self,
+        participant_id: api::CrowdParticipantId,
+        command: api::CrowdParticipantCommand,
+    ) -> anyhow::Result<()> {
+        if !self.session.is_owner(participant_id) {
+            tracing::warn!("Participant that is not the owner tried to moderate");
+            return Ok(());
+        }
+        match command {
+            api::CrowdParticipantCommand::Kick(target) => {
+                if target == participant_id || self.session.is_owner(target) {
+                    tracing::debug!("Ignoring kick of the owner");
+                    return Ok(());
+                }
+                let guests_blocked = self.session.state().guests_blocked;
+                if self.session.kick(target).is_some() {
+                    tracing::info!("Kicked participant {target}");
+                    self.publish_audit(participant_id, command)?;
+                    self.publish(api::CrowdPlayerUpdate::Kicked(target))?;
+                    self.publish_participants().await?;
+                    if self.session.state().guests_blocked != guests_blocked {
+                        tracing::info!("Blocked guests after kicking a guest");
+                        self.publish_audit(
+                            participant_id,
+                            api::CrowdParticipantCommand::BlockGuests(true),
+                        )?;
+                        self.publish_guests_blocked().await?;
+                    }
+                }
+            }
+            api::CrowdParticipantCommand::Mute { participant, muted } => {
+                if self.session.set_muted(participant, muted) {
+                    self.publish_audit(participant_id, command)?;
+                    self.publish_participants().await?;
+                }
+            }
+            api::CrowdParticipantCommand::BlockGuests(blocked) => {
+                if let Some(removed) = self.session.block_guests(blocked) {
+                    tracing::info!("Set guests blocked to {blocked}, removed {removed:?}");
+                    self.publish_audit(participant_id, command)?;
+                    for guest in removed {
+                        self.publish(api::CrowdPlayerUpdate::Kicked(guest))?;
+                    }
+                    self.publish_participants().await?;
+                    self.publish_guests_blocked().await?;
+                }
+            }
+            command => tracing::warn!("Not a moderation command: {command:?}"),
         }
-        let _ = self.websocket.close().await;
+        Ok(())
+    }
+
+    async fn publish_guests_blocked(&mut self) -> anyhow::Result<()> {
+        let blocked = self.session.state().guests_blocked;
+        self.send_to_player(api::CrowdPlayerCommand::GuestsBlocked(blocked))
+            .await?;
+        self.publish(api::CrowdPlayerUpdate::GuestsBlocked(blocked))
+    }
+
+    /// Starts playback once the scheduled start is reached.
+    #[tracing::instrument(skip(self), err(Debug))]
+    async fn start(&mut self) -> anyhow::Result<ShouldContinue> {
+        let Some(scheduled_start) = self.session.state().scheduled_start else {
+            return Ok(ShouldContinue::Continue);
+        };
+        tracing::info!("Reached the scheduled start");
+        self.session.start();
+        self.history.started().await;
+        self.publish_queue().await?;
+        self.send_to_player(api::CrowdPlayerCommand::Start(scheduled_start))
+            .await?;
+        self.publish(api::CrowdPlayerUpdate::Started(scheduled_start))?;
+        Ok(ShouldContinue::Continue)
     }
 
     #[tracing::instrument(skip(self), err(Debug))]
     async fn handle_participant_command(
         &mut self,
-        command: Option<(time::UtcDateTime, api::CrowdParticipantCommand)>,
+        command: Option<CrowdCommand>,
     ) -> anyhow::Result<ShouldContinue> {
-        let Some(command) = command else {
-            tracing::info!("No more commands");
-            return Ok(ShouldContinue::Stop);
+        let (time, participant_id, command) = match command {
+            Some(CrowdCommand::Command(time, participant_id, command)) => {
+                (time, participant_id, command)
+            }
+            Some(CrowdCommand::Join(participant)) => {
+                let participant_id = participant.participant_id;
+                if self.session.join(participant) {
+                    self.publish_participants().await?;
+                } else {
+                    tracing::info!("Kicked participant or blocked guest tried to join");
+                    self.publish(api::CrowdPlayerUpdate::Kicked(participant_id))?;
+                }
+                return Ok(ShouldContinue::Continue);
+            }
+            Some(CrowdCommand::Leave(participant_id)) => {
+                if self.session.leave(participant_id).is_some() {
+                    self.publish_participants().await?;
+                }
+                return Ok(ShouldContinue::Continue);
+            }
+            None => {
+                tracing::info!("No more commands");
+                return Ok(ShouldContinue::Stop);
+            }
         };
-        let command = serde_json::to_string(&command)?;
-        self.websocket
-            .send(axum::extract::ws::Message::Text(command))
-            .await?;
+        let player_command = match command {
+            api::CrowdParticipantCommand::Ping => Some(api::CrowdPlayerCommand::Ping),
+            api::CrowdParticipantCommand::SetPlaybackPosition(_)
+            | api::CrowdParticipantCommand::SetIsPaused(_)
+            | api::CrowdParticipantCommand::GoTo(_)
+            | api::CrowdParticipantCommand::VoteSkip
+                if self.session.is_scheduled() =>
+            {
+                tracing::debug!("Ignoring playback control before the scheduled start");
+                None
+            }
+            api::CrowdParticipantCommand::SetPlaybackPosition(position) => {
+                self.publish_audit(participant_id, command)?;
+                Some(api::CrowdPlayerCommand::SetPlaybackPosition(position))
+            }
+            api::CrowdParticipantCommand::SetIsPaused(is_paused) => {
+                self.publish_audit(participant_id, command)?;
+                Some(api::CrowdPlayerCommand::SetIsPaused(is_paused))
+            }
+            api::CrowdParticipantCommand::SetSpeed(speed) => {
+                self.publish_audit(participant_id, command)?;
+                Some(api::CrowdPlayerCommand::SetSpeed(speed))
+            }
+            api::CrowdParticipantCommand::VoteSkip => {
+                self.publish_audit(participant_id, command)?;
+                let participant_count = self.participant_count();
+                if self.session.vote_skip(participant_id, participant_count) {
+                    self.publish_queue().await?;
+                } else {
+                    self.publish_skip_votes()?;
+                }
+                None
+            }
+            api::CrowdParticipantCommand::Chat(_) | api::CrowdParticipantCommand::React(_)
+                if self.session.is_muted(participant_id) =>
+            {
+                tracing::debug!("Dropping chat from muted participant");
+                None
+            }
+            api::CrowdParticipantCommand::Chat(text) => {
+                if let Some(message) = self
+                    .session
+                    .chat(participant_id, api::CrowdChatContent::Message(text))
+                {
+                    self.publish_chat(message).await?;
+                }
+                None
+            }
+            api::CrowdParticipantCommand::React(reaction) => {
+                if let Some(message) = self
+                    .session
+                    .chat(participant_id, api::CrowdChatContent::Reaction(reaction))
+                {
+                    self.publish_chat(message).await?;
+                }
+                None
+            }
+            api::CrowdParticipantCommand::SetIdle(idle) => {
+                if self.session.set_idle(participant_id, idle) {
+                    self.publish_participants().await?;
+                }
+                None
+            }
+            api::CrowdParticipantCommand::Kick(_)
+            | api::CrowdParticipantCommand::Mute { .. }
+            | api::CrowdParticipantCommand::BlockGuests(_) => {
+                self.moderate(participant_id, command).await?;
+                None
+            }
+            api::CrowdParticipantCommand::AddYoutubeToQueue(youtube_id) => {
+                // Prefer the library copy once the video is downloaded
+                let command = match downloaded_video(&self.pool, &youtube_id).await {
+                    Ok(Some(video_id)) => api::CrowdParticipantCommand::AddToQueue(video_id),
+                    Ok(None) => api::CrowdParticipantCommand::AddYoutubeToQueue(youtube_id),
+                    Err(e) => {
+                        tracing::error!("Could not look up downloaded video: {e:?}");
+                        api::CrowdParticipantCommand::AddYoutubeToQueue(youtube_id)
+                    }
+                };
+                if let Some(entry) = self.session.apply(participant_id, &command) {
+                    self.publish_queue().await?;
+                    self.publish(api::CrowdPlayerUpdate::Audit(entry))?;
+                }
+                None
+            }
+            command => {
+                if let Some(entry) = self.session.apply(participant_id, &command) {
+                    self.publish_queue().await?;
+                    self.publish(api::CrowdPlayerUpdate::Audit(entry))?;
+                }
+                None
+            }
+        };
+        if let Some(player_command) = player_command {
+            self.socket.send(&(time, player_command)).await?;
+        }
         Ok(ShouldContinue::Continue)
     }
 
     #[tracing::instrument(skip(self), err(Debug))]
     async fn handle_websocket_message(
         &mut self,
-        msg: Option<Result<axum::extract::ws::Message, axum::Error>>,
+        msg: anyhow::Result<Frame<(time::UtcDateTime, api::CrowdPlayerUpdate)>>,
     ) -> anyhow::Result<ShouldContinue> {
-        let Some(msg) = msg else {
-            tracing::info!("No more websocket messages");
-            return Ok(ShouldContinue::Stop);
+        let (time, update) = match msg? {
+            Frame::Message(msg) => msg,
+            Frame::Control => return Ok(ShouldContinue::Continue),
+            Frame::Invalid(reason) => {
+                tracing::warn!("Invalid message from player: {reason}");
+                self.send_to_player(api::CrowdPlayerCommand::Error(reason))
+                    .await?;
+                return Ok(ShouldContinue::Continue);
+            }
+            Frame::Closed => {
+                tracing::info!("No more websocket messages");
+                return Ok(ShouldContinue::Stop);
+            }
         };
-        let msg = msg.context("Error while receiving websocket message")?;
-        match msg {
-            axum::extract::ws::Message::Text(msg) => {
-                let msg: (time::UtcDateTime, api::CrowdPlayerUpdate) = serde_json::from_str(&msg)?;
-                if let api::CrowdPlayerUpdate::Ping = &msg.1 {
-                    let command = serde_json::to_string(&api::CrowdParticipantCommand::Ping)?;
-                    self.websocket.send(Message::Text(command)).await?;
-                } else {
-                    self.update_publisher
-                        .send(msg)
-                        .context("Crowd closed, no more receivers?")?;
+        match update {
+            api::CrowdPlayerUpdate::Ping => {
+                self.send_to_player(api::CrowdPlayerCommand::Ping).await?;
+                return Ok(ShouldContinue::Continue);
+            }
+            api::CrowdPlayerUpdate::PlaybackPosition(position) => {
+                self.session.set_playback_time(position)
+            }
+            api::CrowdPlayerUpdate::Duration(duration) => self.session.set_duration(duration),
+            api::CrowdPlayerUpdate::IsPaused(false) if self.session.is_scheduled() => {
+                tracing::debug!("Player started before the scheduled start, pausing it");
+                self.send_to_player(api::CrowdPlayerCommand::SetIsPaused(true))
+                    .await?;
+                return Ok(ShouldContinue::Continue);
+            }
+            api::CrowdPlayerUpdate::IsPaused(is_paused) => self.session.set_is_paused(is_paused),
+            api::CrowdPlayerUpdate::Speed(speed) => self.session.set_speed(speed),
+            api::CrowdPlayerUpdate::Settings(ref settings) => {
+                self.session.set_settings(settings.clone());
+                self.publish_queue().await?;
+            }
+            api::CrowdPlayerUpdate::Ended(entry) => {
+                match self.session.ended(entry) {
+                    Ended::Ignored => {
+                        tracing::debug!("Ignoring end of entry that is not playing");
+                    }
+                    Ended::Repeat => {
+                        self.history.repeat(&self.session).await;
+                        self.send_to_player(api::CrowdPlayerCommand::SetPlaybackPosition(0.0))
+                            .await?;
+                        self.send_to_player(api::CrowdPlayerCommand::SetIsPaused(false))
+                            .await?;
+                    }
+                    Ended::Advanced => {
+                        self.history.record(&self.session, false).await;
+                        self.publish_queue().await?;
+                    }
                 }
-                Ok(ShouldContinue::Continue)
+                return Ok(ShouldContinue::Continue);
+            }
+            api::CrowdPlayerUpdate::Error(reason) => {
+                tracing::warn!("Player reported an error: {reason}");
+                return Ok(ShouldContinue::Continue);
+            }
+            api::CrowdPlayerUpdate::Moderate(command) => {
+                self.moderate(self.session.player_id(), command).await?;
+                return Ok(ShouldContinue::Continue);
+            }
+            api::CrowdPlayerUpdate::Queue { .. }
+            | api::CrowdPlayerUpdate::SkipVotes { .. }
+            | api::CrowdPlayerUpdate::State(_)
+            | api::CrowdPlayerUpdate::Chat(_)
+            | api::CrowdPlayerUpdate::Participants(_)
+            | api::CrowdPlayerUpdate::Identity(_)
+            | api::CrowdPlayerUpdate::Kicked(_)
+            | api::CrowdPlayerUpdate::Audit(_)
+            | api::CrowdPlayerUpdate::Started(_)
+            | api::CrowdPlayerUpdate::GuestsBlocked(_) => {
+                tracing::warn!("Ignoring update owned by the server: {update:?}");
+                return Ok(ShouldContinue::Continue);
             }
-            msg => anyhow::bail!("Unexpected websocket message: {msg:?}"),
         }
+        self.update_publisher
+            .send((time, update))
+            .context("Crowd closed, no more receivers?")?;
+        Ok(ShouldContinue::Continue)
     }
 }
 
 pub async fn ws_handler_player(
-    State(crowd_map): State<CrowdMap>,
+    State(state): State<ServerState>,
+    Extension(session_state): Extension<SessionState>,
     ws: WebSocketUpgrade,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
@@ -96,67 +442,182 @@ pub async fn ws_handler_player(
         String::from("Unknown browser")
     };
     info!("`{user_agent}` at {addr} connected to player websocket.");
+    // Crowds of pending users have no owner
+    let owner = session_state
+        .user_with_scope(api::ApiTokenScope::CrowdControl)
+        .cloned()
+        .and_then(|user| approved(user).ok());
     ws.on_upgrade(move |socket| async move {
-        let _ = handle_player_websocket(crowd_map, socket, addr).await;
+        let _ = handle_player_websocket(state, owner, socket, addr).await;
     })
 }
 
-#[tracing::instrument(skip(crowd_map, websocket), err(Debug))]
+#[tracing::instrument(skip(state, owner, websocket), err(Debug))]
 async fn handle_player_websocket(
-    crowd_map: CrowdMap,
-    mut websocket: WebSocket,
+    state: ServerState,
+    owner: Option<database::models::User>,
+    websocket: WebSocket,
     addr: SocketAddr,
 ) -> anyhow::Result<()> {
-    let (command_sender, command_receiver) = tokio::sync::mpsc::channel(50);
-    let (update_publisher, update_receiver) = tokio::sync::broadcast::channel(50);
-    let Some(msg) = websocket.recv().await else {
-        let _ = websocket.close().await;
-        bail!("Could not receive initial message");
+    let ServerState {
+        pool,
+        crowd_map,
+        join_codes,
+        crowd_list_changes,
+        crowd_config,
+        ..
+    } = state;
+    let (command_sender, command_receiver) =
+        tokio::sync::mpsc::channel(crowd_config.command_channel_size);
+    let (update_publisher, update_receiver) =
+        tokio::sync::broadcast::channel(crowd_config.update_channel_size);
+    let mut socket = CrowdSocket::accept(websocket).await?;
+    let create_request = match socket
+        .recv_initial::<api::CrowdCreateRequest, (time::UtcDateTime, api::CrowdPlayerUpdate)>(
+            |(_, update)| matches!(update, api::CrowdPlayerUpdate::Ping),
+        )
+        .await
+    {
+        Ok(create_request) => create_request,
+        Err(e) => return reject(socket, e).await,
     };
-
-    let name = match msg {
-        Ok(Message::Text(name)) => name,
-        Ok(msg) => {
-            let _ = websocket.close().await;
-            bail!("Unexpected initial message: {msg:?}");
-        }
-        Err(e) => {
-            let _ = websocket.close().await;
-            bail!("Could not receive initial message: {e:?}");
-        }
+    let api::CrowdCreateRequest {
+        name,
+        password,
+        private,
+        join_code_lifetime_minutes,
+        settings,
+        scheduled_start,
+        playlist_id,
+    } = create_request;
+    let playlist = match playlist_id {
+        Some(playlist_id) => match playlist_videos(&pool, owner.as_ref(), playlist_id).await {
+            Ok(video_ids) => video_ids,
+            Err(e) => return reject(socket, e).await,
+        },
+        None => Vec::new(),
     };
+    // A start time in the past starts the crowd right away
+    let scheduled_start = scheduled_start.filter(|start| *start > time::UtcDateTime::now());
     let name = name.trim().to_string();
+    let password = password.filter(|p| !p.is_empty());
+    let join_code_expires = join_code_lifetime_minutes
+        .map(|minutes| time::UtcDateTime::now() + time::Duration::minutes(i64::from(minutes)));
+    let mut session = CrowdSession::new(settings, scheduled_start);
+    session.queue_videos(&playlist);
+    let (snapshot_publisher, snapshot) = tokio::sync::watch::channel(session.state().clone());
 
     let mut crowd_id;
+    let join_code;
     let span;
     loop {
         crowd_id = CrowdId::new_random();
         let dashmap::Entry::Vacant(entry) = crowd_map.entry(crowd_id) else {
             continue;
         };
+        join_code = reserve_join_code(&join_codes, crowd_id, join_code_expires);
         span = tracing::info_span!("crowd player", %crowd_id, %name);
         entry.insert(crate::server_state::CrowdState {
             crowd_id,
             started: time::UtcDateTime::now(),
-            name,
+            name: name.clone(),
+            join_code: join_code.clone(),
+            password,
+            private,
+            owner_id: owner.as_ref().map(|owner| owner.user_id),
+            owner: owner.as_ref().map(|owner| owner.handle.clone()),
             command_sender,
             update_receiver,
+            snapshot,
         });
         break;
     }
 
     {
         let _span = span.enter();
-        tracing::info!("New crowd player");
+        tracing::info!("New crowd player with join code {join_code}");
     }
 
+    let created = api::CrowdCreated {
+        crowd_id,
+        join_code: join_code.clone(),
+    };
+    if let Err(e) = socket.send(&created).await {
+        crowd_map.remove(&crowd_id);
+        join_codes.remove(&join_code);
+        crowd_list_changes.send_replace(());
+        bail!("Could not send crowd information to player: {e:?}");
+    }
+
+    crowd_list_changes.send_replace(());
+
+    let history = CrowdHistoryRecorder::start(
+        pool.clone(),
+        crowd_id,
+        &name,
+        owner.as_ref().map(|owner| owner.user_id),
+        private,
+    )
+    .await;
+
     let player_state = PlayerConnectionState {
         command_receiver,
         update_publisher,
-        websocket,
+        snapshot_publisher,
+        crowd_list_changes: crowd_list_changes.clone(),
+        session,
+        history,
+        pool,
+        socket,
     };
 
     player_state.handle().instrument(span).await;
     crowd_map.remove(&crowd_id);
+    join_codes.remove(&join_code);
+    crowd_list_changes.send_replace(());
     Ok(())
 }
+
+/// Tells the player why its crowd could not be created and closes the socket.
+async fn reject(mut socket: CrowdSocket, e: anyhow::Error) -> anyhow::Result<()> {
+    let _ = socket
+        .send(&(
+            time::UtcDateTime::now(),
+            api::CrowdPlayerCommand::Error(format!("{e:#}")),
+        ))
+        .await;
+    socket.close().await;
+    Err(e)
+}
+
+async fn playlist_videos(
+    pool: &PgPool,
+    owner: Option<&database::models::User>,
+    playlist_id: api::PlaylistId,
+) -> anyhow::Result<Vec<api::VideoId>> {
+    let Some(owner) = owner else {
+        bail!("Log in to start a crowd from a playlist");
+    };
+    let mut conn = pool.get().await?;
+    let playlist = database::models::Playlist::get_by_id(&mut conn, playlist_id).await?;
+    if playlist.is_none_or(|playlist| playlist.user_id != owner.user_id) {
+        bail!("Playlist not found");
+    }
+    Ok(database::models::Playlist::video_ids(&mut conn, playlist_id).await?)
+}
+
+async fn downloaded_video(pool: &PgPool, youtube_id: &str) -> anyhow::Result<Option<api::VideoId>> {
+    let mut conn = pool.get().await?;
+    Ok(database::models::Video::get_playable_id_by_youtube_id(&mut conn, youtube_id).await?)
+}
+
+/// Waits until `at`, or forever if nothing is scheduled.
+async fn sleep_until(at: Option<time::UtcDateTime>) {
+    let Some(at) = at else {
+        return std::future::pending().await;
+    };
+    let remaining = at - time::UtcDateTime::now();
+    if remaining.is_positive() {
+        tokio::time::sleep(remaining.unsigned_abs()).await;
+    }
+}
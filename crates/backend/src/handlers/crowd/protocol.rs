@@ -0,0 +1,142 @@
+use anyhow::{bail, Context};
+use api::{CrowdEncoding, CrowdHandshake, CrowdHandshakeResponse, CROWD_PROTOCOL_VERSION};
+use axum::extract::ws::{Message, WebSocket};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A crowd websocket after a successful handshake. Encodes and decodes
+/// messages with the encoding negotiated for this connection.
+pub struct CrowdSocket {
+    websocket: WebSocket,
+    encoding: CrowdEncoding,
+}
+
+/// A decoded websocket frame.
+#[derive(Debug)]
+pub enum Frame<T> {
+    Message(T),
+    /// The frame could not be decoded, the connection can continue.
+    Invalid(String),
+    /// Ping and pong frames, answered by axum.
+    Control,
+    Closed,
+}
+
+impl CrowdSocket {
+    /// Performs the versioned handshake. The connection is rejected and closed
+    /// if the client speaks a different protocol version.
+    pub async fn accept(mut websocket: WebSocket) -> anyhow::Result<Self> {
+        let handshake = match websocket.recv().await {
+            Some(Ok(Message::Text(handshake))) => {
+                serde_json::from_str::<CrowdHandshake>(&handshake)
+                    .context("Could not parse handshake")
+            }
+            Some(Ok(msg)) => Err(anyhow::anyhow!("Unexpected handshake message: {msg:?}")),
+            Some(Err(e)) => Err(e).context("Could not receive handshake"),
+            None => Err(anyhow::anyhow!("Connection closed before handshake")),
+        };
+        let handshake = match handshake {
+            Ok(handshake) if handshake.protocol_version == CROWD_PROTOCOL_VERSION => handshake,
+            Ok(handshake) => {
+                let reason = format!(
+                    "Unsupported protocol version {}, please reload the page",
+                    handshake.protocol_version
+                );
+                reject(websocket, reason.clone()).await;
+                bail!(reason);
+            }
+            Err(e) => {
+                reject(websocket, format!("{e:#}")).await;
+                return Err(e);
+            }
+        };
+
+        let response = serde_json::to_string(&CrowdHandshakeResponse::Accepted {
+            encoding: handshake.encoding,
+        })?;
+        websocket.send(Message::Text(response)).await?;
+
+        Ok(Self {
+            websocket,
+            encoding: handshake.encoding,
+        })
+    }
+
+    pub async fn send<T: Serialize>(&mut self, msg: &T) -> anyhow::Result<()> {
+        let msg = match self.encoding {
+            CrowdEncoding::Json => Message::Text(serde_json::to_string(msg)?),
+            CrowdEncoding::Bincode => Message::Binary(bincode::serde::encode_to_vec(
+                msg,
+                bincode::config::standard(),
+            )?),
+        };
+        self.websocket.send(msg).await?;
+        Ok(())
+    }
+
+    pub async fn recv<T: DeserializeOwned>(&mut self) -> anyhow::Result<Frame<T>> {
+        let Some(msg) = self.websocket.recv().await else {
+            return Ok(Frame::Closed);
+        };
+        let msg = msg.context("Error while receiving websocket message")?;
+        Ok(self.decode(msg))
+    }
+
+    /// Receives the first message after the handshake, which has to be valid.
+    /// Heartbeats of type `H` the client sends before it are skipped.
+    pub async fn recv_initial<T: DeserializeOwned, H: DeserializeOwned>(
+        &mut self,
+        is_heartbeat: impl Fn(&H) -> bool,
+    ) -> anyhow::Result<T> {
+        loop {
+            let Some(msg) = self.websocket.recv().await else {
+                bail!("Connection closed before initial message");
+            };
+            let msg = msg.context("Error while receiving websocket message")?;
+            match self.decode(msg.clone()) {
+                Frame::Message(msg) => return Ok(msg),
+                Frame::Control => continue,
+                Frame::Invalid(reason) => match self.decode::<H>(msg) {
+                    Frame::Message(heartbeat) if is_heartbeat(&heartbeat) => continue,
+                    _ => bail!("Invalid initial message: {reason}"),
+                },
+                Frame::Closed => bail!("Connection closed before initial message"),
+            }
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(&self, msg: Message) -> Frame<T> {
+        let decoded = match (self.encoding, msg) {
+            (CrowdEncoding::Json, Message::Text(msg)) => {
+                serde_json::from_str(&msg).map_err(|e| e.to_string())
+            }
+            (CrowdEncoding::Bincode, Message::Binary(msg)) => {
+                bincode::serde::decode_from_slice(&msg, bincode::config::standard())
+                    .map(|(msg, _)| msg)
+                    .map_err(|e| e.to_string())
+            }
+            (_, Message::Ping(_) | Message::Pong(_)) => return Frame::Control,
+            (_, Message::Close(_)) => return Frame::Closed,
+            (encoding, msg) => Err(format!("Unexpected {msg:?} frame for {encoding:?}")),
+        };
+        match decoded {
+            Ok(msg) => Frame::Message(msg),
+            Err(reason) => Frame::Invalid(reason),
+        }
+    }
+
+    pub async fn close(self) {
+        let _ = self.websocket.close().await;
+    }
+}
+
+async fn reject(mut websocket: WebSocket, reason: String) {
+    tracing::warn!("Rejecting crowd connection: {reason}");
+    let response = CrowdHandshakeResponse::Rejected {
+        server_version: CROWD_PROTOCOL_VERSION,
+        reason,
+    };
+    if let Ok(response) = serde_json::to_string(&response) {
+        let _ = websocket.send(Message::Text(response)).await;
+    }
+    let _ = websocket.close().await;
+}
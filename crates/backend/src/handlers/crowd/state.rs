@@ -0,0 +1,1192 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use api::{
+    CrowdAuditEntry, CrowdAuditEntryId, CrowdAutofillMode, CrowdChatContent, CrowdChatMessage,
+    CrowdParticipant, CrowdParticipantCommand, CrowdParticipantId, CrowdQueueEntry, CrowdQueueId,
+    CrowdQueueMode, CrowdQueuePosition, CrowdRepeatMode, CrowdVideo, CrowdVote, UserId, VideoId,
+};
+use rand::{seq::IndexedRandom, Rng};
+
+const CHAT_RATE_LIMIT: u32 = 5;
+const CHAT_RATE_LIMIT_WINDOW: time::Duration = time::Duration::seconds(10);
+
+/// Server side state of a crowd. The server owns the queue so it can apply
+/// votes from all participants and reorder it consistently.
+pub struct CrowdSession {
+    state: api::CrowdState,
+    votes: HashMap<CrowdQueueId, HashMap<CrowdParticipantId, CrowdVote>>,
+    skip_votes: HashSet<CrowdParticipantId>,
+    /// The entry that played last, upcoming entries are the ones after it.
+    last_played: Option<CrowdQueueId>,
+    played_at: HashMap<VideoId, time::UtcDateTime>,
+    /// Users kicked by the owner can not join again.
+    kicked_users: HashSet<UserId>,
+    /// Everyone who joined, kept after they leave so entries queued from an
+    /// earlier connection still count for the same user and are attributed
+    /// in the history.
+    known_participants: HashMap<CrowdParticipantId, CrowdParticipant>,
+    /// How to revert the undoable entries of the audit log.
+    undo_actions: HashMap<CrowdAuditEntryId, QueueUndo>,
+    /// Stands in for the player in the audit log, the player acts as the owner.
+    player_id: CrowdParticipantId,
+    /// Shared by all connections of a user, so reconnecting does not reset it.
+    chat_rate_limits: HashMap<Queuer, ChatRateLimit>,
+}
+
+/// Who a queue entry counts for in the fair share settings, and who a chat
+/// message counts for in the rate limit. Logged in users are the same across
+/// connections, guests are only known by their connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Queuer {
+    User(UserId),
+    Guest(CrowdParticipantId),
+}
+
+/// Allows at most `CHAT_RATE_LIMIT` chat messages and reactions per `CHAT_RATE_LIMIT_WINDOW`.
+struct ChatRateLimit {
+    window_start: time::UtcDateTime,
+    count: u32,
+}
+
+impl Default for ChatRateLimit {
+    fn default() -> Self {
+        Self {
+            window_start: time::UtcDateTime::UNIX_EPOCH,
+            count: 0,
+        }
+    }
+}
+
+impl ChatRateLimit {
+    fn check(&mut self, now: time::UtcDateTime) -> bool {
+        if now - self.window_start >= CHAT_RATE_LIMIT_WINDOW {
+            self.window_start = now;
+            self.count = 0;
+        }
+        self.count += 1;
+        self.count <= CHAT_RATE_LIMIT
+    }
+}
+
+/// Reverts a queue change recorded in the audit log.
+enum QueueUndo {
+    /// Removes an added entry.
+    Remove(CrowdQueueId),
+    /// Puts a deleted entry back where it was.
+    Restore {
+        index: usize,
+        id: CrowdQueueId,
+        entry: CrowdQueueEntry,
+        votes: HashMap<CrowdParticipantId, CrowdVote>,
+    },
+    /// Moves an entry back to where it was.
+    Move { id: CrowdQueueId, index: usize },
+}
+
+/// What happened after the player reported the end of an entry.
+pub enum Ended {
+    /// The entry was not the one currently playing.
+    Ignored,
+    /// The entry should be played again from the start.
+    Repeat,
+    /// The queue moved on to the next entry.
+    Advanced,
+}
+
+impl CrowdSession {
+    pub fn new(settings: api::CrowdSettings, scheduled_start: Option<time::UtcDateTime>) -> Self {
+        Self {
+            state: api::CrowdState {
+                playback_time: 0.0,
+                duration: 0.0,
+                is_paused: true,
+                speed: 1.0,
+                currently_playing: None,
+                queue: Default::default(),
+                settings,
+                skip_votes: 0,
+                chat: VecDeque::new(),
+                participants: Vec::new(),
+                audit_log: VecDeque::new(),
+                guests_blocked: false,
+                scheduled_start,
+            },
+            votes: HashMap::new(),
+            skip_votes: HashSet::new(),
+            last_played: None,
+            played_at: HashMap::new(),
+            kicked_users: HashSet::new(),
+            known_participants: HashMap::new(),
+            undo_actions: HashMap::new(),
+            player_id: CrowdParticipantId::new_random(),
+            chat_rate_limits: HashMap::new(),
+        }
+    }
+
+    pub fn state(&self) -> &api::CrowdState {
+        &self.state
+    }
+
+    pub fn set_settings(&mut self, settings: api::CrowdSettings) {
+        self.state.settings = settings;
+        self.reorder();
+    }
+
+    /// Appends videos to the queue without attributing them to a participant.
+    pub fn queue_videos(&mut self, video_ids: &[VideoId]) {
+        for video_id in video_ids {
+            self.state.queue.insert(
+                CrowdQueueId::new_random(),
+                CrowdQueueEntry {
+                    video: CrowdVideo::Local(*video_id),
+                    added_by: None,
+                    added_at: time::UtcDateTime::now(),
+                    score: 0,
+                },
+            );
+        }
+        if self.state.currently_playing.is_none() {
+            self.advance();
+        }
+        self.reorder();
+    }
+
+    /// Whether playback is held until the scheduled start.
+    pub fn is_scheduled(&self) -> bool {
+        self.state.scheduled_start.is_some()
+    }
+
+    /// Ends the wait for the scheduled start and plays the current entry from
+    /// the beginning.
+    pub fn start(&mut self) {
+        self.state.scheduled_start = None;
+        self.state.playback_time = 0.0;
+        self.state.is_paused = false;
+    }
+
+    pub fn set_playback_time(&mut self, playback_time: f64) {
+        self.state.playback_time = playback_time;
+    }
+
+    pub fn set_duration(&mut self, duration: f64) {
+        self.state.duration = duration;
+    }
+
+    pub fn set_is_paused(&mut self, is_paused: bool) {
+        self.state.is_paused = is_paused;
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.state.speed = speed;
+    }
+
+    pub fn queue_update(&self) -> api::CrowdPlayerUpdate {
+        api::CrowdPlayerUpdate::Queue {
+            currently_playing: self.state.currently_playing,
+            queue: self.state.queue.clone(),
+        }
+    }
+
+    pub fn queue_command(&self) -> api::CrowdPlayerCommand {
+        api::CrowdPlayerCommand::Queue {
+            currently_playing: self.state.currently_playing,
+            queue: self.state.queue.clone(),
+        }
+    }
+
+    /// Applies a queue command from a participant. Returns the new audit log
+    /// entry if the queue changed.
+    pub fn apply(
+        &mut self,
+        participant_id: CrowdParticipantId,
+        command: &CrowdParticipantCommand,
+    ) -> Option<CrowdAuditEntry> {
+        let undo = match command {
+            CrowdParticipantCommand::GoTo(entry) => {
+                if !self.state.queue.contains_key(entry) {
+                    return None;
+                }
+                self.set_currently_playing(Some(*entry));
+                None
+            }
+            CrowdParticipantCommand::AddToQueue(video_id) => {
+                Some(self.add(participant_id, CrowdVideo::Local(*video_id))?)
+            }
+            CrowdParticipantCommand::AddYoutubeToQueue(youtube_id) => {
+                Some(self.add(participant_id, CrowdVideo::Youtube(youtube_id.clone()))?)
+            }
+            CrowdParticipantCommand::MoveInQueue {
+                entry_to_move,
+                position,
+            } => {
+                if self.state.settings.queue_mode != CrowdQueueMode::Manual {
+                    tracing::debug!("Ignoring manual reordering in automatic queue mode");
+                    return None;
+                }
+                let index = self.state.queue.get_index_of(entry_to_move)?;
+                if !self.move_entry(*entry_to_move, position) {
+                    return None;
+                }
+                Some(QueueUndo::Move {
+                    id: *entry_to_move,
+                    index,
+                })
+            }
+            CrowdParticipantCommand::DeleteFromQueue(entry) => {
+                let (index, queue_entry, votes) = self.delete(*entry)?;
+                Some(QueueUndo::Restore {
+                    index,
+                    id: *entry,
+                    entry: queue_entry,
+                    votes,
+                })
+            }
+            CrowdParticipantCommand::Vote { entry, vote } => {
+                if !self.vote(participant_id, *entry, *vote) {
+                    return None;
+                }
+                None
+            }
+            CrowdParticipantCommand::Undo(audit_entry_id) => {
+                if !self.undo(*audit_entry_id) {
+                    return None;
+                }
+                None
+            }
+            CrowdParticipantCommand::Ping
+            | CrowdParticipantCommand::SetPlaybackPosition(_)
+            | CrowdParticipantCommand::SetIsPaused(_)
+            | CrowdParticipantCommand::SetSpeed(_)
+            | CrowdParticipantCommand::VoteSkip
+            | CrowdParticipantCommand::Chat(_)
+            | CrowdParticipantCommand::React(_)
+            | CrowdParticipantCommand::SetIdle(_)
+            | CrowdParticipantCommand::Kick(_)
+            | CrowdParticipantCommand::BlockGuests(_)
+            | CrowdParticipantCommand::Mute { .. } => return None,
+        };
+        self.reorder();
+        Some(self.record(participant_id, command.clone(), undo))
+    }
+
+    /// Queues a video for a participant, unless it reached its limit.
+    fn add(&mut self, participant_id: CrowdParticipantId, video: CrowdVideo) -> Option<QueueUndo> {
+        if let Some(limit) = self.state.settings.max_entries_per_participant {
+            if self.upcoming_entries_by(participant_id) >= limit {
+                tracing::debug!("Participant reached the limit of {limit} queued entries");
+                return None;
+            }
+        }
+        let id = CrowdQueueId::new_random();
+        self.state.queue.insert(
+            id,
+            CrowdQueueEntry {
+                video,
+                added_by: Some(participant_id),
+                added_at: time::UtcDateTime::now(),
+                score: 0,
+            },
+        );
+        if self.state.currently_playing.is_none() {
+            self.advance();
+        }
+        Some(QueueUndo::Remove(id))
+    }
+
+    /// Adds a command that was applied outside of the queue to the audit log
+    /// and returns the new entry.
+    pub fn audit(
+        &mut self,
+        participant_id: CrowdParticipantId,
+        command: CrowdParticipantCommand,
+    ) -> CrowdAuditEntry {
+        self.record(participant_id, command, None)
+    }
+
+    fn record(
+        &mut self,
+        participant_id: CrowdParticipantId,
+        command: CrowdParticipantCommand,
+        undo: Option<QueueUndo>,
+    ) -> CrowdAuditEntry {
+        let handle = match self.participant(participant_id) {
+            Some(participant) => participant.handle.clone(),
+            None if participant_id == self.player_id => "The player".to_string(),
+            None => "Someone who left".to_string(),
+        };
+        tracing::info!("{handle} ({participant_id}) applied {command:?}");
+        let entry = CrowdAuditEntry {
+            audit_entry_id: CrowdAuditEntryId::new_random(),
+            participant_id,
+            handle,
+            applied_at: time::UtcDateTime::now(),
+            command,
+            undoable: undo.is_some(),
+            undone: false,
+        };
+        if let Some(undo) = undo {
+            self.undo_actions.insert(entry.audit_entry_id, undo);
+        }
+        if self.state.audit_log.len() >= api::CROWD_AUDIT_LOG_LENGTH {
+            if let Some(evicted) = self.state.audit_log.pop_front() {
+                self.undo_actions.remove(&evicted.audit_entry_id);
+            }
+        }
+        self.state.audit_log.push_back(entry.clone());
+        entry
+    }
+
+    /// Reverts the queue change of an audit log entry. Every change can only
+    /// be undone once. Returns whether the queue changed.
+    fn undo(&mut self, audit_entry_id: CrowdAuditEntryId) -> bool {
+        let Some(undo) = self.undo_actions.remove(&audit_entry_id) else {
+            tracing::debug!("Audit log entry can not be undone");
+            return false;
+        };
+        let undone = match undo {
+            QueueUndo::Remove(id) => self.delete(id).is_some(),
+            QueueUndo::Restore {
+                index,
+                id,
+                entry,
+                votes,
+            } => {
+                let index = index.min(self.state.queue.len());
+                self.state.queue.shift_insert(index, id, entry);
+                if !votes.is_empty() {
+                    self.votes.insert(id, votes);
+                }
+                if self.state.currently_playing.is_none() {
+                    self.advance();
+                }
+                true
+            }
+            QueueUndo::Move { id, index } => match self.state.queue.get_index_of(&id) {
+                Some(current) => {
+                    let index = index.min(self.state.queue.len() - 1);
+                    self.state.queue.move_index(current, index);
+                    true
+                }
+                None => false,
+            },
+        };
+        if undone {
+            if let Some(entry) = self
+                .state
+                .audit_log
+                .iter_mut()
+                .find(|entry| entry.audit_entry_id == audit_entry_id)
+            {
+                entry.undone = true;
+            }
+        }
+        undone
+    }
+
+    /// Removes an entry from the queue, moving on if it is playing. Returns
+    /// its index, the entry and its votes.
+    fn delete(
+        &mut self,
+        entry: CrowdQueueId,
+    ) -> Option<(
+        usize,
+        CrowdQueueEntry,
+        HashMap<CrowdParticipantId, CrowdVote>,
+    )> {
+        if self.state.currently_playing == Some(entry) {
+            self.advance();
+        }
+        let (index, _, queue_entry) = self.state.queue.shift_remove_full(&entry)?;
+        if self.last_played == Some(entry) {
+            self.last_played = index
+                .checked_sub(1)
+                .and_then(|index| self.state.queue.get_index(index))
+                .map(|(id, _)| *id);
+        }
+        let votes = self.votes.remove(&entry).unwrap_or_default();
+        Some((index, queue_entry, votes))
+    }
+
+    pub fn participant_count(&self) -> usize {
+        self.state.participants.len()
+    }
+
+    fn participant(&self, participant_id: CrowdParticipantId) -> Option<&CrowdParticipant> {
+        self.state
+            .participants
+            .iter()
+            .find(|participant| participant.participant_id == participant_id)
+    }
+
+    fn participant_mut(
+        &mut self,
+        participant_id: CrowdParticipantId,
+    ) -> Option<&mut CrowdParticipant> {
+        self.state
+            .participants
+            .iter_mut()
+            .find(|participant| participant.participant_id == participant_id)
+    }
+
+    /// Adds a participant. Returns false if the user was kicked before, or
+    /// for guests while they are blocked.
+    pub fn join(&mut self, participant: CrowdParticipant) -> bool {
+        let allowed = match participant.user_id {
+            Some(user_id) => !self.kicked_users.contains(&user_id),
+            None => !self.state.guests_blocked,
+        };
+        if !allowed {
+            return false;
+        }
+        self.known_participants
+            .insert(participant.participant_id, participant.clone());
+        self.state.participants.push(participant);
+        true
+    }
+
+    /// Removes a participant and keeps its user from joining again. Guests
+    /// can not be recognized when they come back, so kicking one blocks all
+    /// guests.
+    pub fn kick(&mut self, participant_id: CrowdParticipantId) -> Option<CrowdParticipant> {
+        let participant = self.leave(participant_id)?;
+        match participant.user_id {
+            Some(user_id) => {
+                self.kicked_users.insert(user_id);
+            }
+            None => self.state.guests_blocked = true,
+        }
+        Some(participant)
+    }
+
+    /// Blocks or unblocks guests. Blocking removes the connected guests.
+    /// Returns the removed guests, or `None` if nothing changed.
+    pub fn block_guests(&mut self, blocked: bool) -> Option<Vec<CrowdParticipantId>> {
+        if self.state.guests_blocked == blocked {
+            return None;
+        }
+        self.state.guests_blocked = blocked;
+        let guests: Vec<_> = self
+            .state
+            .participants
+            .iter()
+            .filter(|participant| blocked && participant.user_id.is_none())
+            .map(|participant| participant.participant_id)
+            .collect();
+        for guest in &guests {
+            self.leave(*guest);
+        }
+        Some(guests)
+    }
+
+    pub fn player_id(&self) -> CrowdParticipantId {
+        self.player_id
+    }
+
+    /// Removes a participant and its skip vote. Returns the removed participant.
+    pub fn leave(&mut self, participant_id: CrowdParticipantId) -> Option<CrowdParticipant> {
+        let index = self
+            .state
+            .participants
+            .iter()
+            .position(|participant| participant.participant_id == participant_id)?;
+        self.skip_votes.remove(&participant_id);
+        self.state.skip_votes = self.skip_votes.len();
+        Some(self.state.participants.remove(index))
+    }
+
+    pub fn is_owner(&self, participant_id: CrowdParticipantId) -> bool {
+        participant_id == self.player_id
+            || self
+                .participant(participant_id)
+                .is_some_and(|participant| participant.is_owner)
+    }
+
+    pub fn is_muted(&self, participant_id: CrowdParticipantId) -> bool {
+        self.participant(participant_id)
+            .is_some_and(|participant| participant.muted)
+    }
+
+    /// Returns whether the idle state changed.
+    pub fn set_idle(&mut self, participant_id: CrowdParticipantId, idle: bool) -> bool {
+        match self.participant_mut(participant_id) {
+            Some(participant) if participant.idle != idle => {
+                participant.idle = idle;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns whether the muted state changed.
+    pub fn set_muted(&mut self, participant_id: CrowdParticipantId, muted: bool) -> bool {
+        match self.participant_mut(participant_id) {
+            Some(participant) if participant.muted != muted => {
+                participant.muted = muted;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Adds a chat message or reaction to the history and returns it, unless
+    /// the participant's user exceeded the rate limit.
+    pub fn chat(
+        &mut self,
+        participant_id: CrowdParticipantId,
+        content: CrowdChatContent,
+    ) -> Option<CrowdChatMessage> {
+        let now = time::UtcDateTime::now();
+        let queuer = self.queuer(participant_id);
+        if !self.chat_rate_limits.entry(queuer).or_default().check(now) {
+            tracing::debug!("Dropping chat, rate limit exceeded");
+            return None;
+        }
+        let message = CrowdChatMessage {
+            participant_id,
+            sent_at: now,
+            content,
+        };
+        if self.state.chat.len() >= api::CROWD_CHAT_HISTORY_LENGTH {
+            self.state.chat.pop_front();
+        }
+        self.state.chat.push_back(message.clone());
+        Some(message)
+    }
+
+    /// Registers a vote to skip the currently playing entry and skips it if
+    /// enough of the `participant_count` participants agree. Returns whether
+    /// the entry was skipped.
+    pub fn vote_skip(
+        &mut self,
+        participant_id: CrowdParticipantId,
+        participant_count: usize,
+    ) -> bool {
+        if self.state.currently_playing.is_none() {
+            return false;
+        }
+        self.skip_votes.insert(participant_id);
+        self.state.skip_votes = self.skip_votes.len();
+        if self.state.skip_votes < self.skip_votes_needed(participant_count) {
+            return false;
+        }
+        tracing::info!("Skipping entry after {} votes", self.state.skip_votes);
+        self.advance();
+        self.reorder();
+        true
+    }
+
+    pub fn skip_votes_needed(&self, participant_count: usize) -> usize {
+        let needed = (self.state.settings.skip_threshold * participant_count as f64).ceil();
+        (needed as usize).max(1)
+    }
+
+    /// Called when the player reports that `entry` finished playing.
+    pub fn ended(&mut self, entry: CrowdQueueId) -> Ended {
+        if self.state.currently_playing != Some(entry) {
+            return Ended::Ignored;
+        }
+        if self.state.settings.repeat == CrowdRepeatMode::One {
+            return Ended::Repeat;
+        }
+        self.advance();
+        self.reorder();
+        Ended::Advanced
+    }
+
+    pub fn needs_autofill(&self) -> bool {
+        self.state.currently_playing.is_none()
+            && self.state.settings.autofill != CrowdAutofillMode::Off
+    }
+
+    /// Queues and plays one of the `candidates` according to the autofill
+    /// mode. Returns whether the queue changed.
+    pub fn autofill(&mut self, candidates: &[VideoId]) -> bool {
+        let mut rng = rand::rng();
+        let video_id = match self.state.settings.autofill {
+            CrowdAutofillMode::Off => None,
+            CrowdAutofillMode::Random => candidates.choose(&mut rng).copied(),
+            CrowdAutofillMode::LeastRecentlyPlayed => {
+                // Start at a random offset so ties don't always pick the same video
+                let offset = rng.random_range(0..candidates.len().max(1));
+                candidates[offset..]
+                    .iter()
+                    .chain(&candidates[..offset])
+                    .min_by_key(|video_id| self.played_at.get(video_id))
+                    .copied()
+            }
+        };
+        let Some(video_id) = video_id else {
+            return false;
+        };
+        let id = CrowdQueueId::new_random();
+        let index = self.position().map_or(0, |index| index + 1);
+        self.state.queue.shift_insert(
+            index,
+            id,
+            CrowdQueueEntry {
+                video: CrowdVideo::Local(video_id),
+                added_by: None,
+                added_at: time::UtcDateTime::now(),
+                score: 0,
+            },
+        );
+        self.set_currently_playing(Some(id));
+        true
+    }
+
+    /// Moves to the entry after the current one, honoring the shuffle and
+    /// repeat settings.
+    fn advance(&mut self) {
+        let start = self.position().map_or(0, |index| index + 1);
+        let len = self.state.queue.len();
+        let mut rng = rand::rng();
+        let next = if start < len {
+            if self.state.settings.shuffle {
+                // Move the random pick to the front so the queue shows what played
+                self.state
+                    .queue
+                    .move_index(rng.random_range(start..len), start);
+            }
+            Some(start)
+        } else if self.state.settings.repeat == CrowdRepeatMode::All && len > 0 {
+            Some(if self.state.settings.shuffle {
+                rng.random_range(0..len)
+            } else {
+                0
+            })
+        } else {
+            None
+        };
+        let next = next
+            .and_then(|index| self.state.queue.get_index(index))
+            .map(|(id, _)| *id);
+        self.set_currently_playing(next);
+    }
+
+    fn set_currently_playing(&mut self, entry: Option<CrowdQueueId>) {
+        if self.state.currently_playing != entry {
+            self.skip_votes.clear();
+            self.state.skip_votes = 0;
+        }
+        if let Some(entry) = entry {
+            self.last_played = Some(entry);
+            if let Some(CrowdQueueEntry {
+                video: CrowdVideo::Local(video_id),
+                ..
+            }) = self.state.queue.get(&entry)
+            {
+                self.played_at.insert(*video_id, time::UtcDateTime::now());
+            }
+        }
+        self.state.currently_playing = entry;
+    }
+
+    /// Index of the currently playing entry, or of the last played one if
+    /// nothing is playing.
+    fn position(&self) -> Option<usize> {
+        self.state
+            .currently_playing
+            .or(self.last_played)
+            .and_then(|id| self.state.queue.get_index_of(&id))
+    }
+
+    fn queuer(&self, participant_id: CrowdParticipantId) -> Queuer {
+        let user_id = self
+            .known_participants
+            .get(&participant_id)
+            .and_then(|participant| participant.user_id);
+        match user_id {
+            Some(user_id) => Queuer::User(user_id),
+            None => Queuer::Guest(participant_id),
+        }
+    }
+
+    /// The participant that queued the entry as it was when it joined, even
+    /// if it left since.
+    pub fn queued_by(&self, entry: &CrowdQueueEntry) -> Option<&CrowdParticipant> {
+        self.known_participants.get(&entry.added_by?)
+    }
+
+    /// Upcoming entries queued by the participant's user, or by the
+    /// participant itself for guests.
+    fn upcoming_entries_by(&self, participant_id: CrowdParticipantId) -> usize {
+        let queuer = self.queuer(participant_id);
+        let start = self.position().map_or(0, |index| index + 1);
+        self.state.queue[start..]
+            .values()
+            .filter(|entry| {
+                entry
+                    .added_by
+                    .is_some_and(|added_by| self.queuer(added_by) == queuer)
+            })
+            .count()
+    }
+
+    fn move_entry(&mut self, entry_to_move: CrowdQueueId, position: &CrowdQueuePosition) -> bool {
+        let Some((_, id, entry)) = self.state.queue.shift_remove_full(&entry_to_move) else {
+            return false;
+        };
+        let index = match position {
+            CrowdQueuePosition::Before(other) | CrowdQueuePosition::Between(_, other) => {
+                self.state.queue.get_index_of(other)
+            }
+            CrowdQueuePosition::After(other) => {
+                self.state.queue.get_index_of(other).map(|index| index + 1)
+            }
+        };
+        let index = index.unwrap_or(self.state.queue.len());
+        self.state.queue.shift_insert(index, id, entry);
+        true
+    }
+
+    fn vote(
+        &mut self,
+        participant_id: CrowdParticipantId,
+        entry: CrowdQueueId,
+        vote: CrowdVote,
+    ) -> bool {
+        if !self.state.queue.contains_key(&entry) {
+            return false;
+        }
+        let votes = self.votes.entry(entry).or_default();
+        let changed = match vote {
+            CrowdVote::Clear => votes.remove(&participant_id).is_some(),
+            vote => votes.insert(participant_id, vote) != Some(vote),
+        };
+        if !changed {
+            return false;
+        }
+        let score = votes
+            .values()
+            .map(|vote| match vote {
+                CrowdVote::Up => 1,
+                CrowdVote::Down => -1,
+                CrowdVote::Clear => 0,
+            })
+            .sum();
+        if let Some(entry) = self.state.queue.get_mut(&entry) {
+            entry.score = score;
+        }
+        self.reorder();
+        true
+    }
+
+    /// Reorders the entries after the currently playing one according to the
+    /// queue mode. Ties are broken by insertion time.
+    fn reorder(&mut self) {
+        let start = self.position().map_or(0, |index| index + 1);
+        match self.state.settings.queue_mode {
+            CrowdQueueMode::Manual => {}
+            CrowdQueueMode::Voting => {
+                let mut upcoming = self.state.queue.split_off(start);
+                upcoming
+                    .sort_by(|_, a, _, b| b.score.cmp(&a.score).then(a.added_at.cmp(&b.added_at)));
+                self.state.queue.extend(upcoming);
+            }
+            CrowdQueueMode::RoundRobin => {
+                // The n'th upcoming entry of every user or guest is played
+                // before anyone's (n+1)'th entry.
+                let mut upcoming = self.state.queue.split_off(start);
+                upcoming.sort_by(|_, a, _, b| a.added_at.cmp(&b.added_at));
+                let mut turns = HashMap::<Option<Queuer>, usize>::new();
+                let mut upcoming: Vec<_> = upcoming
+                    .into_iter()
+                    .map(|(id, entry)| {
+                        let queuer = entry.added_by.map(|added_by| self.queuer(added_by));
+                        let turn = turns.entry(queuer).or_default();
+                        *turn += 1;
+                        (*turn, id, entry)
+                    })
+                    .collect();
+                upcoming.sort_by(|(a_turn, _, a), (b_turn, _, b)| {
+                    a_turn.cmp(b_turn).then(a.added_at.cmp(&b.added_at))
+                });
+                self.state
+                    .queue
+                    .extend(upcoming.into_iter().map(|(_, id, entry)| (id, entry)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(queue_mode: CrowdQueueMode) -> CrowdSession {
+        CrowdSession::new(
+            api::CrowdSettings {
+                queue_mode,
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    fn join(session: &mut CrowdSession, user_id: Option<UserId>) -> CrowdParticipantId {
+        let participant_id = CrowdParticipantId::new_random();
+        assert!(session.join(CrowdParticipant {
+            participant_id,
+            user_id,
+            handle: "Someone".to_string(),
+            profile_picture_url: None,
+            joined_at: time::UtcDateTime::now(),
+            idle: false,
+            muted: false,
+            is_owner: false,
+        }));
+        participant_id
+    }
+
+    /// Queues a new video and returns it with the audit log entry.
+    fn add(
+        session: &mut CrowdSession,
+        participant_id: CrowdParticipantId,
+    ) -> (VideoId, CrowdAuditEntryId) {
+        let video_id = VideoId::new_random();
+        let entry = session
+            .apply(
+                participant_id,
+                &CrowdParticipantCommand::AddToQueue(video_id),
+            )
+            .expect("video should be queued");
+        (video_id, entry.audit_entry_id)
+    }
+
+    fn id_of(session: &CrowdSession, video_id: VideoId) -> CrowdQueueId {
+        session
+            .state()
+            .queue
+            .iter()
+            .find(|(_, entry)| entry.video == CrowdVideo::Local(video_id))
+            .map(|(id, _)| *id)
+            .expect("video should be in the queue")
+    }
+
+    fn videos(session: &CrowdSession) -> Vec<VideoId> {
+        session
+            .state()
+            .queue
+            .values()
+            .map(|entry| match entry.video {
+                CrowdVideo::Local(video_id) => video_id,
+                CrowdVideo::Youtube(_) => panic!("only library videos are queued"),
+            })
+            .collect()
+    }
+
+    fn playing(session: &CrowdSession) -> Option<VideoId> {
+        let id = session.state().currently_playing?;
+        match session.state().queue[&id].video {
+            CrowdVideo::Local(video_id) => Some(video_id),
+            CrowdVideo::Youtube(_) => None,
+        }
+    }
+
+    #[test]
+    fn manual_mode_keeps_insertion_order() {
+        let mut session = session(CrowdQueueMode::Manual);
+        let participant = CrowdParticipantId::new_random();
+        let (a, _) = add(&mut session, participant);
+        let (b, _) = add(&mut session, participant);
+        let (c, _) = add(&mut session, participant);
+
+        session.apply(
+            participant,
+            &CrowdParticipantCommand::Vote {
+                entry: id_of(&session, c),
+                vote: CrowdVote::Up,
+            },
+        );
+        assert_eq!(videos(&session), [a, b, c]);
+    }
+
+    #[test]
+    fn voting_mode_orders_upcoming_entries_by_score() {
+        let mut session = session(CrowdQueueMode::Voting);
+        let first = CrowdParticipantId::new_random();
+        let second = CrowdParticipantId::new_random();
+        let (a, _) = add(&mut session, first);
+        let (b, _) = add(&mut session, first);
+        let (c, _) = add(&mut session, first);
+        let (d, _) = add(&mut session, first);
+
+        for participant in [first, second] {
+            session.apply(
+                participant,
+                &CrowdParticipantCommand::Vote {
+                    entry: id_of(&session, d),
+                    vote: CrowdVote::Up,
+                },
+            );
+        }
+        session.apply(
+            first,
+            &CrowdParticipantCommand::Vote {
+                entry: id_of(&session, b),
+                vote: CrowdVote::Down,
+            },
+        );
+        session.apply(
+            second,
+            &CrowdParticipantCommand::Vote {
+                entry: id_of(&session, c),
+                vote: CrowdVote::Up,
+            },
+        );
+
+        // The playing entry stays in front
+        assert_eq!(videos(&session), [a, d, c, b]);
+
+        session.apply(
+            second,
+            &CrowdParticipantCommand::Vote {
+                entry: id_of(&session, c),
+                vote: CrowdVote::Clear,
+            },
+        );
+        assert_eq!(videos(&session), [a, d, c, b]);
+        assert_eq!(session.state().queue[&id_of(&session, c)].score, 0);
+    }
+
+    #[test]
+    fn round_robin_mode_interleaves_participants() {
+        let mut session = session(CrowdQueueMode::RoundRobin);
+        let first = CrowdParticipantId::new_random();
+        let second = CrowdParticipantId::new_random();
+        let (a, _) = add(&mut session, first);
+        let (b, _) = add(&mut session, first);
+        let (c, _) = add(&mut session, first);
+        let (d, _) = add(&mut session, second);
+        let (e, _) = add(&mut session, second);
+        // The playing entry does not take a turn
+        assert_eq!(videos(&session), [a, b, d, c, e]);
+    }
+
+    #[test]
+    fn entry_limit_counts_upcoming_entries_per_participant() {
+        let mut session = CrowdSession::new(
+            api::CrowdSettings {
+                max_entries_per_participant: Some(2),
+                ..Default::default()
+            },
+            None,
+        );
+        let first = CrowdParticipantId::new_random();
+        let second = CrowdParticipantId::new_random();
+        // The first entry plays right away and does not count
+        add(&mut session, first);
+        add(&mut session, first);
+        add(&mut session, first);
+
+        assert!(session
+            .apply(
+                first,
+                &CrowdParticipantCommand::AddToQueue(VideoId::new_random()),
+            )
+            .is_none());
+        assert!(session
+            .apply(
+                second,
+                &CrowdParticipantCommand::AddToQueue(VideoId::new_random()),
+            )
+            .is_some());
+    }
+
+    #[test]
+    fn round_robin_mode_shares_turns_between_connections_of_a_user() {
+        let mut session = session(CrowdQueueMode::RoundRobin);
+        let user = UserId::new_random();
+        let first = join(&mut session, Some(user));
+        let guest = join(&mut session, None);
+        let (a, _) = add(&mut session, first);
+        let (b, _) = add(&mut session, first);
+        let (c, _) = add(&mut session, first);
+        let (d, _) = add(&mut session, guest);
+        assert_eq!(videos(&session), [a, b, d, c]);
+
+        let second = join(&mut session, Some(user));
+        let (e, _) = add(&mut session, second);
+        let (f, _) = add(&mut session, guest);
+        assert_eq!(videos(&session), [a, b, d, c, f, e]);
+    }
+
+    #[test]
+    fn entry_limit_counts_all_connections_of_a_user() {
+        let mut session = CrowdSession::new(
+            api::CrowdSettings {
+                max_entries_per_participant: Some(2),
+                ..Default::default()
+            },
+            None,
+        );
+        let user = UserId::new_random();
+        let first = join(&mut session, Some(user));
+        let second = join(&mut session, Some(user));
+        let guest = join(&mut session, None);
+        // The first entry plays right away and does not count
+        add(&mut session, first);
+        add(&mut session, first);
+        add(&mut session, second);
+
+        assert!(session
+            .apply(
+                second,
+                &CrowdParticipantCommand::AddToQueue(VideoId::new_random()),
+            )
+            .is_none());
+        assert!(session
+            .apply(
+                guest,
+                &CrowdParticipantCommand::AddToQueue(VideoId::new_random()),
+            )
+            .is_some());
+    }
+
+    #[test]
+    fn undo_restores_deleted_entry_after_later_changes() {
+        let mut session = session(CrowdQueueMode::Manual);
+        let participant = join(&mut session, None);
+        let (a, _) = add(&mut session, participant);
+        let (b, _) = add(&mut session, participant);
+        let (c, _) = add(&mut session, participant);
+        let (d, _) = add(&mut session, participant);
+
+        let delete_b = session
+            .apply(
+                participant,
+                &CrowdParticipantCommand::DeleteFromQueue(id_of(&session, b)),
+            )
+            .unwrap();
+        session
+            .apply(
+                participant,
+                &CrowdParticipantCommand::DeleteFromQueue(id_of(&session, c)),
+            )
+            .unwrap();
+        session
+            .apply(
+                participant,
+                &CrowdParticipantCommand::MoveInQueue {
+                    entry_to_move: id_of(&session, d),
+                    position: CrowdQueuePosition::Before(id_of(&session, a)),
+                },
+            )
+            .unwrap();
+        assert_eq!(videos(&session), [d, a]);
+
+        // The old index is clamped to the shorter queue
+        session.apply(
+            participant,
+            &CrowdParticipantCommand::Undo(delete_b.audit_entry_id),
+        );
+        assert_eq!(videos(&session), [d, b, a]);
+        assert!(
+            session
+                .state()
+                .audit_log
+                .iter()
+                .find(|entry| entry.audit_entry_id == delete_b.audit_entry_id)
+                .unwrap()
+                .undone
+        );
+
+        // Every change can only be undone once
+        assert!(session
+            .apply(
+                participant,
+                &CrowdParticipantCommand::Undo(delete_b.audit_entry_id),
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn undo_move_after_later_deletes() {
+        let mut session = session(CrowdQueueMode::Manual);
+        let participant = join(&mut session, None);
+        let (a, _) = add(&mut session, participant);
+        let (b, _) = add(&mut session, participant);
+        let (c, _) = add(&mut session, participant);
+
+        let move_c = session
+            .apply(
+                participant,
+                &CrowdParticipantCommand::MoveInQueue {
+                    entry_to_move: id_of(&session, c),
+                    position: CrowdQueuePosition::Before(id_of(&session, b)),
+                },
+            )
+            .unwrap();
+        assert_eq!(videos(&session), [a, c, b]);
+        session
+            .apply(
+                participant,
+                &CrowdParticipantCommand::DeleteFromQueue(id_of(&session, b)),
+            )
+            .unwrap();
+
+        session.apply(
+            participant,
+            &CrowdParticipantCommand::Undo(move_c.audit_entry_id),
+        );
+        assert_eq!(videos(&session), [a, c]);
+    }
+
+    #[test]
+    fn undo_delete_of_playing_entry() {
+        let mut session = session(CrowdQueueMode::Manual);
+        let participant = join(&mut session, None);
+        let (a, _) = add(&mut session, participant);
+        let (b, _) = add(&mut session, participant);
+        assert_eq!(playing(&session), Some(a));
+
+        let delete_a = session
+            .apply(
+                participant,
+                &CrowdParticipantCommand::DeleteFromQueue(id_of(&session, a)),
+            )
+            .unwrap();
+        assert_eq!(playing(&session), Some(b));
+
+        // The entry comes back, but playback stays where it moved on to
+        session.apply(
+            participant,
+            &CrowdParticipantCommand::Undo(delete_a.audit_entry_id),
+        );
+        assert_eq!(videos(&session), [a, b]);
+        assert_eq!(playing(&session), Some(b));
+    }
+
+    #[test]
+    fn undo_add_of_playing_entry() {
+        let mut session = session(CrowdQueueMode::Manual);
+        let participant = join(&mut session, None);
+        let (a, add_a) = add(&mut session, participant);
+        let (b, _) = add(&mut session, participant);
+        assert_eq!(playing(&session), Some(a));
+
+        session.apply(participant, &CrowdParticipantCommand::Undo(add_a));
+        assert_eq!(videos(&session), [b]);
+        assert_eq!(playing(&session), Some(b));
+    }
+
+    #[test]
+    fn chat_rate_limit_is_shared_between_connections_of_a_user() {
+        let mut session = session(CrowdQueueMode::Manual);
+        let user = UserId::new_random();
+        let first = join(&mut session, Some(user));
+        let second = join(&mut session, Some(user));
+        let guest = join(&mut session, None);
+        let mut chat = |participant_id| {
+            session
+                .chat(participant_id, CrowdChatContent::Message("hi".to_string()))
+                .is_some()
+        };
+
+        for _ in 0..CHAT_RATE_LIMIT {
+            assert!(chat(first));
+        }
+        assert!(!chat(second));
+        assert!(chat(guest));
+    }
+}
@@ -1,5 +1,7 @@
+pub mod admin;
 pub mod auth;
 pub mod crowd;
 pub mod download;
+pub mod playlists;
 pub mod user;
 pub mod videos;
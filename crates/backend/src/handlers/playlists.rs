@@ -0,0 +1,94 @@
+use api::{ApiError, CrowdId, PlaylistId};
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+
+use crate::{
+    error::{ListenErrorExt, Result},
+    PgPool,
+};
+
+pub async fn list_playlists(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<database::models::User>,
+) -> Result<Json<Vec<api::Playlist>>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    let playlists = database::models::Playlist::list_by_user_id(&mut conn, user.user_id)
+        .await
+        .with_internal_server_error()?;
+    Ok(Json(playlists.into_iter().map(Into::into).collect()))
+}
+
+pub async fn get_playlist(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<database::models::User>,
+    Path(playlist_id): Path<PlaylistId>,
+) -> Result<Json<api::PlaylistWithVideos>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    let Some(playlist) = database::models::Playlist::get_by_id(&mut conn, playlist_id)
+        .await
+        .with_internal_server_error()?
+    else {
+        return Err(ApiError::NotFound.into());
+    };
+    if playlist.user_id != user.user_id {
+        return Err(ApiError::NotFound.into());
+    }
+    let video_ids = database::models::Playlist::video_ids(&mut conn, playlist_id)
+        .await
+        .with_internal_server_error()?;
+
+    Ok(Json(api::PlaylistWithVideos {
+        playlist: playlist.into(),
+        video_ids,
+    }))
+}
+
+/// Saves the videos played in a crowd as a playlist, in the order they played.
+pub async fn create_playlist_from_crowd(
+    State(pool): State<PgPool>,
+    Extension(user): Extension<database::models::User>,
+    Path(crowd_id): Path<CrowdId>,
+    Json(request): Json<api::PlaylistFromCrowdRequest>,
+) -> Result<Json<api::Playlist>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    let Some(crowd) = database::models::Crowd::get_by_id(&mut conn, crowd_id)
+        .await
+        .with_internal_server_error()?
+        .filter(|crowd| crowd.is_visible_to(user.user_id))
+    else {
+        return Err(ApiError::NotFound.into());
+    };
+    let entries = database::models::CrowdHistoryEntry::list_by_crowd_id(&mut conn, crowd_id)
+        .await
+        .with_internal_server_error()?;
+    let mut video_ids = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if request.exclude_skipped && entry.skipped {
+            continue;
+        }
+        // Playlists only hold library videos, YouTube videos count once downloaded
+        let video_id = match (entry.video_id, entry.youtube_id) {
+            (Some(video_id), _) => Some(video_id),
+            (None, Some(youtube_id)) => {
+                database::models::Video::get_playable_id_by_youtube_id(&mut conn, &youtube_id)
+                    .await
+                    .with_internal_server_error()?
+            }
+            (None, None) => None,
+        };
+        video_ids.extend(video_id);
+    }
+
+    let name = request
+        .name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or(crowd.name);
+    let playlist = database::models::Playlist::create(&mut conn, user.user_id, &name, &video_ids)
+        .await
+        .with_internal_server_error()?;
+
+    Ok(Json(playlist.into()))
+}
@@ -1,5 +1,210 @@
-use axum::{Extension, Json};
+use api::{ApiTokenId, OidcMappingId, UserSessionId};
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use strum::VariantArray;
+
+use crate::{
+    api_tokens,
+    auth_providers::AuthProviders,
+    error::{ListenErrorExt, Result},
+    handlers::auth::{SessionState, SESSION_IDLE_TIMEOUT},
+    PgPool,
+};
 
 pub async fn get_profile(Extension(user): Extension<database::models::User>) -> Json<api::User> {
     Json(user.into())
 }
+
+pub async fn list_sessions(
+    Extension(user): Extension<database::models::User>,
+    Extension(session_state): Extension<SessionState>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Vec<api::SessionInfo>>> {
+    let current = match session_state {
+        SessionState::Authenticated { user_session, .. } => Some(user_session.user_session_id),
+        _ => None,
+    };
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    let mut sessions = database::models::UserSession::list_active_by_user_id(
+        &mut conn,
+        user.user_id,
+        time::OffsetDateTime::now_utc() - SESSION_IDLE_TIMEOUT,
+    )
+    .await
+    .with_internal_server_error()?;
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| api::SessionInfo {
+                current: Some(session.user_session_id) == current,
+                user_session_id: session.user_session_id,
+                user_agent: session.user_agent,
+                created_at: session.created_at,
+                last_active_at: session.updated_at,
+            })
+            .collect(),
+    ))
+}
+
+/// Logs out a session, e.g. on a lost device.
+pub async fn revoke_session(
+    Extension(user): Extension<database::models::User>,
+    State(pool): State<PgPool>,
+    Path(user_session_id): Path<UserSessionId>,
+) -> Result<Json<()>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    if database::models::UserSession::delete_for_user(&mut conn, user.user_id, user_session_id)
+        .await
+        .with_internal_server_error()?
+    {
+        Ok(Json(()))
+    } else {
+        Err(api::ApiError::NotFound.into())
+    }
+}
+
+pub async fn list_api_tokens(
+    Extension(user): Extension<database::models::User>,
+    State(pool): State<PgPool>,
+) -> Result<Json<Vec<api::ApiToken>>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    let api_tokens = database::models::ApiToken::list_by_user_id(&mut conn, user.user_id)
+        .await
+        .with_internal_server_error()?;
+
+    Ok(Json(api_tokens.into_iter().map(Into::into).collect()))
+}
+
+/// Mints a personal API token. Only its hash is stored, so the token is only
+/// returned here.
+pub async fn create_api_token(
+    Extension(user): Extension<database::models::User>,
+    State(pool): State<PgPool>,
+    Json(request): Json<api::ApiTokenCreateRequest>,
+) -> Result<Json<api::ApiTokenCreated>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+
+    let name = Some(request.name.trim())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("API token");
+    let scopes: Vec<_> = api::ApiTokenScope::VARIANTS
+        .iter()
+        .copied()
+        .filter(|scope| request.scopes.contains(scope))
+        .collect();
+    let token = api_tokens::generate();
+    let api_token = database::models::ApiToken::create(
+        &mut conn,
+        user.user_id,
+        name,
+        &api_tokens::hash(&token),
+        &scopes,
+    )
+    .await
+    .with_internal_server_error()?;
+
+    Ok(Json(api::ApiTokenCreated {
+        api_token: api_token.into(),
+        token,
+    }))
+}
+
+pub async fn revoke_api_token(
+    Extension(user): Extension<database::models::User>,
+    State(pool): State<PgPool>,
+    Path(api_token_id): Path<ApiTokenId>,
+) -> Result<Json<()>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    if database::models::ApiToken::delete_for_user(&mut conn, user.user_id, api_token_id)
+        .await
+        .with_internal_server_error()?
+    {
+        Ok(Json(()))
+    } else {
+        Err(api::ApiError::NotFound.into())
+    }
+}
+
+pub async fn list_identities(
+    Extension(user): Extension<database::models::User>,
+    State(pool): State<PgPool>,
+    State(auth_providers): State<AuthProviders>,
+) -> Result<Json<Vec<api::LinkedIdentity>>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+    let mappings = database::models::OidcMapping::list_by_user_id(&mut conn, user.user_id)
+        .await
+        .with_internal_server_error()?;
+
+    Ok(Json(
+        mappings
+            .into_iter()
+            .map(|mapping| api::LinkedIdentity {
+                provider_name: auth_providers
+                    .get_by_issuer_url(&mapping.oidc_issuer_url)
+                    .map(|provider| provider.name.clone()),
+                oidc_mapping_id: mapping.oidc_mapping_id,
+                oidc_issuer_url: mapping.oidc_issuer_url,
+                created_at: mapping.created_at,
+            })
+            .collect(),
+    ))
+}
+
+/// Starts a login with another provider. When it completes, the identity is
+/// linked to the logged in user instead of creating a new user.
+pub async fn link_identity(
+    Extension(session_state): Extension<SessionState>,
+    State(pool): State<PgPool>,
+    State(auth_providers): State<AuthProviders>,
+    Json(request): Json<api::AuthUrlRequest>,
+) -> Result<Json<api::AuthUrlResponse>> {
+    let SessionState::Authenticated { user_session, .. } = session_state else {
+        return Err(api::ApiError::NotAuthorized.into());
+    };
+    let mut conn = pool.get().await.with_internal_server_error()?;
+
+    let Some(auth_provider) = auth_providers.get(&request.provider) else {
+        return Err(api::ApiError::NotFound.into());
+    };
+    let auth_url = auth_provider.auth_url().await?;
+
+    database::models::UserSession::start_link(
+        &mut conn,
+        user_session.user_session_id,
+        &auth_provider.id,
+        &auth_url.issuer_url,
+        &auth_url.csrf_token,
+        &auth_url.nonce,
+        &auth_url.pkce_code_verifier,
+    )
+    .await
+    .with_internal_server_error()?;
+
+    Ok(Json(api::AuthUrlResponse {
+        url: auth_url.auth_url.as_str().to_string(),
+    }))
+}
+
+pub async fn unlink_identity(
+    Extension(user): Extension<database::models::User>,
+    State(pool): State<PgPool>,
+    Path(oidc_mapping_id): Path<OidcMappingId>,
+) -> Result<Json<()>> {
+    let mut conn = pool.get().await.with_internal_server_error()?;
+
+    match database::models::OidcMapping::unlink(&mut conn, user.user_id, oidc_mapping_id)
+        .await
+        .with_internal_server_error()?
+    {
+        database::models::Unlink::Unlinked => Ok(Json(())),
+        database::models::Unlink::NotFound => Err(api::ApiError::NotFound.into()),
+        database::models::Unlink::LastIdentity => Err(api::ApiError::Conflict(
+            "Can't unlink the last identity of an account".to_string(),
+        )
+        .into()),
+    }
+}
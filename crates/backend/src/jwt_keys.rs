@@ -0,0 +1,89 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{bail, Context};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Key id of tokens issued before tokens had a `kid` header, and of the
+/// signing key if `JWT_KEY_ID` is not set.
+const DEFAULT_KEY_ID: &str = "default";
+
+/// Keys for the session tokens. Tokens are signed with the current key and
+/// name it in their `kid` header. Previous keys are still accepted, so the
+/// secret can be rotated without logging everyone out.
+#[derive(Clone)]
+pub struct JwtKeys(Arc<JwtKeysInner>);
+
+struct JwtKeysInner {
+    current_key_id: String,
+    encoding_key: EncodingKey,
+    decoding_keys: HashMap<String, DecodingKey>,
+}
+
+impl JwtKeys {
+    /// Uses `jwt_secret` as the signing key with the id in `JWT_KEY_ID`.
+    /// `JWT_PREVIOUS_KEYS` lists keys that are only accepted, as
+    /// comma separated `id=secret` pairs.
+    pub fn load_from_env(jwt_secret: &str) -> anyhow::Result<Self> {
+        let current_key_id =
+            std::env::var("JWT_KEY_ID").unwrap_or_else(|_| DEFAULT_KEY_ID.to_string());
+
+        let mut decoding_keys = HashMap::from([(
+            current_key_id.clone(),
+            DecodingKey::from_secret(jwt_secret.as_bytes()),
+        )]);
+        if let Ok(previous_keys) = std::env::var("JWT_PREVIOUS_KEYS") {
+            for key in previous_keys
+                .split(',')
+                .filter(|key| !key.trim().is_empty())
+            {
+                let (key_id, secret) = key
+                    .trim()
+                    .split_once('=')
+                    .context("JWT_PREVIOUS_KEYS entries must be of the form id=secret")?;
+                if decoding_keys
+                    .insert(
+                        key_id.to_string(),
+                        DecodingKey::from_secret(secret.as_bytes()),
+                    )
+                    .is_some()
+                {
+                    bail!("JWT key id {key_id} is used more than once");
+                }
+            }
+        }
+
+        Ok(Self(Arc::new(JwtKeysInner {
+            encoding_key: EncodingKey::from_secret(jwt_secret.as_bytes()),
+            current_key_id,
+            decoding_keys,
+        })))
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> jsonwebtoken::errors::Result<String> {
+        let header = Header {
+            kid: Some(self.0.current_key_id.clone()),
+            ..Header::new(Algorithm::HS256)
+        };
+        jsonwebtoken::encode(&header, claims, &self.0.encoding_key)
+    }
+
+    /// Validates the token with the key named in its header.
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> anyhow::Result<T> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let key_id = header.kid.as_deref().unwrap_or(DEFAULT_KEY_ID);
+        let Some(decoding_key) = self.0.decoding_keys.get(key_id) else {
+            bail!("Unknown JWT key id {key_id}");
+        };
+        let validation = Validation::new(Algorithm::HS256);
+        Ok(jsonwebtoken::decode::<T>(token, decoding_key, &validation)?.claims)
+    }
+
+    /// Whether the token was signed with the current key. Other tokens should
+    /// be reissued, so old keys can be removed.
+    pub fn is_current(&self, token: &str) -> bool {
+        jsonwebtoken::decode_header(token).is_ok_and(|header| {
+            header.kid.as_deref().unwrap_or(DEFAULT_KEY_ID) == self.0.current_key_id
+        })
+    }
+}
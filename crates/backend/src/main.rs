@@ -1,16 +1,17 @@
-use std::{net::SocketAddr, path::PathBuf, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use anyhow::Context;
 use api::ApiError;
 use axum::{
-    middleware::{map_request, map_request_with_state},
+    extract::Request,
+    middleware::{from_fn_with_state, map_request, map_request_with_state},
     routing::{get, post},
     Router,
 };
 use axum_server::tls_rustls::RustlsConfig;
 use database::MIGRATIONS;
 use error::ListenError;
-use handlers::crowd;
+use handlers::{auth::SessionState, crowd};
 use leptos::prelude::*;
 use leptos_axum::{generate_route_list, LeptosRoutes};
 use tokio::signal;
@@ -25,14 +26,20 @@ use ui::App;
 
 use crate::{
     db::setup_database_pool,
-    server_state::{ServerState, VideosDir},
+    server_state::{CrowdConfig, ServerConfig, ServerState, TlsConfig, VideosDir},
 };
 
+mod api_tokens;
 mod app_env_vars;
+mod auth_providers;
 mod csrf_protection;
 pub mod db;
+#[cfg(feature = "dev-login")]
+mod dev_login;
 pub mod error;
+mod github;
 pub mod handlers;
+mod jwt_keys;
 mod oidc;
 mod server_state;
 
@@ -50,22 +57,12 @@ async fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
 
     let app_env_vars = app_env_vars::AppEnvVars::load_from_env()?;
+    let crowd_config = CrowdConfig::load_from_env(&app_env_vars)?;
+    let server_config = ServerConfig::load_from_env(&app_env_vars)?;
 
-    let google_client_id = openidconnect::ClientId::new(app_env_vars.google_oidc_client_id);
-    let google_client_secret =
-        openidconnect::ClientSecret::new(app_env_vars.google_oidc_client_secret);
-    let google_issuer_url =
-        openidconnect::IssuerUrl::new("https://accounts.google.com".to_string())
-            .context("Unable to create issue url")?;
-    let redirect_url = openidconnect::RedirectUrl::new(
-        "https://dev.listen.pwnies.dk:3000/auth/callback".to_string(),
-    )?;
-    let google_oidc_client = oidc::OidcClient::new(
-        google_issuer_url,
-        google_client_id,
-        google_client_secret,
-        redirect_url,
-    );
+    let auth_providers = auth_providers::AuthProviders::load(&server_config)?;
+    auth_providers.discover().await?;
+    tokio::task::spawn(auth_providers.clone().refresh_periodically());
 
     info!(
         "Connecting to database {database_url}",
@@ -94,11 +91,7 @@ async fn main() -> anyhow::Result<()> {
 
     let conf = get_configuration(None).unwrap();
     let leptos_options = conf.leptos_options;
-    let addr: SocketAddr = "0.0.0.0:3000".parse().unwrap();
-    let jwt_encoding_key =
-        jsonwebtoken::EncodingKey::from_secret(app_env_vars.jwt_secret.as_bytes());
-    let jwt_decoding_key =
-        jsonwebtoken::DecodingKey::from_secret(app_env_vars.jwt_secret.as_bytes());
+    let jwt_keys = jwt_keys::JwtKeys::load_from_env(&app_env_vars.jwt_secret)?;
     let state = ServerState {
         pool,
         leptos_options,
@@ -107,13 +100,20 @@ async fn main() -> anyhow::Result<()> {
                 .canonicalize()
                 .unwrap(),
         ),
-        jwt_encoding_key,
-        jwt_decoding_key,
-        google_oidc_client,
+        jwt_keys,
+        auth_providers,
         crowd_map: Default::default(),
+        join_codes: Default::default(),
+        crowd_list_changes: Arc::new(tokio::sync::watch::Sender::new(())),
+        crowd_config,
+        server_config,
+        crowd_metrics: Default::default(),
     };
 
-    info!("listening on {}", addr);
+    info!(
+        "listening on {}, public url {}",
+        state.server_config.bind_address, state.server_config.public_url
+    );
     info!("video dir: {}", state.videos_dir.display());
 
     {
@@ -128,34 +128,40 @@ async fn main() -> anyhow::Result<()> {
         });
     }
 
+    {
+        let pool = state.pool.clone();
+        tokio::task::spawn(async move {
+            loop {
+                if let Err(e) = handlers::auth::purge_expired_sessions(&pool).await {
+                    tracing::error!("Error purging expired sessions: {e:?}");
+                }
+                tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+            }
+        });
+    }
+
     let app = routes(state.clone());
 
-    if cfg!(feature = "local-https") {
-        serve_https(addr, app).await.unwrap();
-    } else {
-        serve_http(addr, app).await.unwrap();
+    let addr = state.server_config.bind_address;
+    match &state.server_config.tls {
+        Some(tls) => serve_https(addr, tls, app).await.unwrap(),
+        None => serve_http(addr, app).await.unwrap(),
     }
 
     Ok(())
 }
 
-async fn serve_https(addr: SocketAddr, app: Router<()>) -> anyhow::Result<()> {
+async fn serve_https(addr: SocketAddr, tls: &TlsConfig, app: Router<()>) -> anyhow::Result<()> {
     let app = app.into_make_service_with_connect_info::<SocketAddr>();
 
     rustls::crypto::aws_lc_rs::default_provider()
         .install_default()
         .expect("Could not install default crypto provider for rustls");
-    let config = RustlsConfig::from_pem_file(
-        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("dev-certificates")
-            .join("dev.listen.pwnies.dk.crt"),
-        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-            .join("dev-certificates")
-            .join("dev.listen.pwnies.dk.key"),
-    )
-    .await.context(
-        "Could not create rustls config. Did you run the script to generate the self-signed certificates"
-    )?;
+    let config = RustlsConfig::from_pem_file(&tls.cert_file, &tls.key_file)
+        .await
+        .context(
+            "Could not create rustls config. Did you run the script to generate the self-signed certificates"
+        )?;
 
     let handle = axum_server::Handle::new();
     tokio::spawn({
@@ -210,33 +216,122 @@ fn routes(state: ServerState) -> Router {
 }
 
 fn api_routes(state: ServerState) -> Router<ServerState> {
-    let csrf_layer = map_request(csrf_protection::csrf_layer);
+    let csrf_layer = map_request_with_state(state.clone(), csrf_protection::csrf_layer);
     let auth_required_layer =
         map_request_with_state(state.clone(), handlers::auth::auth_required_layer);
-    let user_session_layer = map_request_with_state(state, handlers::auth::user_session_layer);
+    let user_session_layer = from_fn_with_state(state, handlers::auth::user_session_layer);
+
+    // Also accepts API tokens with the scope, in place of the browser session
+    let api_token_auth_layer = |scope: api::ApiTokenScope| {
+        map_request(
+            move |session_state: axum::Extension<SessionState>, request: Request| {
+                handlers::auth::api_token_auth_layer(scope, session_state, request)
+            },
+        )
+    };
 
     // Routes will full protection: CSRF + authentication required
     let api_routes = Router::new()
-        .route("/videos", get(handlers::videos::list_videos))
-        .route("/videos/:id", get(handlers::videos::get_video))
-        .route("/downloads", get(handlers::download::list_downloads))
+        .route("/users/profile", get(handlers::user::get_profile))
+        .route("/users/sessions", get(handlers::user::list_sessions))
         .route(
-            "/downloads/add",
-            post(handlers::download::add_video_to_queue),
+            "/users/sessions/:id/revoke",
+            post(handlers::user::revoke_session),
+        )
+        .route("/users/api-tokens", get(handlers::user::list_api_tokens))
+        .route(
+            "/users/api-tokens/create",
+            post(handlers::user::create_api_token),
+        )
+        .route(
+            "/users/api-tokens/:id/revoke",
+            post(handlers::user::revoke_api_token),
+        )
+        .route("/users/identities", get(handlers::user::list_identities))
+        .route(
+            "/users/identities/link",
+            post(handlers::user::link_identity),
+        )
+        .route(
+            "/users/identities/:id/unlink",
+            post(handlers::user::unlink_identity),
+        )
+        .route(
+            "/playlists/from-crowd/:crowd_id",
+            post(handlers::playlists::create_playlist_from_crowd),
         )
-        .route("/users/profile", get(handlers::user::get_profile))
         .route_layer(csrf_layer.clone())
         .layer(auth_required_layer.clone());
 
+    // Routes for admins only, on top of the full protection
+    let admin_routes = Router::new()
+        .route("/admin/users", get(handlers::admin::list_users))
+        .route(
+            "/admin/users/:id/update",
+            post(handlers::admin::update_user),
+        )
+        .route(
+            "/admin/users/:id/delete",
+            post(handlers::admin::delete_user),
+        )
+        .route("/admin/crowd/metrics", get(crowd::metrics))
+        .route_layer(map_request(handlers::admin::admin_required_layer))
+        .route_layer(csrf_layer.clone())
+        .layer(auth_required_layer);
+
+    // Routes that scripts can use with an API token. Requests with a token
+    // are exempt from the CSRF checks.
+    let api_token_routes = Router::new()
+        .route(
+            "/videos",
+            get(handlers::videos::list_videos)
+                .route_layer(api_token_auth_layer(api::ApiTokenScope::VideosRead)),
+        )
+        .route(
+            "/videos/:id",
+            get(handlers::videos::get_video)
+                .route_layer(api_token_auth_layer(api::ApiTokenScope::VideosRead)),
+        )
+        .route(
+            "/downloads",
+            get(handlers::download::list_downloads)
+                .route_layer(api_token_auth_layer(api::ApiTokenScope::DownloadsRead)),
+        )
+        .route(
+            "/downloads/add",
+            post(handlers::download::add_video_to_queue)
+                .route_layer(api_token_auth_layer(api::ApiTokenScope::DownloadsWrite)),
+        )
+        .route(
+            "/playlists",
+            get(handlers::playlists::list_playlists)
+                .route_layer(api_token_auth_layer(api::ApiTokenScope::PlaylistsRead)),
+        )
+        .route(
+            "/playlists/:id",
+            get(handlers::playlists::get_playlist)
+                .route_layer(api_token_auth_layer(api::ApiTokenScope::PlaylistsRead)),
+        )
+        .route_layer(csrf_layer.clone());
+
     // Routes for simple get requires issued by the browser, e.g. through a <source> tag.
     // These should get authentication protection, but not csrf protection.
-    let non_csrf_api_routes = Router::new()
-        .route("/videos/:id/play", get(handlers::videos::play_video))
-        .route_layer(auth_required_layer);
+    let non_csrf_api_routes = Router::new().route(
+        "/videos/:id/play",
+        get(handlers::videos::play_video)
+            .route_layer(api_token_auth_layer(api::ApiTokenScope::VideosRead)),
+    );
+
+    // Login page of the dev login provider, visited by the browser like the
+    // page of a real provider
+    #[cfg(feature = "dev-login")]
+    let non_csrf_api_routes =
+        non_csrf_api_routes.route("/auth/dev/authorize", get(dev_login::authorize));
 
     // Routes we want to access without authentication. They still need csrf protection
     let unauthenticated_routes = Router::new()
         .route("/auth/logout", post(handlers::auth::auth_logout))
+        .route("/auth/providers", get(handlers::auth::list_auth_providers))
         .route("/auth/auth-url", post(handlers::auth::auth_url))
         .route("/auth/auth-verify", post(handlers::auth::auth_verify))
         .route(
@@ -250,10 +345,12 @@ fn api_routes(state: ServerState) -> Router<ServerState> {
         .layer(csrf_layer);
 
     api_routes
+        .merge(api_token_routes)
+        .merge(admin_routes)
         .merge(non_csrf_api_routes)
         .merge(unauthenticated_routes)
-        .layer(user_session_layer)
         .nest("/crowd", crowd::routes())
+        .layer(user_session_layer)
 }
 
 async fn shutdown_signal() {
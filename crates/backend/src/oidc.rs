@@ -1,16 +1,32 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
 use oauth2::{
     reqwest, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet,
     EndpointNotSet, EndpointSet, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope,
 };
 use openidconnect::{
     core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata},
-    EndUserEmail, EndUserPictureUrl, EndUserUsername, IssuerUrl, Nonce, SubjectIdentifier,
-    TokenResponse,
+    ClaimsVerificationError, EndUserEmail, EndUserPictureUrl, EndUserUsername, IssuerUrl, Nonce,
+    SignatureVerificationError, SubjectIdentifier, TokenResponse,
 };
 use url::Url;
 
 use crate::error::Result;
 
+/// Discovered metadata, including the signing keys, is fetched again after
+/// this long.
+const METADATA_TTL: Duration = Duration::from_secs(60 * 60);
+/// How often metadata is refreshed in the background, so logins don't wait for
+/// discovery.
+pub const METADATA_REFRESH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+/// An id token signed with an unknown key triggers a refresh, to pick up
+/// rotated keys. This limits how often that can happen.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Clone)]
 pub struct OidcClient {
     issuer_url: IssuerUrl,
@@ -18,6 +34,12 @@ pub struct OidcClient {
     client_secret: ClientSecret,
     redirect_url: RedirectUrl,
     reqwest_client: reqwest::Client,
+    metadata: Arc<RwLock<Option<CachedMetadata>>>,
+}
+
+struct CachedMetadata {
+    metadata: CoreProviderMetadata,
+    fetched_at: Instant,
 }
 
 #[derive(Debug)]
@@ -51,20 +73,96 @@ impl OidcClient {
             client_secret,
             redirect_url,
             reqwest_client: reqwest::Client::new(),
+            metadata: Default::default(),
         }
     }
 
-    // TODO: Do caching?
+    pub fn issuer_url(&self) -> &IssuerUrl {
+        &self.issuer_url
+    }
+
+    /// Fetches the provider metadata and signing keys and replaces the cached
+    /// ones.
+    pub async fn refresh_metadata(&self) -> anyhow::Result<CoreProviderMetadata> {
+        let metadata =
+            CoreProviderMetadata::discover_async(self.issuer_url.clone(), &self.reqwest_client)
+                .await
+                .map_err(|e| {
+                    anyhow!(
+                        "Error doing metadata discovery for {}: {e:?}",
+                        self.issuer_url.as_str()
+                    )
+                })?;
+        *self.metadata.write().unwrap() = Some(CachedMetadata {
+            metadata: metadata.clone(),
+            fetched_at: Instant::now(),
+        });
+        Ok(metadata)
+    }
+
     async fn get_provider_metadata(&self) -> Result<CoreProviderMetadata> {
-        CoreProviderMetadata::discover_async(self.issuer_url.clone(), &self.reqwest_client)
-            .await
-            .map_err(|e| {
-                tracing::error!(
-                    "Error doing metadata discovery for {}: {e:?}",
-                    self.issuer_url.as_str()
-                );
-                api::ApiError::InternalServerError.into()
-            })
+        let stale = {
+            let cached = self.metadata.read().unwrap();
+            match &*cached {
+                Some(cached) if cached.fetched_at.elapsed() < METADATA_TTL => {
+                    return Ok(cached.metadata.clone());
+                }
+                Some(cached) => Some(cached.metadata.clone()),
+                None => None,
+            }
+        };
+        match (self.refresh_metadata().await, stale) {
+            (Ok(metadata), _) => Ok(metadata),
+            // Keep logins working while the provider is unreachable
+            (Err(e), Some(stale)) => {
+                tracing::warn!("Using stale metadata: {e:?}");
+                Ok(stale)
+            }
+            (Err(e), None) => {
+                tracing::error!("{e:?}");
+                Err(api::ApiError::InternalServerError.into())
+            }
+        }
+    }
+
+    /// Refreshes the metadata after an id token was signed with an unknown
+    /// key. Returns false if it was refreshed too recently to try again.
+    async fn refresh_for_unknown_key(&self) -> bool {
+        let recently_refreshed = self
+            .metadata
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|cached| cached.fetched_at.elapsed() < MIN_REFRESH_INTERVAL);
+        if recently_refreshed {
+            return false;
+        }
+        match self.refresh_metadata().await {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::error!("{e:?}");
+                false
+            }
+        }
+    }
+
+    fn client_from_metadata(
+        &self,
+        metadata: CoreProviderMetadata,
+    ) -> CoreClient<
+        EndpointSet,
+        EndpointNotSet,
+        EndpointNotSet,
+        EndpointNotSet,
+        EndpointMaybeSet,
+        EndpointMaybeSet,
+    > {
+        CoreClient::from_provider_metadata(
+            metadata,
+            self.client_id.clone(),
+            Some(self.client_secret.clone()),
+        )
+        .set_redirect_uri(self.redirect_url.clone())
     }
 
     async fn get_client(
@@ -79,13 +177,7 @@ impl OidcClient {
             EndpointMaybeSet,
         >,
     > {
-        let client = CoreClient::from_provider_metadata(
-            self.get_provider_metadata().await?,
-            self.client_id.clone(),
-            Some(self.client_secret.clone()),
-        )
-        .set_redirect_uri(self.redirect_url.clone());
-        Ok(client)
+        Ok(self.client_from_metadata(self.get_provider_metadata().await?))
     }
     pub async fn auth_url(&self) -> Result<AuthUrl> {
         let client = self.get_client().await?;
@@ -156,12 +248,22 @@ impl OidcClient {
         })?;
 
         // The claims are actual contents inside the jwt
-        let claims = id_token
-            .claims(&client.id_token_verifier(), &nonce)
-            .map_err(|e| {
-                tracing::error!("Invalid OIDC claims: {e:?}");
-                api::ApiError::InternalServerError
-            })?;
+        let verifier = client.id_token_verifier();
+        let claims = match id_token.claims(&verifier, &nonce) {
+            Err(ClaimsVerificationError::SignatureVerification(
+                SignatureVerificationError::NoMatchingKey,
+            )) if self.refresh_for_unknown_key().await => {
+                // The provider rotated its keys since they were cached
+                let client = self.client_from_metadata(self.get_provider_metadata().await?);
+                let verifier = client.id_token_verifier();
+                id_token.claims(&verifier, &nonce).cloned()
+            }
+            claims => claims.cloned(),
+        }
+        .map_err(|e| {
+            tracing::error!("Invalid OIDC claims: {e:?}");
+            api::ApiError::InternalServerError
+        })?;
 
         if claims.issuer() != &self.issuer_url {
             tracing::error!(
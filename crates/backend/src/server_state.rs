@@ -1,22 +1,219 @@
 use std::{
+    net::SocketAddr,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
+use anyhow::Context;
 use api::CrowdId;
 use axum::extract::FromRef;
 use diesel_async::{pooled_connection::deadpool::Pool, AsyncPgConnection};
 use leptos::config::LeptosOptions;
-use tokio::sync::{broadcast, mpsc};
+use subtle::ConstantTimeEq;
+use tokio::sync::{broadcast, mpsc, watch};
+use url::Url;
+
+use crate::app_env_vars::AppEnvVars;
 
 pub type CrowdMap = Arc<dashmap::DashMap<CrowdId, CrowdState>>;
+pub type JoinCodeMap = Arc<dashmap::DashMap<String, JoinCode>>;
+/// Notified whenever something shown in the crowd list changes.
+pub type CrowdListChanges = Arc<watch::Sender<()>>;
+
+/// Messages from participant connections to the player task.
+#[derive(Debug)]
+pub enum CrowdCommand {
+    Join(api::CrowdParticipant),
+    Leave(api::CrowdParticipantId),
+    Command(
+        time::UtcDateTime,
+        api::CrowdParticipantId,
+        api::CrowdParticipantCommand,
+    ),
+}
 
 pub struct CrowdState {
     pub crowd_id: CrowdId,
     pub started: time::UtcDateTime,
     pub name: String,
-    pub command_sender: mpsc::Sender<(time::UtcDateTime, api::CrowdParticipantCommand)>,
+    pub join_code: String,
+    pub password: Option<String>,
+    /// Private crowds are only listed for their owner.
+    pub private: bool,
+    pub owner_id: Option<api::UserId>,
+    pub owner: Option<String>,
+    pub command_sender: mpsc::Sender<CrowdCommand>,
     pub update_receiver: broadcast::Receiver<(time::UtcDateTime, api::CrowdPlayerUpdate)>,
+    /// Latest full state of the crowd, sent to participants when they join.
+    pub snapshot: watch::Receiver<api::CrowdState>,
+}
+
+impl CrowdState {
+    pub fn check_password(&self, password: Option<&str>) -> bool {
+        match (&self.password, password) {
+            // Constant time, so the password can't be guessed from response times
+            (Some(expected), Some(password)) => {
+                password.as_bytes().ct_eq(expected.as_bytes()).into()
+            }
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+}
+
+/// Channel capacities for crowd connections.
+#[derive(Clone, Copy, Debug)]
+pub struct CrowdConfig {
+    /// Commands from participants waiting to be handled by the player task.
+    pub command_channel_size: usize,
+    /// Updates a participant can fall behind before it has to resync from a snapshot.
+    pub update_channel_size: usize,
+}
+
+impl CrowdConfig {
+    pub fn load_from_env(env_vars: &AppEnvVars) -> anyhow::Result<Self> {
+        fn channel_size(var: &str, size: Option<&str>, default: usize) -> anyhow::Result<usize> {
+            match size {
+                Some(size) => {
+                    let size = size
+                        .parse()
+                        .with_context(|| format!("Invalid {var}: {size}"))?;
+                    anyhow::ensure!(size > 0, "{var} must be larger than 0");
+                    Ok(size)
+                }
+                None => Ok(default),
+            }
+        }
+        Ok(Self {
+            command_channel_size: channel_size(
+                "CROWD_COMMAND_CHANNEL_SIZE",
+                env_vars.crowd_command_channel_size.as_deref(),
+                50,
+            )?,
+            update_channel_size: channel_size(
+                "CROWD_UPDATE_CHANNEL_SIZE",
+                env_vars.crowd_update_channel_size.as_deref(),
+                50,
+            )?,
+        })
+    }
+}
+
+/// Where the server listens and the address users reach it on, which can
+/// differ when running behind a reverse proxy.
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Base URL of the site as seen by browsers, e.g. `https://listen.example.com`.
+    pub public_url: Url,
+    pub bind_address: SocketAddr,
+    /// Serve https directly. Leave unset when TLS is terminated by a proxy.
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+}
+
+impl ServerConfig {
+    pub fn load_from_env(env_vars: &AppEnvVars) -> anyhow::Result<Self> {
+        let public_url = env_vars
+            .public_url
+            .as_deref()
+            .unwrap_or("https://dev.listen.pwnies.dk:3000");
+        let mut public_url =
+            Url::parse(public_url).with_context(|| format!("Invalid PUBLIC_URL: {public_url}"))?;
+        anyhow::ensure!(
+            matches!(public_url.scheme(), "http" | "https") && public_url.host().is_some(),
+            "PUBLIC_URL must be a http or https URL"
+        );
+        // Paths are joined onto the base URL
+        if !public_url.path().ends_with('/') {
+            public_url.set_path(&format!("{}/", public_url.path()));
+        }
+
+        let bind_address = env_vars.bind_address.as_deref().unwrap_or("0.0.0.0:3000");
+        let bind_address = bind_address
+            .parse()
+            .with_context(|| format!("Invalid BIND_ADDRESS: {bind_address}"))?;
+
+        let tls = match (&env_vars.tls_cert_file, &env_vars.tls_key_file) {
+            (Some(cert_file), Some(key_file)) => Some(TlsConfig {
+                cert_file: cert_file.into(),
+                key_file: key_file.into(),
+            }),
+            (None, None) if cfg!(feature = "local-https") => {
+                let certificates =
+                    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("dev-certificates");
+                Some(TlsConfig {
+                    cert_file: certificates.join("dev.listen.pwnies.dk.crt"),
+                    key_file: certificates.join("dev.listen.pwnies.dk.key"),
+                })
+            }
+            (None, None) => None,
+            _ => anyhow::bail!("TLS_CERT_FILE and TLS_KEY_FILE must be set together"),
+        };
+
+        Ok(Self {
+            public_url,
+            bind_address,
+            tls,
+        })
+    }
+
+    /// Absolute URL for a path on the site.
+    pub fn url(&self, path: &str) -> Url {
+        self.public_url
+            .join(path.trim_start_matches('/'))
+            .expect("path joined onto the public url")
+    }
+
+    /// Cookies can only be marked secure when the site is reached over https.
+    pub fn secure_cookies(&self) -> bool {
+        self.public_url.scheme() == "https"
+    }
+}
+
+#[derive(Default)]
+pub struct CrowdMetrics {
+    lagged_participants: AtomicU64,
+    skipped_updates: AtomicU64,
+    resync_snapshots: AtomicU64,
+}
+
+impl CrowdMetrics {
+    pub fn record_lag(&self, skipped: u64) {
+        self.lagged_participants.fetch_add(1, Ordering::Relaxed);
+        self.skipped_updates.fetch_add(skipped, Ordering::Relaxed);
+    }
+
+    pub fn record_resync(&self) {
+        self.resync_snapshots.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> api::CrowdMetrics {
+        api::CrowdMetrics {
+            lagged_participants: self.lagged_participants.load(Ordering::Relaxed),
+            skipped_updates: self.skipped_updates.load(Ordering::Relaxed),
+            resync_snapshots: self.resync_snapshots.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct JoinCode {
+    pub crowd_id: CrowdId,
+    pub expires: Option<time::UtcDateTime>,
+}
+
+impl JoinCode {
+    pub fn is_expired(&self) -> bool {
+        self.expires
+            .is_some_and(|expires| expires < time::UtcDateTime::now())
+    }
 }
 
 /// Derive FromRef to allow multiple items in state, using Axum’s
@@ -26,10 +223,14 @@ pub struct ServerState {
     pub leptos_options: LeptosOptions,
     pub pool: Pool<AsyncPgConnection>,
     pub videos_dir: VideosDir,
-    pub jwt_encoding_key: jsonwebtoken::EncodingKey,
-    pub jwt_decoding_key: jsonwebtoken::DecodingKey,
-    pub google_oidc_client: crate::oidc::OidcClient,
+    pub jwt_keys: crate::jwt_keys::JwtKeys,
+    pub auth_providers: crate::auth_providers::AuthProviders,
     pub crowd_map: CrowdMap,
+    pub join_codes: JoinCodeMap,
+    pub crowd_list_changes: CrowdListChanges,
+    pub crowd_config: CrowdConfig,
+    pub server_config: ServerConfig,
+    pub crowd_metrics: Arc<CrowdMetrics>,
 }
 
 #[derive(Clone, Debug)]
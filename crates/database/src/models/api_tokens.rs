@@ -0,0 +1,141 @@
+use api::{ApiTokenId, ApiTokenScope, UserId};
+use diesel::{delete, dsl::now, insert_into, prelude::*, update, QueryDsl, Selectable};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use time::OffsetDateTime;
+
+use super::{Result, User};
+
+#[derive(Clone, Debug, PartialEq, Queryable, Selectable, Identifiable, Associations)]
+#[diesel(primary_key(api_token_id))]
+#[diesel(table_name = crate::schema::api_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(User))]
+pub struct ApiToken {
+    pub api_token_id: ApiTokenId,
+    pub user_id: UserId,
+    pub name: String,
+    pub token_hash: String,
+    /// Names of the granted [`ApiTokenScope`]s.
+    pub scopes: Vec<String>,
+    pub last_used_at: Option<OffsetDateTime>,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::api_tokens)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[derive(Clone, Debug)]
+struct NewApiToken<'a> {
+    pub user_id: UserId,
+    pub name: &'a str,
+    pub token_hash: &'a str,
+    pub scopes: Vec<String>,
+}
+
+impl ApiToken {
+    pub fn has_scope(&self, scope: ApiTokenScope) -> bool {
+        self.scopes.contains(&scope.to_string())
+    }
+
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        user_id: UserId,
+        name: &str,
+        token_hash: &str,
+        scopes: &[ApiTokenScope],
+    ) -> Result<Self> {
+        use crate::schema::api_tokens::dsl as t;
+
+        let result = insert_into(t::api_tokens)
+            .values(NewApiToken {
+                user_id,
+                name,
+                token_hash,
+                scopes: scopes.iter().map(ToString::to_string).collect(),
+            })
+            .get_result(conn)
+            .await?;
+
+        Ok(result)
+    }
+
+    pub async fn get_by_hash(
+        conn: &mut AsyncPgConnection,
+        token_hash: &str,
+    ) -> Result<Option<(Self, User)>> {
+        use crate::schema::{api_tokens::dsl as t, users::dsl as u};
+
+        let result = t::api_tokens
+            .inner_join(u::users)
+            .filter(t::token_hash.eq(token_hash))
+            .select((Self::as_select(), User::as_select()))
+            .first(conn)
+            .await
+            .optional()?;
+
+        Ok(result)
+    }
+
+    pub async fn list_by_user_id(
+        conn: &mut AsyncPgConnection,
+        user_id: UserId,
+    ) -> Result<Vec<Self>> {
+        use crate::schema::api_tokens::dsl as t;
+
+        let results = t::api_tokens
+            .filter(t::user_id.eq(user_id))
+            .order(t::created_at.desc())
+            .get_results(conn)
+            .await?;
+
+        Ok(results)
+    }
+
+    pub async fn touch(conn: &mut AsyncPgConnection, api_token_id: ApiTokenId) -> Result<()> {
+        use crate::schema::api_tokens::dsl as t;
+
+        update(t::api_tokens)
+            .filter(t::api_token_id.eq(api_token_id))
+            .set(t::last_used_at.eq(now))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Deletes a token of the user. Returns false if there was none.
+    pub async fn delete_for_user(
+        conn: &mut AsyncPgConnection,
+        user_id: UserId,
+        api_token_id: ApiTokenId,
+    ) -> Result<bool> {
+        use crate::schema::api_tokens::dsl as t;
+
+        let deleted = delete(t::api_tokens)
+            .filter(t::api_token_id.eq(api_token_id))
+            .filter(t::user_id.eq(user_id))
+            .execute(conn)
+            .await?;
+
+        Ok(deleted > 0)
+    }
+}
+
+impl From<ApiToken> for api::ApiToken {
+    fn from(api_token: ApiToken) -> Self {
+        Self {
+            api_token_id: api_token.api_token_id,
+            name: api_token.name,
+            // Scopes that no longer exist are left out
+            scopes: api_token
+                .scopes
+                .iter()
+                .filter_map(|scope| scope.parse().ok())
+                .collect(),
+            created_at: api_token.created_at,
+            last_used_at: api_token.last_used_at,
+        }
+    }
+}
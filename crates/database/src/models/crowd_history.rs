@@ -0,0 +1,118 @@
+use api::{CrowdHistoryEntryId, CrowdId, UserId, VideoId};
+use diesel::{
+    dsl::{insert_into, now},
+    prelude::*,
+    update,
+};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use structural_convert::StructuralConvert;
+use time::OffsetDateTime;
+
+use super::{Crowd, Result};
+
+#[derive(
+    Clone, Debug, PartialEq, Queryable, Selectable, Identifiable, Associations, StructuralConvert,
+)]
+#[diesel(primary_key(crowd_history_id))]
+#[diesel(table_name = crate::schema::crowd_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Crowd))]
+#[convert(into(api::CrowdHistoryEntry))]
+pub struct CrowdHistoryEntry {
+    pub crowd_history_id: CrowdHistoryEntryId,
+    pub crowd_id: CrowdId,
+    /// `None` for YouTube videos that were not downloaded.
+    pub video_id: Option<VideoId>,
+    /// Set for videos played from YouTube.
+    pub youtube_id: Option<String>,
+    /// User who queued the video, `None` for guests, autofill and deleted users.
+    pub added_by: Option<UserId>,
+    /// Handle of who queued the video when it played, `None` for autofill.
+    pub added_by_handle: Option<String>,
+    pub started_at: OffsetDateTime,
+    pub ended_at: Option<OffsetDateTime>,
+    pub skipped: bool,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Clone, Debug, PartialEq, Insertable)]
+#[diesel(table_name = crate::schema::crowd_history)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewCrowdHistoryEntry<'a> {
+    pub crowd_id: CrowdId,
+    pub video_id: Option<VideoId>,
+    pub youtube_id: Option<&'a str>,
+    pub added_by: Option<UserId>,
+    pub added_by_handle: Option<&'a str>,
+}
+
+impl CrowdHistoryEntry {
+    /// Records that a library video or a YouTube video started playing in the
+    /// crowd.
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        crowd_id: CrowdId,
+        video: &api::CrowdVideo,
+        added_by: Option<UserId>,
+        added_by_handle: Option<&str>,
+    ) -> Result<Self> {
+        use crate::schema::crowd_history::dsl as h;
+
+        let result = insert_into(h::crowd_history)
+            .values(NewCrowdHistoryEntry {
+                crowd_id,
+                video_id: match video {
+                    api::CrowdVideo::Local(video_id) => Some(*video_id),
+                    api::CrowdVideo::Youtube(_) => None,
+                },
+                youtube_id: match video {
+                    api::CrowdVideo::Local(_) => None,
+                    api::CrowdVideo::Youtube(youtube_id) => Some(youtube_id),
+                },
+                added_by,
+                added_by_handle,
+            })
+            .get_result(conn)
+            .await?;
+
+        Ok(result)
+    }
+
+    pub async fn update_ended(
+        conn: &mut AsyncPgConnection,
+        crowd_history_id: CrowdHistoryEntryId,
+        skipped: bool,
+    ) -> Result<()> {
+        use crate::schema::crowd_history::dsl as h;
+
+        update(h::crowd_history)
+            .filter(h::crowd_history_id.eq(crowd_history_id))
+            .set((
+                h::updated_at.eq(now),
+                h::ended_at.eq(now),
+                h::skipped.eq(skipped),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Played entries of a crowd, oldest first.
+    pub async fn list_by_crowd_id(
+        conn: &mut AsyncPgConnection,
+        crowd_id: CrowdId,
+    ) -> Result<Vec<Self>> {
+        use crate::schema::crowd_history::dsl as h;
+
+        let results = h::crowd_history
+            .filter(h::crowd_id.eq(crowd_id))
+            .order(h::started_at.asc())
+            .get_results(conn)
+            .await?;
+
+        Ok(results)
+    }
+}
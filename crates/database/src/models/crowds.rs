@@ -0,0 +1,102 @@
+use api::{CrowdId, UserId};
+use diesel::{
+    dsl::{insert_into, now},
+    prelude::*,
+    update,
+};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use structural_convert::StructuralConvert;
+use time::OffsetDateTime;
+
+use super::Result;
+
+#[derive(Clone, Debug, PartialEq, Queryable, Selectable, Identifiable, StructuralConvert)]
+#[diesel(primary_key(crowd_id))]
+#[diesel(table_name = crate::schema::crowds)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[convert(into(api::Crowd))]
+pub struct Crowd {
+    pub crowd_id: CrowdId,
+    pub name: String,
+    pub owner_id: Option<UserId>,
+    pub started_at: OffsetDateTime,
+    pub ended_at: Option<OffsetDateTime>,
+    /// Only the owner can see the history of private crowds.
+    pub private: bool,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Clone, Debug, PartialEq, Insertable)]
+#[diesel(table_name = crate::schema::crowds)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewCrowd<'a> {
+    pub crowd_id: CrowdId,
+    pub name: &'a str,
+    pub owner_id: Option<UserId>,
+    pub private: bool,
+}
+
+impl Crowd {
+    /// Whether `user_id` may see the crowd's history.
+    pub fn is_visible_to(&self, user_id: UserId) -> bool {
+        !self.private || self.owner_id == Some(user_id)
+    }
+
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        crowd_id: CrowdId,
+        name: &str,
+        owner_id: Option<UserId>,
+        private: bool,
+    ) -> Result<Self> {
+        use crate::schema::crowds::dsl as c;
+
+        let result = insert_into(c::crowds)
+            .values(NewCrowd {
+                crowd_id,
+                name,
+                owner_id,
+                private,
+            })
+            .get_result(conn)
+            .await?;
+
+        Ok(result)
+    }
+
+    pub async fn get_by_id(
+        conn: &mut AsyncPgConnection,
+        crowd_id: CrowdId,
+    ) -> Result<Option<Self>> {
+        use crate::schema::crowds::dsl as c;
+        let result = c::crowds.find(crowd_id).first(conn).await.optional()?;
+        Ok(result)
+    }
+
+    /// Sets the start to now, for crowds that waited for a scheduled start.
+    pub async fn update_started(conn: &mut AsyncPgConnection, crowd_id: CrowdId) -> Result<()> {
+        use crate::schema::crowds::dsl as c;
+
+        update(c::crowds)
+            .filter(c::crowd_id.eq(crowd_id))
+            .set((c::updated_at.eq(now), c::started_at.eq(now)))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_ended(conn: &mut AsyncPgConnection, crowd_id: CrowdId) -> Result<()> {
+        use crate::schema::crowds::dsl as c;
+
+        update(c::crowds)
+            .filter(c::crowd_id.eq(crowd_id))
+            .set((c::updated_at.eq(now), c::ended_at.eq(now)))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,12 +1,20 @@
+mod api_tokens;
+mod crowd_history;
+mod crowds;
 mod downloads;
 mod oidc_mapping;
+mod playlists;
 mod user;
 mod user_session;
 mod videos;
 
+pub use api_tokens::ApiToken;
+pub use crowd_history::CrowdHistoryEntry;
+pub use crowds::Crowd;
 pub use downloads::{Download, DownloadStatus};
-pub use oidc_mapping::OidcMapping;
-pub use user::User;
+pub use oidc_mapping::{OidcMapping, Unlink};
+pub use playlists::Playlist;
+pub use user::{AdminChange, User};
 pub use user_session::UserSession;
 pub use videos::Video;
 
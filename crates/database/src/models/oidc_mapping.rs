@@ -1,6 +1,8 @@
 use api::{OidcMappingId, UserId};
 use diesel::{delete, insert_into, prelude::*, QueryDsl};
-use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
+};
 use openidconnect::{IssuerUrl, SubjectIdentifier};
 use structural_convert::StructuralConvert;
 use time::OffsetDateTime;
@@ -25,6 +27,15 @@ pub struct OidcMapping {
     pub updated_at: OffsetDateTime,
 }
 
+/// Result of [`OidcMapping::unlink`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unlink {
+    Unlinked,
+    NotFound,
+    /// Users always keep at least one identity, so they can still log in.
+    LastIdentity,
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::oidc_mapping)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -83,6 +94,47 @@ impl OidcMapping {
         Ok(results)
     }
 
+    /// Deletes a mapping of the user, unless it is their only one.
+    pub async fn unlink(
+        conn: &mut AsyncPgConnection,
+        user_id: UserId,
+        oidc_mapping_id: OidcMappingId,
+    ) -> Result<Unlink> {
+        conn.transaction(|conn| {
+            async move {
+                use crate::schema::{oidc_mapping::dsl as m, users::dsl as u};
+
+                // Lock the user so concurrent unlinks can't remove every mapping
+                u::users
+                    .find(user_id)
+                    .select(u::user_id)
+                    .for_update()
+                    .first::<UserId>(conn)
+                    .await?;
+
+                let mappings = Self::list_by_user_id(conn, user_id).await?;
+                if !mappings
+                    .iter()
+                    .any(|mapping| mapping.oidc_mapping_id == oidc_mapping_id)
+                {
+                    return Ok(Unlink::NotFound);
+                }
+                if mappings.len() == 1 {
+                    return Ok(Unlink::LastIdentity);
+                }
+
+                delete(m::oidc_mapping)
+                    .filter(m::oidc_mapping_id.eq(oidc_mapping_id))
+                    .execute(conn)
+                    .await?;
+
+                Ok(Unlink::Unlinked)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
     pub async fn delete(
         conn: &mut AsyncPgConnection,
         oidc_mapping_id: OidcMappingId,
@@ -0,0 +1,124 @@
+use api::{PlaylistId, UserId, VideoId};
+use diesel::{dsl::insert_into, prelude::*};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
+};
+use structural_convert::StructuralConvert;
+use time::OffsetDateTime;
+
+use super::Result;
+
+#[derive(Clone, Debug, PartialEq, Queryable, Selectable, Identifiable, StructuralConvert)]
+#[diesel(primary_key(playlist_id))]
+#[diesel(table_name = crate::schema::playlists)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[convert(into(api::Playlist))]
+pub struct Playlist {
+    pub playlist_id: PlaylistId,
+    pub name: String,
+    pub user_id: UserId,
+
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Clone, Debug, PartialEq, Insertable)]
+#[diesel(table_name = crate::schema::playlists)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewPlaylist<'a> {
+    pub name: &'a str,
+    pub user_id: UserId,
+}
+
+#[derive(Clone, Debug, PartialEq, Insertable)]
+#[diesel(table_name = crate::schema::playlist_entries)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+struct NewPlaylistEntry {
+    pub playlist_id: PlaylistId,
+    pub position: i32,
+    pub video_id: VideoId,
+}
+
+impl Playlist {
+    /// Creates a playlist with the videos in the given order.
+    pub async fn create(
+        conn: &mut AsyncPgConnection,
+        user_id: UserId,
+        name: &str,
+        video_ids: &[VideoId],
+    ) -> Result<Self> {
+        conn.transaction(|conn| {
+            async move {
+                use crate::schema::{playlist_entries::dsl as e, playlists::dsl as p};
+
+                let playlist: Self = insert_into(p::playlists)
+                    .values(NewPlaylist { name, user_id })
+                    .get_result(conn)
+                    .await?;
+
+                let entries: Vec<_> = video_ids
+                    .iter()
+                    .zip(0..)
+                    .map(|(&video_id, position)| NewPlaylistEntry {
+                        playlist_id: playlist.playlist_id,
+                        position,
+                        video_id,
+                    })
+                    .collect();
+                insert_into(e::playlist_entries)
+                    .values(&entries)
+                    .execute(conn)
+                    .await?;
+
+                Ok(playlist)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn get_by_id(
+        conn: &mut AsyncPgConnection,
+        playlist_id: PlaylistId,
+    ) -> Result<Option<Self>> {
+        use crate::schema::playlists::dsl as p;
+        let result = p::playlists
+            .find(playlist_id)
+            .first(conn)
+            .await
+            .optional()?;
+        Ok(result)
+    }
+
+    pub async fn list_by_user_id(
+        conn: &mut AsyncPgConnection,
+        user_id: UserId,
+    ) -> Result<Vec<Self>> {
+        use crate::schema::playlists::dsl as p;
+
+        let results = p::playlists
+            .filter(p::user_id.eq(user_id))
+            .order(p::created_at.desc())
+            .get_results(conn)
+            .await?;
+
+        Ok(results)
+    }
+
+    /// Videos of the playlist in order.
+    pub async fn video_ids(
+        conn: &mut AsyncPgConnection,
+        playlist_id: PlaylistId,
+    ) -> Result<Vec<VideoId>> {
+        use crate::schema::playlist_entries::dsl as e;
+
+        let results = e::playlist_entries
+            .filter(e::playlist_id.eq(playlist_id))
+            .order(e::position.asc())
+            .select(e::video_id)
+            .get_results(conn)
+            .await?;
+
+        Ok(results)
+    }
+}
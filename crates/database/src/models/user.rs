@@ -30,6 +30,15 @@ pub struct User {
     pub updated_at: OffsetDateTime,
 }
 
+/// Result of [`User::update_by_admin`] and [`User::delete_by_admin`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdminChange {
+    Changed,
+    NotFound,
+    /// There always has to be an approved admin left to manage users.
+    LastAdmin,
+}
+
 #[derive(Insertable)]
 #[diesel(table_name = crate::schema::users)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -225,6 +234,104 @@ impl User {
         Ok(())
     }
 
+    /// Approves, rejects, promotes or demotes a user, unless that would leave
+    /// no approved admin.
+    pub async fn update_by_admin(
+        conn: &mut AsyncPgConnection,
+        user_id: UserId,
+        is_approved: Option<bool>,
+        is_admin: Option<bool>,
+    ) -> Result<AdminChange> {
+        conn.transaction(|conn| {
+            async move {
+                let Some(user) = Self::lock_for_admin_change(conn, user_id).await? else {
+                    return Ok(AdminChange::NotFound);
+                };
+                let stays_admin =
+                    is_approved.unwrap_or(user.is_approved) && is_admin.unwrap_or(user.is_admin);
+                if !stays_admin && Self::is_last_admin(conn, &user).await? {
+                    return Ok(AdminChange::LastAdmin);
+                }
+
+                if let Some(is_approved) = is_approved {
+                    Self::update_is_approved(conn, user_id, is_approved).await?;
+                }
+                if let Some(is_admin) = is_admin {
+                    Self::update_is_admin(conn, user_id, is_admin).await?;
+                }
+
+                Ok(AdminChange::Changed)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Deletes a user, unless they are the last approved admin.
+    pub async fn delete_by_admin(
+        conn: &mut AsyncPgConnection,
+        user_id: UserId,
+    ) -> Result<AdminChange> {
+        conn.transaction(|conn| {
+            async move {
+                let Some(user) = Self::lock_for_admin_change(conn, user_id).await? else {
+                    return Ok(AdminChange::NotFound);
+                };
+                if Self::is_last_admin(conn, &user).await? {
+                    return Ok(AdminChange::LastAdmin);
+                }
+
+                Self::delete(conn, user_id).await?;
+
+                Ok(AdminChange::Changed)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Locks the approved admins and the user, so concurrent changes can't
+    /// remove every admin.
+    async fn lock_for_admin_change(
+        conn: &mut AsyncPgConnection,
+        user_id: UserId,
+    ) -> Result<Option<Self>> {
+        use crate::schema::users::dsl as u;
+
+        u::users
+            .filter(u::is_admin.eq(true))
+            .filter(u::is_approved.eq(true))
+            .select(u::user_id)
+            .for_update()
+            .load::<UserId>(conn)
+            .await?;
+
+        u::users
+            .find(user_id)
+            .for_update()
+            .first(conn)
+            .await
+            .optional()
+    }
+
+    /// Whether `user` is the only approved admin. Has to run after
+    /// [`Self::lock_for_admin_change`].
+    async fn is_last_admin(conn: &mut AsyncPgConnection, user: &Self) -> Result<bool> {
+        use crate::schema::users::dsl as u;
+
+        if !(user.is_admin && user.is_approved) {
+            return Ok(false);
+        }
+        let admins: i64 = u::users
+            .filter(u::is_admin.eq(true))
+            .filter(u::is_approved.eq(true))
+            .count()
+            .get_result(conn)
+            .await?;
+
+        Ok(admins <= 1)
+    }
+
     pub async fn update_last_activity(conn: &mut AsyncPgConnection, user_id: UserId) -> Result<()> {
         use crate::schema::users::dsl as u;
 
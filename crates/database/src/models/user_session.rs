@@ -29,6 +29,13 @@ pub struct UserSession {
 
     pub created_at: OffsetDateTime,
     pub updated_at: OffsetDateTime,
+    /// Configured auth provider the login was started with.
+    pub auth_provider_id: Option<String>,
+    /// Browser the session was started from, to tell sessions apart.
+    pub user_agent: Option<String>,
+    /// When the login state above was created. Logins have to be completed
+    /// shortly after.
+    pub login_started_at: Option<OffsetDateTime>,
 }
 
 #[derive(Insertable)]
@@ -36,28 +43,36 @@ pub struct UserSession {
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[derive(Clone, Debug)]
 struct NewUserSession<'a> {
+    pub auth_provider_id: &'a str,
     pub oidc_issuer_url: &'a str,
     pub csrf_token: &'a str,
     pub nonce: &'a str,
     pub pkce_code_verifier: &'a str,
+    pub user_agent: Option<&'a str>,
+    pub login_started_at: OffsetDateTime,
 }
 
 impl UserSession {
     pub async fn create(
         conn: &mut AsyncPgConnection,
+        auth_provider_id: &str,
         oidc_issuer_url: &IssuerUrl,
         csrf_token: &CsrfToken,
         nonce: &Nonce,
         pkce_code_verifier: &PkceCodeVerifier,
+        user_agent: Option<&str>,
     ) -> Result<Self> {
         use crate::schema::user_sessions::dsl as s;
 
         let result = insert_into(s::user_sessions)
             .values(NewUserSession {
+                auth_provider_id,
                 oidc_issuer_url,
                 csrf_token: csrf_token.secret(),
                 nonce: nonce.secret(),
                 pkce_code_verifier: pkce_code_verifier.secret(),
+                user_agent,
+                login_started_at: OffsetDateTime::now_utc(),
             })
             .get_result(conn)
             .await?;
@@ -78,20 +93,54 @@ impl UserSession {
         Ok(result)
     }
 
-    pub async fn list_by_user_id(
+    /// Logged in sessions of the user that were used since `active_since`.
+    /// Expired sessions stay in the table until they are purged.
+    pub async fn list_active_by_user_id(
         conn: &mut AsyncPgConnection,
         user_id: UserId,
+        active_since: OffsetDateTime,
     ) -> Result<Vec<Self>> {
         use crate::schema::user_sessions::dsl as s;
 
         let results = s::user_sessions
             .filter(s::user_id.eq(user_id))
+            .filter(s::updated_at.ge(active_since))
             .get_results(conn)
             .await?;
 
         Ok(results)
     }
 
+    /// Stores the login state on an already logged in session, to link
+    /// another identity to its user.
+    pub async fn start_link(
+        conn: &mut AsyncPgConnection,
+        user_session_id: UserSessionId,
+        auth_provider_id: &str,
+        oidc_issuer_url: &IssuerUrl,
+        csrf_token: &CsrfToken,
+        nonce: &Nonce,
+        pkce_code_verifier: &PkceCodeVerifier,
+    ) -> Result<()> {
+        use crate::schema::user_sessions::dsl as s;
+
+        update(s::user_sessions)
+            .filter(s::user_session_id.eq(user_session_id))
+            .set((
+                s::updated_at.eq(now),
+                s::auth_provider_id.eq(auth_provider_id),
+                s::oidc_issuer_url.eq(oidc_issuer_url.as_str()),
+                s::csrf_token.eq(csrf_token.secret()),
+                s::nonce.eq(nonce.secret()),
+                s::pkce_code_verifier.eq(pkce_code_verifier.secret()),
+                s::login_started_at.eq(now),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
     pub async fn update_after_completed_login(
         conn: &mut AsyncPgConnection,
         user_session_id: UserSessionId,
@@ -104,10 +153,12 @@ impl UserSession {
             .set((
                 s::updated_at.eq(now),
                 s::user_id.eq(user_id),
+                s::auth_provider_id.eq(None::<String>),
                 s::oidc_issuer_url.eq(None::<String>),
                 s::csrf_token.eq(None::<String>),
                 s::nonce.eq(None::<String>),
                 s::pkce_code_verifier.eq(None::<String>),
+                s::login_started_at.eq(None::<OffsetDateTime>),
             ))
             .execute(conn)
             .await?;
@@ -115,6 +166,99 @@ impl UserSession {
         Ok(())
     }
 
+    /// Clears the login state if it still has the given CSRF token. Only one
+    /// verification can consume it, so a replayed callback is rejected.
+    /// Returns false if the state was already consumed.
+    pub async fn consume_login_state(
+        conn: &mut AsyncPgConnection,
+        user_session_id: UserSessionId,
+        csrf_token: &str,
+    ) -> Result<bool> {
+        use crate::schema::user_sessions::dsl as s;
+
+        let updated = update(s::user_sessions)
+            .filter(s::user_session_id.eq(user_session_id))
+            .filter(s::csrf_token.eq(csrf_token))
+            .set((
+                s::updated_at.eq(now),
+                s::auth_provider_id.eq(None::<String>),
+                s::oidc_issuer_url.eq(None::<String>),
+                s::csrf_token.eq(None::<String>),
+                s::nonce.eq(None::<String>),
+                s::pkce_code_verifier.eq(None::<String>),
+                s::login_started_at.eq(None::<OffsetDateTime>),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    /// Marks the session as used now, which extends its expiry.
+    pub async fn touch(conn: &mut AsyncPgConnection, user_session_id: UserSessionId) -> Result<()> {
+        use crate::schema::user_sessions::dsl as s;
+
+        update(s::user_sessions)
+            .filter(s::user_session_id.eq(user_session_id))
+            .set(s::updated_at.eq(now))
+            .execute(conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Deletes a session of the user. Returns false if there was none.
+    pub async fn delete_for_user(
+        conn: &mut AsyncPgConnection,
+        user_id: UserId,
+        user_session_id: UserSessionId,
+    ) -> Result<bool> {
+        use crate::schema::user_sessions::dsl as s;
+
+        let deleted = delete(s::user_sessions)
+            .filter(s::user_session_id.eq(user_session_id))
+            .filter(s::user_id.eq(user_id))
+            .execute(conn)
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Deletes sessions unused since `idle_before` and logins that were
+    /// started before `pending_before` but never completed. Expired login
+    /// state of logged in sessions, from linking identities, is cleared.
+    pub async fn delete_expired(
+        conn: &mut AsyncPgConnection,
+        idle_before: OffsetDateTime,
+        pending_before: OffsetDateTime,
+    ) -> Result<usize> {
+        use crate::schema::user_sessions::dsl as s;
+
+        let deleted = delete(s::user_sessions)
+            .filter(
+                s::updated_at
+                    .lt(idle_before)
+                    .or(s::user_id.is_null().and(s::created_at.lt(pending_before))),
+            )
+            .execute(conn)
+            .await?;
+
+        update(s::user_sessions)
+            .filter(s::login_started_at.lt(pending_before))
+            .set((
+                s::auth_provider_id.eq(None::<String>),
+                s::oidc_issuer_url.eq(None::<String>),
+                s::csrf_token.eq(None::<String>),
+                s::nonce.eq(None::<String>),
+                s::pkce_code_verifier.eq(None::<String>),
+                s::login_started_at.eq(None::<OffsetDateTime>),
+            ))
+            .execute(conn)
+            .await?;
+
+        Ok(deleted)
+    }
+
     pub async fn delete(
         conn: &mut AsyncPgConnection,
         user_session_id: UserSessionId,
@@ -6,7 +6,7 @@ use diesel_async::{
 use structural_convert::StructuralConvert;
 use time::OffsetDateTime;
 
-use super::{Download, Result};
+use super::{Download, DownloadStatus, Result};
 
 #[derive(Clone, Debug, PartialEq, Queryable, Selectable, Identifiable, StructuralConvert)]
 #[diesel(primary_key(video_id))]
@@ -107,4 +107,48 @@ impl Video {
         let results = results?;
         Ok(results)
     }
+
+    /// Ids of all videos that have finished downloading.
+    pub async fn list_playable_ids(conn: &mut AsyncPgConnection) -> Result<Vec<VideoId>> {
+        use crate::schema::{downloads::dsl as d, videos::dsl as v};
+        let results = v::videos
+            .inner_join(d::downloads)
+            .filter(d::status.eq(DownloadStatus::Finished))
+            .select(v::video_id)
+            .distinct()
+            .get_results(conn)
+            .await?;
+        Ok(results)
+    }
+
+    /// The finished download of the given YouTube video, if there is one.
+    pub async fn get_playable_id_by_youtube_id(
+        conn: &mut AsyncPgConnection,
+        youtube_id: &str,
+    ) -> Result<Option<VideoId>> {
+        use crate::schema::{downloads::dsl as d, videos::dsl as v};
+        let result = v::videos
+            .inner_join(d::downloads)
+            .filter(v::youtube_id.eq(youtube_id))
+            .filter(d::status.eq(DownloadStatus::Finished))
+            .select(v::video_id)
+            .first(conn)
+            .await
+            .optional()?;
+        Ok(result)
+    }
+
+    /// Titles of the given videos. Unknown ids are left out.
+    pub async fn get_titles(
+        conn: &mut AsyncPgConnection,
+        video_ids: &[VideoId],
+    ) -> Result<Vec<(VideoId, String)>> {
+        use crate::schema::videos::dsl as v;
+        let results = v::videos
+            .filter(v::video_id.eq_any(video_ids))
+            .select((v::video_id, v::title))
+            .get_results(conn)
+            .await?;
+        Ok(results)
+    }
 }
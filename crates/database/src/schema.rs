@@ -6,6 +6,49 @@ pub mod sql_types {
     pub struct DownloadStatus;
 }
 
+diesel::table! {
+    api_tokens (api_token_id) {
+        api_token_id -> Uuid,
+        user_id -> Uuid,
+        name -> Text,
+        #[max_length = 64]
+        token_hash -> Varchar,
+        scopes -> Array<Text>,
+        last_used_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    crowd_history (crowd_history_id) {
+        crowd_history_id -> Uuid,
+        crowd_id -> Uuid,
+        video_id -> Nullable<Uuid>,
+        youtube_id -> Nullable<Text>,
+        added_by -> Nullable<Uuid>,
+        added_by_handle -> Nullable<Text>,
+        started_at -> Timestamptz,
+        ended_at -> Nullable<Timestamptz>,
+        skipped -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    crowds (crowd_id) {
+        crowd_id -> Uuid,
+        name -> Text,
+        owner_id -> Nullable<Uuid>,
+        started_at -> Timestamptz,
+        ended_at -> Nullable<Timestamptz>,
+        private -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::DownloadStatus;
@@ -35,6 +78,24 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    playlist_entries (playlist_id, position) {
+        playlist_id -> Uuid,
+        position -> Int4,
+        video_id -> Uuid,
+    }
+}
+
+diesel::table! {
+    playlists (playlist_id) {
+        playlist_id -> Uuid,
+        name -> Text,
+        user_id -> Uuid,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     user_sessions (user_session_id) {
         user_session_id -> Uuid,
@@ -49,6 +110,10 @@ diesel::table! {
         user_id -> Nullable<Uuid>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        #[max_length = 255]
+        auth_provider_id -> Nullable<Varchar>,
+        user_agent -> Nullable<Text>,
+        login_started_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -82,13 +147,26 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(api_tokens -> users (user_id));
+diesel::joinable!(crowd_history -> crowds (crowd_id));
+diesel::joinable!(crowd_history -> users (added_by));
+diesel::joinable!(crowd_history -> videos (video_id));
+diesel::joinable!(crowds -> users (owner_id));
 diesel::joinable!(downloads -> videos (video_id));
 diesel::joinable!(oidc_mapping -> users (user_id));
+diesel::joinable!(playlist_entries -> playlists (playlist_id));
+diesel::joinable!(playlist_entries -> videos (video_id));
+diesel::joinable!(playlists -> users (user_id));
 diesel::joinable!(user_sessions -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    crowd_history,
+    crowds,
     downloads,
     oidc_mapping,
+    playlist_entries,
+    playlists,
     user_sessions,
     users,
     videos,
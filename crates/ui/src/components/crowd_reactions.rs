@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use leptos::prelude::*;
+
+const REACTION_DURATION: std::time::Duration = std::time::Duration::from_secs(3);
+
+static NEXT_REACTION_KEY: AtomicU64 = AtomicU64::new(0);
+
+pub type Reactions = RwSignal<Vec<(u64, api::CrowdReaction)>>;
+
+/// Shows `reaction` on top of the overlay for a few seconds.
+pub fn push_reaction(reactions: Reactions, reaction: api::CrowdReaction) {
+    let key = NEXT_REACTION_KEY.fetch_add(1, Ordering::Relaxed);
+    reactions.update(|reactions| reactions.push((key, reaction)));
+    set_timeout(
+        move || reactions.update(|reactions| reactions.retain(|(k, _)| *k != key)),
+        REACTION_DURATION,
+    );
+}
+
+/// Overlay showing participant reactions, meant to be placed inside a `relative` container.
+#[component]
+pub fn CrowdReactions(reactions: Reactions) -> impl IntoView {
+    view! {
+        <div class="absolute inset-0 pointer-events-none overflow-hidden">
+            <For each=move || reactions.get() key=|(key, _)| *key let:reaction>
+                <span
+                    class="absolute bottom-8 text-4xl animate-bounce"
+                    style=format!("left: {}%", reaction.0 * 37 % 90)
+                >
+                    {reaction.1.emoji()}
+                </span>
+            </For>
+        </div>
+    }
+}
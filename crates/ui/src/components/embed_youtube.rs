@@ -1,18 +1,205 @@
+use std::cell::RefCell;
+
 use leptos::prelude::*;
+use leptos_use::use_interval_fn;
+use wasm_bindgen::{closure::Closure, prelude::wasm_bindgen, JsValue};
+use web_sys::js_sys::{Object, Reflect};
+
+use crate::contexts::video_player::use_video_player;
+
+const YOUTUBE_PLAYER_ID: &str = "youtube_player";
+const YOUTUBE_API_SCRIPT_ID: &str = "youtube_iframe_api";
+/// How often the playback position is read from the embedded player, in milliseconds.
+const POLL_INTERVAL: u64 = 250;
+
+#[wasm_bindgen]
+extern "C" {
+    /// Player from the YouTube IFrame API.
+    #[wasm_bindgen(js_namespace = YT, js_name = Player)]
+    pub type YoutubePlayer;
+
+    #[wasm_bindgen(constructor, js_namespace = YT, js_class = "Player")]
+    fn new(element_id: &str, options: &JsValue) -> YoutubePlayer;
+
+    #[wasm_bindgen(method, js_name = playVideo)]
+    pub fn play_video(this: &YoutubePlayer);
+
+    #[wasm_bindgen(method, js_name = pauseVideo)]
+    pub fn pause_video(this: &YoutubePlayer);
+
+    #[wasm_bindgen(method, js_name = seekTo)]
+    pub fn seek_to(this: &YoutubePlayer, seconds: f64, allow_seek_ahead: bool);
+
+    #[wasm_bindgen(method, js_name = setPlaybackRate)]
+    pub fn set_playback_rate(this: &YoutubePlayer, rate: f64);
+
+    #[wasm_bindgen(method, js_name = getPlaybackRate)]
+    fn get_playback_rate(this: &YoutubePlayer) -> f64;
+
+    #[wasm_bindgen(method, js_name = getCurrentTime)]
+    fn get_current_time(this: &YoutubePlayer) -> f64;
+
+    #[wasm_bindgen(method, js_name = getDuration)]
+    fn get_duration(this: &YoutubePlayer) -> f64;
+
+    #[wasm_bindgen(method)]
+    fn destroy(this: &YoutubePlayer);
+}
+
+/// States reported by `onStateChange`.
+const STATE_ENDED: f64 = 0.0;
+const STATE_PLAYING: f64 = 1.0;
+const STATE_PAUSED: f64 = 2.0;
+
+struct Embedded {
+    player: YoutubePlayer,
+    ready: bool,
+    _callbacks: [Closure<dyn Fn(JsValue)>; 2],
+}
+
+thread_local! {
+    /// The mounted embed. Only one can be shown at a time.
+    static EMBEDDED: RefCell<Option<Embedded>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with the embedded YouTube player once it is ready. Returns `None`
+/// if no embed is ready.
+pub fn with_youtube_player<R>(f: impl FnOnce(&YoutubePlayer) -> R) -> Option<R> {
+    EMBEDDED.with_borrow(|embedded| {
+        embedded
+            .as_ref()
+            .filter(|embedded| embedded.ready)
+            .map(|embedded| f(&embedded.player))
+    })
+}
 
+/// Plays a YouTube video through the IFrame API. Playback is controlled
+/// through the video player context, just like local videos.
 #[component]
-#[allow(dead_code)]
-pub fn EmbedYoutube(youtube_id: String) -> impl IntoView {
+pub fn EmbedYoutube(
+    youtube_id: String,
+    /// Start playing as soon as the embed is ready.
+    #[prop(optional)]
+    autoplay: bool,
+    /// Called when the video finished playing.
+    #[prop(optional)]
+    on_ended: Option<Callback<()>>,
+) -> impl IntoView {
+    let video_player = use_video_player();
+    let api_ready = RwSignal::new(false);
+
+    Effect::new(move |_| load_iframe_api(api_ready));
+
+    Effect::new(move |created: Option<bool>| {
+        if created == Some(true) || !api_ready.get() {
+            return created.unwrap_or_default();
+        }
+        let on_ready = Closure::<dyn Fn(JsValue)>::new(move |_| {
+            EMBEDDED.with_borrow_mut(|embedded| {
+                if let Some(embedded) = embedded {
+                    embedded.ready = true;
+                }
+            });
+        });
+        let on_state_change = Closure::<dyn Fn(JsValue)>::new(move |event: JsValue| {
+            let state = Reflect::get(&event, &"data".into())
+                .ok()
+                .and_then(|state| state.as_f64());
+            match state {
+                Some(STATE_PLAYING) => video_player.playing.set(true),
+                Some(STATE_PAUSED) => video_player.playing.set(false),
+                Some(STATE_ENDED) => {
+                    video_player.playing.set(false);
+                    if let Some(on_ended) = on_ended {
+                        // Ending may unmount the embed, which must not happen
+                        // from within its own callback
+                        set_timeout(move || on_ended.run(()), std::time::Duration::ZERO);
+                    }
+                }
+                _ => {}
+            }
+        });
+
+        let player_vars = Object::new();
+        set(&player_vars, "autoplay", &u8::from(autoplay).into());
+        set(&player_vars, "playsinline", &1.into());
+        let events = Object::new();
+        set(&events, "onReady", on_ready.as_ref());
+        set(&events, "onStateChange", on_state_change.as_ref());
+        let options = Object::new();
+        set(&options, "videoId", &youtube_id.as_str().into());
+        set(&options, "playerVars", &player_vars);
+        set(&options, "events", &events);
+
+        let player = YoutubePlayer::new(YOUTUBE_PLAYER_ID, &options);
+        EMBEDDED.set(Some(Embedded {
+            player,
+            ready: false,
+            _callbacks: [on_ready, on_state_change],
+        }));
+        true
+    });
+
+    let _ = use_interval_fn(
+        move || {
+            with_youtube_player(|player| {
+                video_player.current_time.set(player.get_current_time());
+                video_player.duration.set(player.get_duration());
+                video_player.speed.set(player.get_playback_rate());
+            });
+        },
+        POLL_INTERVAL,
+    );
+
+    on_cleanup(|| {
+        if let Some(embedded) = EMBEDDED.take() {
+            embedded.player.destroy();
+        }
+    });
+
+    // The API replaces the inner element with the iframe, the outer one keeps
+    // it inside the part of the page that is removed on unmount.
     view! {
-        <iframe
-            width="560"
-            height="315"
-            src=format!("https://www.youtube.com/embed/{youtube_id}")
-            title="YouTube video player"
-            // frameborder="0"
-            allow="accelerometer; autoplay; clipboard-write; encrypted-media; gyroscope; picture-in-picture; web-share"
-            referrerpolicy="strict-origin-when-cross-origin"
-            allowfullscreen
-        ></iframe>
+        <div class="flex flex-1 items-center justify-center w-full bg-black">
+            <div id=YOUTUBE_PLAYER_ID class="w-full aspect-video"></div>
+        </div>
     }
 }
+
+/// Adds the IFrame API script to the page once and sets `ready` when it is
+/// loaded.
+fn load_iframe_api(ready: RwSignal<bool>) {
+    let window = window();
+    let loaded = Reflect::get(&window, &"YT".into())
+        .ok()
+        .and_then(|yt| Reflect::get(&yt, &"Player".into()).ok())
+        .is_some_and(|player| player.is_function());
+    if loaded {
+        ready.set(true);
+        return;
+    }
+    // The API calls this global function when it finished loading
+    let on_api_ready = Closure::<dyn Fn()>::new(move || {
+        // The embed may be gone by now
+        let _ = ready.try_set(true);
+    });
+    set(&window, "onYouTubeIframeAPIReady", on_api_ready.as_ref());
+    on_api_ready.forget();
+
+    let document = document();
+    if document.get_element_by_id(YOUTUBE_API_SCRIPT_ID).is_some() {
+        return;
+    }
+    let Ok(script) = document.create_element("script") else {
+        return;
+    };
+    script.set_id(YOUTUBE_API_SCRIPT_ID);
+    let _ = script.set_attribute("src", "https://www.youtube.com/iframe_api");
+    if let Some(body) = document.body() {
+        let _ = body.append_child(&script);
+    }
+}
+
+fn set(target: &JsValue, key: &str, value: &JsValue) {
+    let _ = Reflect::set(target, &key.into(), value);
+}
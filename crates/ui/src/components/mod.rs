@@ -1,3 +1,4 @@
+pub mod crowd_reactions;
 pub mod embed_youtube;
 pub mod nav_bar;
 pub mod video_player;
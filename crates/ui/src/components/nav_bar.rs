@@ -30,12 +30,26 @@ pub fn NavBar() -> impl IntoView {
             <A href="/downloads">
                 <Icon icon=i::BsCloudArrowDown attr:title="Downloads" width=SIZE height=SIZE />
             </A>
-            <Transition fallback=move || view! {}>
+            <Transition fallback=move || ()>
                 <div class="mt-auto mb-4">
                     {move || match profile.get().map(|p| p.take()) {
                         Some(Ok(profile)) => {
                             view! {
                                 <>
+                                    {profile
+                                        .is_admin
+                                        .then(|| {
+                                            view! {
+                                                <A href="/admin">
+                                                    <Icon
+                                                        icon=i::LuShieldCheck
+                                                        attr:title="Admin"
+                                                        width=SIZE
+                                                        height=SIZE
+                                                    />
+                                                </A>
+                                            }
+                                        })}
                                     <A href="/settings">
                                         <img
                                             class="rounded-full w-12 h-12"
@@ -53,7 +67,7 @@ pub fn NavBar() -> impl IntoView {
                             }
                                 .into_any()
                         }
-                        _ => view! {}.into_any(),
+                        _ => ().into_any(),
                     }}
                 </div>
             </Transition>
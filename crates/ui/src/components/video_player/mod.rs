@@ -14,7 +14,12 @@ use crate::contexts::video_player::{
 };
 
 #[component]
-pub fn VideoPlayer(id: VideoId) -> impl IntoView {
+pub fn VideoPlayer(
+    id: VideoId,
+    /// Called when the video finished playing.
+    #[prop(optional)]
+    on_ended: Option<Callback<()>>,
+) -> impl IntoView {
     let video_player = use_video_player();
     video_player.update_source();
 
@@ -45,10 +50,16 @@ pub fn VideoPlayer(id: VideoId) -> impl IntoView {
                 }
                 on:ended=move |_| {
                     video_player.pause();
+                    if let Some(on_ended) = on_ended {
+                        on_ended.run(());
+                    }
                 }
                 on:timeupdate=move |_| {
                     video_player.update_time();
                 }
+                on:ratechange=move |_| {
+                    video_player.update_speed();
+                }
 
                 class="w-full max-h-[calc(100vh-2rem)]"
                 id=VIDEO_PLAYER_ID
@@ -37,6 +37,7 @@ impl Backend {
                 }
                 ApiError::CsrfFailure
                 | ApiError::NotFound
+                | ApiError::Conflict(_)
                 | ApiError::InternalServerError
                 | ApiError::Unknown(_) => (),
             }
@@ -102,8 +103,15 @@ impl Backend {
         self.get("/get-auth").await
     }
 
-    pub async fn auth_url(&self) -> BackendResult<api::AuthUrlResponse> {
-        self.post("/auth/auth-url").await
+    pub async fn auth_providers(&self) -> BackendResult<Vec<api::AuthProvider>> {
+        self.get("/auth/providers").await
+    }
+
+    pub async fn auth_url(
+        &self,
+        request: &api::AuthUrlRequest,
+    ) -> BackendResult<api::AuthUrlResponse> {
+        self.post_json("/auth/auth-url", request).await
     }
 
     pub async fn auth_verify(&self, request: &api::AuthVerificationRequest) -> BackendResult<bool> {
@@ -118,6 +126,64 @@ impl Backend {
         self.get("/users/profile").await
     }
 
+    pub async fn list_sessions(&self) -> BackendResult<Vec<api::SessionInfo>> {
+        self.get("/users/sessions").await
+    }
+
+    pub async fn revoke_session(&self, session: api::UserSessionId) -> BackendResult<()> {
+        self.post(&format!("/users/sessions/{session}/revoke"))
+            .await
+    }
+
+    pub async fn list_api_tokens(&self) -> BackendResult<Vec<api::ApiToken>> {
+        self.get("/users/api-tokens").await
+    }
+
+    pub async fn create_api_token(
+        &self,
+        request: &api::ApiTokenCreateRequest,
+    ) -> BackendResult<api::ApiTokenCreated> {
+        self.post_json("/users/api-tokens/create", request).await
+    }
+
+    pub async fn revoke_api_token(&self, api_token: api::ApiTokenId) -> BackendResult<()> {
+        self.post(&format!("/users/api-tokens/{api_token}/revoke"))
+            .await
+    }
+
+    pub async fn admin_list_users(&self) -> BackendResult<Vec<api::User>> {
+        self.get("/admin/users").await
+    }
+
+    pub async fn admin_update_user(
+        &self,
+        user: api::UserId,
+        request: &api::AdminUserUpdate,
+    ) -> BackendResult<api::User> {
+        self.post_json(&format!("/admin/users/{user}/update"), request)
+            .await
+    }
+
+    pub async fn admin_delete_user(&self, user: api::UserId) -> BackendResult<()> {
+        self.post(&format!("/admin/users/{user}/delete")).await
+    }
+
+    pub async fn list_identities(&self) -> BackendResult<Vec<api::LinkedIdentity>> {
+        self.get("/users/identities").await
+    }
+
+    pub async fn link_identity(
+        &self,
+        request: &api::AuthUrlRequest,
+    ) -> BackendResult<api::AuthUrlResponse> {
+        self.post_json("/users/identities/link", request).await
+    }
+
+    pub async fn unlink_identity(&self, identity: api::OidcMappingId) -> BackendResult<()> {
+        self.post(&format!("/users/identities/{identity}/unlink"))
+            .await
+    }
+
     pub async fn get_unauthorized(&self) -> BackendResult<()> {
         self.get("/auth/test-unauthorized").await
     }
@@ -129,6 +195,34 @@ impl Backend {
     pub async fn get_crowd_list(&self) -> BackendResult<Vec<api::CrowdListEntry>> {
         self.get("/crowd/list").await
     }
+
+    pub async fn get_crowd_join_info(&self, join_code: &str) -> BackendResult<api::CrowdJoinInfo> {
+        self.get(&format!("/crowd/join/{join_code}")).await
+    }
+
+    pub async fn get_crowd_history(&self, crowd: api::CrowdId) -> BackendResult<api::CrowdHistory> {
+        self.get(&format!("/crowd/history/{crowd}")).await
+    }
+
+    pub async fn list_playlists(&self) -> BackendResult<Vec<api::Playlist>> {
+        self.get("/playlists").await
+    }
+
+    pub async fn get_playlist(
+        &self,
+        playlist: api::PlaylistId,
+    ) -> BackendResult<api::PlaylistWithVideos> {
+        self.get(&format!("/playlists/{playlist}")).await
+    }
+
+    pub async fn create_playlist_from_crowd(
+        &self,
+        crowd: api::CrowdId,
+        request: &api::PlaylistFromCrowdRequest,
+    ) -> BackendResult<api::Playlist> {
+        self.post_json(&format!("/playlists/from-crowd/{crowd}"), request)
+            .await
+    }
 }
 
 pub fn provide_backend() {
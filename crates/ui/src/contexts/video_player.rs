@@ -3,7 +3,7 @@ use leptos::prelude::*;
 use serde::{Deserialize, Serialize};
 use web_sys::HtmlVideoElement;
 
-use crate::util::get_element_by_id;
+use crate::{components::embed_youtube::with_youtube_player, util::get_element_by_id};
 
 pub const VIDEO_STATE_KEY: &str = "video_state";
 pub const VIDEO_PLAYER_ID: &str = "video_player";
@@ -31,6 +31,7 @@ pub struct VideoPlayer {
     pub current_time: RwSignal<f64>,
     pub duration: RwSignal<f64>,
     pub playing: RwSignal<bool>,
+    pub speed: RwSignal<f64>,
     is_ready: RwSignal<bool>,
 }
 
@@ -64,6 +65,50 @@ impl VideoPlayer {
         self.duration.set(total_time);
     }
 
+    pub fn set_paused(&self, paused: bool) {
+        let youtube = with_youtube_player(|player| {
+            if paused {
+                player.pause_video();
+            } else {
+                player.play_video();
+            }
+        });
+        if youtube.is_some() {
+            self.playing.set(!paused);
+            return;
+        }
+        let Some(video) = get_element_by_id::<HtmlVideoElement>(VIDEO_PLAYER_ID) else {
+            return;
+        };
+
+        if paused {
+            pause(&video);
+        } else {
+            play(&video);
+        }
+
+        self.playing.set(!paused);
+    }
+
+    pub fn set_speed(&self, speed: f64) {
+        if with_youtube_player(|player| player.set_playback_rate(speed)).is_some() {
+            return;
+        }
+        let Some(video) = get_element_by_id::<HtmlVideoElement>(VIDEO_PLAYER_ID) else {
+            return;
+        };
+
+        video.set_playback_rate(speed);
+    }
+
+    pub fn update_speed(&self) {
+        let Some(video) = get_element_by_id::<HtmlVideoElement>(VIDEO_PLAYER_ID) else {
+            return;
+        };
+
+        self.speed.set(video.playback_rate());
+    }
+
     pub fn update_source(&self) {
         let Some(video) = get_element_by_id::<HtmlVideoElement>(VIDEO_PLAYER_ID) else {
             return;
@@ -73,12 +118,14 @@ impl VideoPlayer {
     }
 
     pub fn seek(&self, time: f64) {
+        let time = time.clamp(0.0, self.duration.get_untracked());
+        if with_youtube_player(|player| player.seek_to(time, true)).is_some() {
+            return;
+        }
         let Some(video) = get_element_by_id::<HtmlVideoElement>(VIDEO_PLAYER_ID) else {
             return;
         };
 
-        let time = time.clamp(0.0, self.duration.get_untracked());
-
         video.set_current_time(time);
     }
 
@@ -92,6 +139,10 @@ impl VideoPlayer {
     }
 
     pub fn toggle_playback(&self) {
+        if with_youtube_player(|_| ()).is_some() {
+            self.set_paused(self.playing.get());
+            return;
+        }
         let Some(video) = get_element_by_id::<HtmlVideoElement>(VIDEO_PLAYER_ID) else {
             return;
         };
@@ -99,9 +150,9 @@ impl VideoPlayer {
         let playing = self.playing.get();
 
         if playing {
-            video.pause().unwrap();
+            pause(&video);
         } else {
-            let _ = video.play().unwrap();
+            play(&video);
         }
 
         self.playing.set(!playing);
@@ -114,6 +165,24 @@ impl VideoPlayer {
     }
 }
 
+fn pause(video: &HtmlVideoElement) {
+    if let Err(e) = video.pause() {
+        log::error!("Could not pause video: {e:?}");
+    }
+}
+
+/// Browsers reject playback e.g. before the user interacted with the page.
+fn play(video: &HtmlVideoElement) {
+    match video.play() {
+        Ok(promise) => leptos::task::spawn_local(async move {
+            if let Err(e) = wasm_bindgen_futures::JsFuture::from(promise).await {
+                log::error!("Could not play video: {e:?}");
+            }
+        }),
+        Err(e) => log::error!("Could not play video: {e:?}"),
+    }
+}
+
 pub fn provide_video_player(video_player: VideoPlayer) {
     provide_context(video_player);
 }
@@ -28,8 +28,6 @@ pub fn VideoStoreProvider() -> impl IntoView {
     });
 
     provide_context(Store::new(VideoStore::default()));
-
-    view! {}
 }
 
 pub fn use_video_store() -> Store<VideoStore> {
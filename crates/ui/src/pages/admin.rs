@@ -0,0 +1,129 @@
+use leptos::prelude::*;
+
+use crate::{
+    contexts::backend::use_backend,
+    errors::{map_gloo_net_error, AppError},
+};
+
+/// Lets admins approve new users and manage existing ones.
+#[component]
+pub fn AdminPage() -> impl IntoView {
+    let backend = use_backend();
+    let (error, set_error) = signal::<Result<(), AppError>>(Ok(()));
+
+    let users = LocalResource::new(move || {
+        let backend = backend.clone();
+        async move { map_gloo_net_error(backend.admin_list_users().await) }
+    });
+
+    let update = move |user: api::UserId, request: api::AdminUserUpdate| {
+        let backend = use_backend();
+        leptos::task::spawn_local(async move {
+            match map_gloo_net_error(backend.admin_update_user(user, &request).await) {
+                Ok(_) => users.refetch(),
+                Err(e) => set_error(Err(e)),
+            }
+        });
+    };
+    let delete = move |user: api::UserId| {
+        let backend = use_backend();
+        leptos::task::spawn_local(async move {
+            match map_gloo_net_error(backend.admin_delete_user(user).await) {
+                Ok(()) => users.refetch(),
+                Err(e) => set_error(Err(e)),
+            }
+        });
+    };
+
+    let users_view = move |approved: bool| {
+        users.get().map(|users| {
+            users.take().map(|users| {
+                users
+                    .into_iter()
+                    .filter(|user| user.is_approved == approved)
+                    .map(|user| {
+                        let user_id = user.user_id;
+                        let is_admin = user.is_admin;
+                        let actions = if approved {
+                            view! {
+                                <button
+                                    class="text-blue-500"
+                                    on:click=move |_| {
+                                        update(
+                                            user_id,
+                                            api::AdminUserUpdate {
+                                                is_admin: Some(!is_admin),
+                                                ..Default::default()
+                                            },
+                                        )
+                                    }
+                                >
+                                    {if is_admin { "Remove admin" } else { "Make admin" }}
+                                </button>
+                                <button
+                                    class="text-red-500"
+                                    on:click=move |_| {
+                                        update(
+                                            user_id,
+                                            api::AdminUserUpdate {
+                                                is_approved: Some(false),
+                                                ..Default::default()
+                                            },
+                                        )
+                                    }
+                                >
+                                    "Revoke approval"
+                                </button>
+                                <button class="text-red-500" on:click=move |_| delete(user_id)>
+                                    "Delete"
+                                </button>
+                            }
+                            .into_any()
+                        } else {
+                            view! {
+                                <button
+                                    class="text-blue-500"
+                                    on:click=move |_| {
+                                        update(
+                                            user_id,
+                                            api::AdminUserUpdate {
+                                                is_approved: Some(true),
+                                                ..Default::default()
+                                            },
+                                        )
+                                    }
+                                >
+                                    "Approve"
+                                </button>
+                                // Rejected users can log in again, and are then pending again
+                                <button class="text-red-500" on:click=move |_| delete(user_id)>
+                                    "Reject"
+                                </button>
+                            }
+                            .into_any()
+                        };
+                        view! {
+                            <div class="flex flex-row gap-2 items-center">
+                                <span class="flex-1 truncate max-w-md">
+                                    {user.handle} " (" {user.email} ")"
+                                </span>
+                                {is_admin.then(|| view! { <span class="text-gray-500">"Admin"</span> })}
+                                {actions}
+                            </div>
+                        }
+                    })
+                    .collect_view()
+            })
+        })
+    };
+
+    view! {
+        <div class="flex flex-col flex-1 items-center gap-2 mt-4">
+            <p class="font-bold">"Pending users"</p>
+            <Transition fallback=move || ()>{move || users_view(false)}</Transition>
+            <p class="font-bold mt-4">"Users"</p>
+            <Transition fallback=move || ()>{move || users_view(true)}</Transition>
+            {move || error.get()}
+        </div>
+    }
+}
@@ -0,0 +1,104 @@
+use leptos::prelude::*;
+
+use crate::contexts::video_store::{use_video_store, VideoStoreStoreFields};
+
+/// Shows who applied which crowd command, newest first. Queue changes that can
+/// still be undone get an undo button.
+#[component]
+pub fn CrowdAuditLog(
+    #[prop(into)] audit_log: Signal<Vec<api::CrowdAuditEntry>>,
+    on_command: Callback<api::CrowdParticipantCommand>,
+) -> impl IntoView {
+    let video_store = use_video_store();
+    let video_title = move |video_id: api::VideoId| {
+        video_store
+            .videos()
+            .with(|videos| {
+                videos
+                    .iter()
+                    .find(|video| video.video_id == video_id)
+                    .map(|video| video.title.clone())
+            })
+            .unwrap_or_else(|| "a video".to_string())
+    };
+
+    let entries = move || {
+        audit_log
+            .get()
+            .into_iter()
+            .rev()
+            .map(|entry| {
+                let audit_entry_id = entry.audit_entry_id;
+                let description = match &entry.command {
+                    api::CrowdParticipantCommand::AddToQueue(video_id) => {
+                        format!("added {}", video_title(*video_id))
+                    }
+                    command => describe(command),
+                };
+                let undo = (entry.undoable && !entry.undone).then(|| {
+                    view! {
+                        <button on:click=move |_| {
+                            on_command.run(api::CrowdParticipantCommand::Undo(audit_entry_id))
+                        }>"Undo"</button>
+                    }
+                });
+                view! {
+                    <div class="flex gap-2 items-center">
+                        <span class="text-gray-500">
+                            {format!(
+                                "{:02}:{:02}:{:02}",
+                                entry.applied_at.hour(),
+                                entry.applied_at.minute(),
+                                entry.applied_at.second(),
+                            )}
+                        </span>
+                        <span class=("line-through", entry.undone) class="flex-1">
+                            {format!("{} {description}", entry.handle)}
+                        </span>
+                        {undo}
+                    </div>
+                }
+            })
+            .collect_view()
+    };
+
+    view! {
+        <div class="flex flex-col gap-1">
+            <p class="font-bold">"Activity"</p>
+            {entries}
+        </div>
+    }
+}
+
+fn describe(command: &api::CrowdParticipantCommand) -> String {
+    match command {
+        api::CrowdParticipantCommand::SetPlaybackPosition(position) => {
+            let position = *position as i64;
+            format!("jumped to {:02}:{:02}", position / 60, position % 60)
+        }
+        api::CrowdParticipantCommand::SetIsPaused(true) => "paused".to_string(),
+        api::CrowdParticipantCommand::SetIsPaused(false) => "resumed".to_string(),
+        api::CrowdParticipantCommand::SetSpeed(speed) => format!("set the speed to {speed}x"),
+        api::CrowdParticipantCommand::GoTo(_) => "switched to another entry".to_string(),
+        api::CrowdParticipantCommand::AddToQueue(_) => "added a video".to_string(),
+        api::CrowdParticipantCommand::AddYoutubeToQueue(youtube_id) => {
+            format!("added YouTube video {youtube_id}")
+        }
+        api::CrowdParticipantCommand::MoveInQueue { .. } => "moved an entry".to_string(),
+        api::CrowdParticipantCommand::DeleteFromQueue(_) => "deleted an entry".to_string(),
+        api::CrowdParticipantCommand::Vote { .. } => "voted on an entry".to_string(),
+        api::CrowdParticipantCommand::VoteSkip => "voted to skip".to_string(),
+        api::CrowdParticipantCommand::Kick(_) => "kicked a participant".to_string(),
+        api::CrowdParticipantCommand::Mute { muted: true, .. } => "muted a participant".to_string(),
+        api::CrowdParticipantCommand::Mute { muted: false, .. } => {
+            "unmuted a participant".to_string()
+        }
+        api::CrowdParticipantCommand::BlockGuests(true) => "blocked guests".to_string(),
+        api::CrowdParticipantCommand::BlockGuests(false) => "allowed guests".to_string(),
+        api::CrowdParticipantCommand::Undo(_) => "undid a change".to_string(),
+        api::CrowdParticipantCommand::Ping
+        | api::CrowdParticipantCommand::Chat(_)
+        | api::CrowdParticipantCommand::React(_)
+        | api::CrowdParticipantCommand::SetIdle(_) => "did something".to_string(),
+    }
+}
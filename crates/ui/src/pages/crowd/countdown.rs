@@ -0,0 +1,42 @@
+use leptos::prelude::*;
+use leptos_use::use_timestamp;
+
+/// Counts down to the scheduled start of a crowd. Renders nothing once the
+/// crowd is running.
+#[component]
+pub fn CrowdCountdown(
+    #[prop(into)] scheduled_start: Signal<Option<time::UtcDateTime>>,
+) -> impl IntoView {
+    let now = use_timestamp();
+
+    let remaining = move || {
+        let start = scheduled_start.get()?;
+        let start_millis = (start.unix_timestamp_nanos() / 1_000_000) as f64;
+        let seconds = ((start_millis - now.get()) / 1000.0).ceil().max(0.0) as i64;
+        Some(if seconds == 0 {
+            "Starting...".to_string()
+        } else {
+            format!(
+                "Starts in {}:{:02}:{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            )
+        })
+    };
+
+    move || remaining().map(|text| view! { <p class="font-bold">{text}</p> })
+}
+
+/// Parses the value of a `datetime-local` input, which is in the browser's
+/// time zone.
+pub fn parse_local_datetime(value: &str) -> Option<time::UtcDateTime> {
+    if value.is_empty() {
+        return None;
+    }
+    let millis = web_sys::js_sys::Date::new(&value.into()).get_time();
+    if millis.is_nan() {
+        return None;
+    }
+    time::UtcDateTime::from_unix_timestamp_nanos(millis as i128 * 1_000_000).ok()
+}
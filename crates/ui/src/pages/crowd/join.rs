@@ -0,0 +1,47 @@
+use leptos::prelude::*;
+use leptos_router::{components::A, hooks::use_params_map};
+
+use crate::contexts::backend::use_backend;
+
+#[component]
+pub fn CrowdJoinPage() -> impl IntoView {
+    let backend = use_backend();
+    let params = use_params_map();
+
+    let join_info = LocalResource::new(move || {
+        let backend = backend.clone();
+        let code = params.read().get("code").unwrap_or_default();
+        async move { backend.get_crowd_join_info(&code).await.unwrap() }
+    });
+
+    view! {
+        <div class="flex flex-col flex-1 justify-center items-center">
+            <Transition fallback=move || {
+                view! { <p>"Loading..."</p> }
+            }>
+                {move || match join_info.get().map(|i| i.take()) {
+                    Some(Ok(join_info)) => {
+                        view! {
+                            <p>"You are about to join " <b>{join_info.name}</b></p>
+                            <Show when=move || join_info.requires_password>
+                                <p>"This crowd is protected by a password."</p>
+                            </Show>
+                            <A href=format!("/crowd/participant/{}", join_info.crowd_id)>
+                                <button class="bg-blue-500 hover:bg-blue-700 text-white font-bold rounded py-2 px-4 mt-4">
+                                    "Join"
+                                </button>
+                            </A>
+                        }
+                            .into_any()
+                    }
+                    Some(Err(api::ApiError::NotFound)) => {
+                        view! { <p>"No crowd found for this code. It may have expired."</p> }
+                            .into_any()
+                    }
+                    Some(Err(e)) => view! { {format!("Error: {e:?}")} }.into_any(),
+                    None => view! { <p>"Loading..."</p> }.into_any(),
+                }}
+            </Transition>
+        </div>
+    }
+}
@@ -1,31 +1,60 @@
+use codee::string::JsonSerdeCodec;
 use leptos::prelude::*;
-use leptos::{component, server::LocalResource, view, IntoView};
-use leptos_use::use_interval_fn;
-
-use crate::contexts::backend::use_backend;
+use leptos::{component, view, IntoView};
+use leptos_router::components::A;
+use leptos_use::{use_event_source, UseEventSourceReturn};
 
 #[component]
 pub fn CrowdListPage() -> impl IntoView {
-    let backend = use_backend();
+    // The server pushes a new list whenever a crowd starts, ends or changes.
+    let UseEventSourceReturn { data, error, .. } =
+        use_event_source::<Vec<api::CrowdListEntry>, JsonSerdeCodec>("/api/crowd/list/stream");
 
-    let crowd_list = LocalResource::new(move || {
-        let backend = backend.clone();
-        async move { backend.get_crowd_list().await.unwrap() }
-    });
+    view! {
+        <div>
+            {move || match data.get() {
+                Some(list) => view! { <CrowdList list /> }.into_any(),
+                None => view! { <p>"Loading..."</p> }.into_any(),
+            }}
+            {move || {
+                error
+                    .with(Option::is_some)
+                    .then(|| view! { <p class="text-red-500">"Lost connection, reconnecting..."</p> })
+            }}
+        </div>
+    }
+}
 
-    use_interval_fn(move || crowd_list.refetch(), 2000);
+#[component]
+fn CrowdList(list: Vec<api::CrowdListEntry>) -> impl IntoView {
+    if list.is_empty() {
+        return view! { <p>"No crowds right now"</p> }.into_any();
+    }
+    let entries = list
+        .into_iter()
+        .map(|entry| view! { <CrowdListEntry entry /> })
+        .collect_view();
+    view! { <div class="flex flex-col gap-2">{entries}</div> }.into_any()
+}
 
+#[component]
+fn CrowdListEntry(entry: api::CrowdListEntry) -> impl IntoView {
     view! {
-        <Transition fallback=move || {
-            view! { <p>"Loading..."</p> }
-        }>
-            <div>
-                {move || match crowd_list.get().map(|l| l.take()) {
-                    Some(Ok(list)) => view! { {format!("{list:?}")} }.into_any(),
-                    Some(Err(e)) => view! { {format!("Error: {e:?}")} }.into_any(),
-                    _ => view! { <p>"Loading..."</p> }.into_any(),
-                }}
+        <A href=format!("/join/{}", entry.join_code)>
+            <div class="hover:bg-green-500">
+                <b>{entry.name}</b>
+                {format!(" ({} participants)", entry.participant_count)}
+                {entry.requires_password.then_some(" (password protected)")}
+                {entry.private.then_some(" (private)")}
+                {entry.scheduled_start.map(|_| " (not started yet)")}
+                <div class="text-gray-500">
+                    {entry.owner.map(|owner| format!("Started by {owner} · "))}
+                    {entry
+                        .current_video_title
+                        .map(|title| format!("Playing {title} · "))}
+                    {format!("{} in queue", entry.queue_length)}
+                </div>
             </div>
-        </Transition>
+        </A>
     }
 }
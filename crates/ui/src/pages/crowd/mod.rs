@@ -1,3 +1,26 @@
+mod audit;
+mod countdown;
+pub mod join;
 pub mod list;
 pub mod participant;
 pub mod player;
+mod presence;
+mod queue;
+
+/// First message on both crowd websockets.
+fn handshake() -> String {
+    serde_json::to_string(&api::CrowdHandshake {
+        protocol_version: api::CROWD_PROTOCOL_VERSION,
+        encoding: api::CrowdEncoding::Json,
+    })
+    .unwrap()
+}
+
+/// Parses the reply to [`handshake`]. Returns the reason if the server rejected the connection.
+fn handshake_result(message: &str) -> Result<(), String> {
+    match serde_json::from_str(message) {
+        Ok(api::CrowdHandshakeResponse::Accepted { .. }) => Ok(()),
+        Ok(api::CrowdHandshakeResponse::Rejected { reason, .. }) => Err(reason),
+        Err(e) => Err(format!("Unexpected handshake response: {e}")),
+    }
+}
@@ -1,12 +1,19 @@
 use codee::string::FromToStringCodec;
 use leptos::prelude::*;
-use leptos_router::hooks::{use_navigate, use_params_map};
+use leptos_router::hooks::use_params_map;
 use leptos_use::{
-    core::ConnectionReadyState, use_websocket, use_websocket_with_options, UseWebSocketOptions,
-    UseWebSocketReturn,
+    core::ConnectionReadyState, use_document_visibility, use_websocket_with_options,
+    UseWebSocketOptions, UseWebSocketReturn,
 };
 
-use crate::contexts::backend::use_backend;
+use super::{
+    audit::CrowdAuditLog, countdown::CrowdCountdown, handshake, handshake_result,
+    presence::CrowdPresence, queue::CrowdQueueList,
+};
+use crate::contexts::video_store::{use_video_store, VideoStoreStoreFields};
+
+const SPEEDS: [f64; 6] = [0.5, 0.75, 1.0, 1.25, 1.5, 2.0];
+const MAX_SEARCH_RESULTS: usize = 10;
 
 #[derive(Default)]
 struct Heartbeat;
@@ -18,12 +25,72 @@ impl std::fmt::Display for Heartbeat {
         write!(f, "{msg}")
     }
 }
+
+fn apply_update(state: &mut api::CrowdState, update: api::CrowdPlayerUpdate) {
+    match update {
+        api::CrowdPlayerUpdate::Ping
+        | api::CrowdPlayerUpdate::Ended(_)
+        | api::CrowdPlayerUpdate::Identity(_)
+        | api::CrowdPlayerUpdate::Kicked(_)
+        | api::CrowdPlayerUpdate::Moderate(_)
+        | api::CrowdPlayerUpdate::Error(_) => {}
+        api::CrowdPlayerUpdate::PlaybackPosition(position) => state.playback_time = position,
+        api::CrowdPlayerUpdate::Duration(duration) => state.duration = duration,
+        api::CrowdPlayerUpdate::IsPaused(is_paused) => state.is_paused = is_paused,
+        api::CrowdPlayerUpdate::Speed(speed) => state.speed = speed,
+        api::CrowdPlayerUpdate::Queue {
+            currently_playing,
+            queue,
+        } => {
+            state.currently_playing = currently_playing;
+            state.queue = queue;
+        }
+        api::CrowdPlayerUpdate::Settings(settings) => state.settings = settings,
+        api::CrowdPlayerUpdate::SkipVotes { votes, .. } => state.skip_votes = votes,
+        api::CrowdPlayerUpdate::State(new_state) => *state = new_state,
+        api::CrowdPlayerUpdate::Chat(message) => {
+            if state.chat.len() >= api::CROWD_CHAT_HISTORY_LENGTH {
+                state.chat.pop_front();
+            }
+            state.chat.push_back(message);
+        }
+        api::CrowdPlayerUpdate::Participants(participants) => state.participants = participants,
+        api::CrowdPlayerUpdate::Audit(entry) => {
+            if let api::CrowdParticipantCommand::Undo(undone) = entry.command {
+                if let Some(undone) = state
+                    .audit_log
+                    .iter_mut()
+                    .find(|entry| entry.audit_entry_id == undone)
+                {
+                    undone.undone = true;
+                }
+            }
+            if state.audit_log.len() >= api::CROWD_AUDIT_LOG_LENGTH {
+                state.audit_log.pop_front();
+            }
+            state.audit_log.push_back(entry);
+        }
+        api::CrowdPlayerUpdate::GuestsBlocked(blocked) => state.guests_blocked = blocked,
+        api::CrowdPlayerUpdate::Started(_) => {
+            state.scheduled_start = None;
+            state.playback_time = 0.0;
+            state.is_paused = false;
+        }
+    }
+}
+
 #[component]
 pub fn CrowdParticipantPage() -> impl IntoView {
     let crowd_id = use_params_map().get().get("crowd_id").unwrap();
-    let (message, set_message) = signal("".to_string());
+    let (password, set_password) = signal("".to_string());
+    let crowd_state = RwSignal::new(None::<api::CrowdState>);
+    let skip_votes_needed = RwSignal::new(None::<usize>);
+    let own_id = RwSignal::new(None::<api::CrowdParticipantId>);
 
+    let (handshake_sent, set_handshake_sent) = signal(false);
+    let handshake_accepted = RwSignal::new(false);
     let (initial_message_sent, set_initial_message_sent) = signal(false);
+    let error = RwSignal::new(None::<String>);
 
     let UseWebSocketReturn {
         ready_state,
@@ -34,8 +101,50 @@ pub fn CrowdParticipantPage() -> impl IntoView {
     } = use_websocket_with_options::<String, String, FromToStringCodec, Heartbeat, FromToStringCodec>(
         "/api/crowd/participant",
         UseWebSocketOptions::default()
-            .on_message(|message| {
-                log::info!("Got message: {message:?}");
+            .on_message(move |message: &String| {
+                if !handshake_accepted.get_untracked() {
+                    match handshake_result(message) {
+                        Ok(()) => handshake_accepted.set(true),
+                        Err(reason) => error.set(Some(reason)),
+                    }
+                    return;
+                }
+                let update = match serde_json::from_str::<api::CrowdPlayerUpdate>(message) {
+                    Ok(update) => update,
+                    Err(e) => {
+                        log::error!("Unexpected message {message:?}: {e:?}");
+                        return;
+                    }
+                };
+                if let api::CrowdPlayerUpdate::Error(reason) = &update {
+                    log::error!("Crowd error: {reason}");
+                    error.set(Some(reason.clone()));
+                }
+                match &update {
+                    api::CrowdPlayerUpdate::Identity(participant_id) => {
+                        own_id.set(Some(*participant_id))
+                    }
+                    api::CrowdPlayerUpdate::Kicked(participant_id)
+                        if own_id.get_untracked() == Some(*participant_id) =>
+                    {
+                        error.set(Some("You were removed from the crowd".to_string()));
+                        crowd_state.set(None);
+                        return;
+                    }
+                    _ => {}
+                }
+                if let api::CrowdPlayerUpdate::SkipVotes { needed, .. } = &update {
+                    skip_votes_needed.set(Some(*needed));
+                }
+                if let api::CrowdPlayerUpdate::State(state) = update {
+                    crowd_state.set(Some(state));
+                } else {
+                    crowd_state.update(|state| {
+                        if let Some(state) = state {
+                            apply_update(state, update);
+                        }
+                    });
+                }
             })
             .heartbeat(2000)
             .immediate(false),
@@ -43,54 +152,350 @@ pub fn CrowdParticipantPage() -> impl IntoView {
     let status = move || ready_state.get().to_string();
     let connected = move || ready_state.get() == ConnectionReadyState::Open;
 
-    let connect = move |_| {
+    let connect = Callback::new(move |_| {
+        set_handshake_sent.set(false);
+        handshake_accepted.set(false);
         set_initial_message_sent.set(false);
+        error.set(None);
+        crowd_state.set(None);
+        skip_votes_needed.set(None);
+        own_id.set(None);
         open();
-    };
-    let disconnect = move |_| close();
+    });
+    let disconnect = Callback::new(move |_| close());
 
     {
         let send = send.clone();
         Effect::new(move || {
-            if !initial_message_sent.get() && connected() {
-                send(&crowd_id);
+            if connected() && !handshake_sent.get() {
+                send(&handshake());
+                set_handshake_sent.set(true);
+            }
+        });
+    }
+
+    {
+        let send = send.clone();
+        Effect::new(move || {
+            if handshake_accepted.get() && !initial_message_sent.get() {
+                let crowd_id = match crowd_id.parse() {
+                    Ok(crowd_id) => crowd_id,
+                    Err(e) => {
+                        log::error!("Invalid crowd id {crowd_id}: {e:?}");
+                        return;
+                    }
+                };
+                let password = password.get_untracked();
+                let join_request = api::CrowdJoinRequest {
+                    crowd_id,
+                    password: (!password.is_empty()).then_some(password),
+                };
+                send(&serde_json::to_string(&join_request).unwrap());
                 set_initial_message_sent.set(true);
             }
         });
     }
 
-    let send_message = move |_| {
-        send(&message.get_untracked());
-    };
+    let send_command = Callback::new(move |command: api::CrowdParticipantCommand| {
+        send(&serde_json::to_string(&command).unwrap());
+    });
+
+    // Show the participant as idle while the page is in the background
+    let visibility = use_document_visibility();
+    Effect::new(move || {
+        let idle = visibility.get() == web_sys::VisibilityState::Hidden;
+        if own_id.get().is_some() {
+            send_command.run(api::CrowdParticipantCommand::SetIdle(idle));
+        }
+    });
 
     view! {
-        <div>
+        <div class="flex flex-col gap-2 p-2 w-full max-w-md mx-auto">
             <p>"Status:" {move || status()}</p>
-            <p>
-                <button on:click=send_message disabled=move || !connected()>
-                    "Send"
+            <p class="text-red-500">{move || error.get()}</p>
+            <Show
+                when=move || crowd_state.with(Option::is_some)
+                fallback=move || {
+                    view! {
+                        <p>
+                            "Password:"
+                            <input
+                                type="password"
+                                on:input:target=move |ev| {
+                                    set_password.set(ev.target().value());
+                                }
+                                prop:value=password
+                            />
+                        </p>
+                        <button
+                            class="bg-blue-500 hover:bg-blue-700 text-white font-bold rounded py-2 px-4 mt-4"
+                            on:click=move |_| connect.run(())
+                            disabled=connected
+                        >
+                            "Join"
+                        </button>
+                    }
+                }
+            >
+                <CrowdRemote crowd_state skip_votes_needed own_id send_command />
+                <button
+                    class="bg-blue-500 hover:bg-blue-700 text-white font-bold rounded py-2 px-4 mt-4"
+                    on:click=move |_| disconnect.run(())
+                    disabled=move || !connected()
+                >
+                    "Leave"
                 </button>
-            </p>
-            <p>
-                <button on:click=connect disabled=move || connected()>
-                    "Connect"
+            </Show>
+        </div>
+    }
+}
+
+#[component]
+fn CrowdRemote(
+    crowd_state: RwSignal<Option<api::CrowdState>>,
+    skip_votes_needed: RwSignal<Option<usize>>,
+    own_id: RwSignal<Option<api::CrowdParticipantId>>,
+    send_command: Callback<api::CrowdParticipantCommand>,
+) -> impl IntoView {
+    let state = move |f: fn(&api::CrowdState) -> f64| {
+        crowd_state.with(|state| state.as_ref().map(f).unwrap_or_default())
+    };
+    let playback_time = move || state(|state| state.playback_time);
+    let duration = move || state(|state| state.duration);
+    let speed = move || state(|state| state.speed);
+    let is_paused =
+        move || crowd_state.with(|state| state.as_ref().is_none_or(|state| state.is_paused));
+    let queue = Signal::derive(move || {
+        crowd_state.with(|state| {
+            state
+                .as_ref()
+                .map(|state| state.queue.clone())
+                .unwrap_or_default()
+        })
+    });
+    let currently_playing =
+        Signal::derive(move || crowd_state.with(|state| state.as_ref()?.currently_playing));
+    let queue_mode = Signal::derive(move || {
+        crowd_state.with(|state| {
+            state
+                .as_ref()
+                .map(|state| state.settings.queue_mode)
+                .unwrap_or_default()
+        })
+    });
+    let participants = Signal::derive(move || {
+        crowd_state.with(|state| {
+            state
+                .as_ref()
+                .map(|state| state.participants.clone())
+                .unwrap_or_default()
+        })
+    });
+    let audit_log = Signal::derive(move || {
+        crowd_state.with(|state| {
+            state
+                .as_ref()
+                .map(|state| state.audit_log.iter().cloned().collect())
+                .unwrap_or_default()
+        })
+    });
+    let guests_blocked = Signal::derive(move || {
+        crowd_state.with(|state| state.as_ref().is_some_and(|state| state.guests_blocked))
+    });
+    let scheduled_start =
+        Signal::derive(move || crowd_state.with(|state| state.as_ref()?.scheduled_start));
+    let skip_votes = move || {
+        let votes = crowd_state.with(|state| state.as_ref().map_or(0, |state| state.skip_votes));
+        match skip_votes_needed.get() {
+            Some(needed) => format!("Vote to skip ({votes}/{needed})"),
+            None => "Vote to skip".to_string(),
+        }
+    };
+
+    let format_time = |t: f64| {
+        let t = t as i64;
+        format!("{:02}:{:02}", t / 60, t % 60)
+    };
+
+    view! {
+        <CrowdCountdown scheduled_start />
+        <div class="flex gap-2 items-center">
+            <button
+                class="bg-blue-500 hover:bg-blue-700 text-white font-bold rounded py-2 px-4"
+                on:click=move |_| {
+                    send_command.run(api::CrowdParticipantCommand::SetIsPaused(!is_paused()))
+                }
+            >
+                {move || if is_paused() { "Play" } else { "Pause" }}
+            </button>
+            <span>{move || format!("{}/{}", format_time(playback_time()), format_time(duration()))}</span>
+        </div>
+        <input
+            type="range"
+            min="0"
+            step="1"
+            prop:max=duration
+            prop:value=playback_time
+            on:change:target=move |ev| {
+                if let Ok(position) = ev.target().value().parse() {
+                    send_command.run(api::CrowdParticipantCommand::SetPlaybackPosition(position));
+                }
+            }
+        />
+        <p>
+            "Speed:"
+            <select on:change:target=move |ev| {
+                if let Ok(speed) = ev.target().value().parse() {
+                    send_command.run(api::CrowdParticipantCommand::SetSpeed(speed));
+                }
+            }>
+                {SPEEDS
+                    .map(|s| {
+                        view! {
+                            <option value=s.to_string() selected=move || speed() == s>
+                                {format!("{s}x")}
+                            </option>
+                        }
+                    })}
+            </select>
+        </p>
+        <button
+            class="bg-blue-500 hover:bg-blue-700 text-white font-bold rounded py-2 px-4"
+            on:click=move |_| send_command.run(api::CrowdParticipantCommand::VoteSkip)
+        >
+            {skip_votes}
+        </button>
+        <CrowdQueueList queue currently_playing queue_mode on_command=send_command />
+        <VideoSearch send_command />
+        <CrowdChat crowd_state send_command />
+        <CrowdPresence participants guests_blocked own_id on_command=send_command />
+        <CrowdAuditLog audit_log on_command=send_command />
+    }
+}
+
+#[component]
+fn VideoSearch(send_command: Callback<api::CrowdParticipantCommand>) -> impl IntoView {
+    let video_store = use_video_store();
+    let (search, set_search) = signal("".to_string());
+
+    let results = move || {
+        let search = search.get().to_lowercase();
+        if search.is_empty() {
+            return Vec::new();
+        }
+        video_store
+            .videos()
+            .get()
+            .into_iter()
+            .filter(|video| video.title.to_lowercase().contains(&search))
+            .take(MAX_SEARCH_RESULTS)
+            .collect()
+    };
+
+    view! {
+        <div class="flex flex-col gap-1">
+            <input
+                type="search"
+                placeholder="Search videos or paste a YouTube link"
+                on:input:target=move |ev| {
+                    set_search.set(ev.target().value());
+                }
+                prop:value=search
+            />
+            <Show when=move || search.with(|search| search.contains("youtu"))>
+                <button
+                    class="text-left hover:bg-green-500"
+                    on:click=move |_| {
+                        send_command
+                            .run(
+                                api::CrowdParticipantCommand::AddYoutubeToQueue(search.get_untracked()),
+                            );
+                        set_search.set(String::new());
+                    }
+                >
+                    "Add YouTube video"
                 </button>
-            </p>
-            <p>
-                <button on:click=disconnect disabled=move || !connected()>
-                    "Disconnect"
+            </Show>
+            <For each=results key=|video| video.video_id let:video>
+                <button
+                    class="text-left hover:bg-green-500"
+                    on:click=move |_| {
+                        send_command.run(api::CrowdParticipantCommand::AddToQueue(video.video_id));
+                        set_search.set(String::new());
+                    }
+                >
+                    {video.title}
                 </button>
-            </p>
-            <p>
-                "Message:"
-                <input
-                    type="text"
-                    on:input:target=move |ev| {
-                        set_message.set(ev.target().value());
+            </For>
+        </div>
+    }
+}
+
+#[component]
+fn CrowdChat(
+    crowd_state: RwSignal<Option<api::CrowdState>>,
+    send_command: Callback<api::CrowdParticipantCommand>,
+) -> impl IntoView {
+    let (message, set_message) = signal("".to_string());
+
+    let messages = move || {
+        crowd_state.with(|state| {
+            state
+                .iter()
+                .flat_map(|state| state.chat.iter())
+                .filter_map(|message| {
+                    let api::CrowdChatContent::Message(text) = &message.content else {
+                        return None;
+                    };
+                    let handle = state
+                        .iter()
+                        .flat_map(|state| state.participants.iter())
+                        .find(|participant| participant.participant_id == message.participant_id)
+                        .map_or("Someone who left", |participant| {
+                            participant.handle.as_str()
+                        });
+                    Some(format!("{handle}: {text}"))
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let send_message = move || {
+        let text = message.get_untracked();
+        if !text.trim().is_empty() {
+            send_command.run(api::CrowdParticipantCommand::Chat(text));
+        }
+        set_message.set(String::new());
+    };
+
+    view! {
+        <div class="flex gap-2 text-2xl">
+            {api::CrowdReaction::ALL
+                .map(|reaction| {
+                    view! {
+                        <button on:click=move |_| {
+                            send_command.run(api::CrowdParticipantCommand::React(reaction))
+                        }>{reaction.emoji()}</button>
                     }
-                    prop:value=message
-                />
-            </p>
+                })}
+        </div>
+        <div class="flex flex-col">
+            {move || {
+                messages().into_iter().map(|text| view! { <p>{text}</p> }).collect_view()
+            }}
         </div>
+        <form on:submit=move |ev| {
+            ev.prevent_default();
+            send_message();
+        }>
+            <input
+                type="text"
+                placeholder="Say something"
+                on:input:target=move |ev| {
+                    set_message.set(ev.target().value());
+                }
+                prop:value=message
+            />
+        </form>
     }
 }
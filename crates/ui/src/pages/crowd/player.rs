@@ -4,27 +4,72 @@ use leptos_use::{
     core::ConnectionReadyState, use_websocket_with_options, UseWebSocketOptions, UseWebSocketReturn,
 };
 
+use super::{
+    countdown::{parse_local_datetime, CrowdCountdown},
+    handshake, handshake_result,
+    presence::CrowdPresence,
+    queue::CrowdQueueList,
+};
+use crate::{
+    components::{
+        crowd_reactions::{push_reaction, CrowdReactions, Reactions},
+        embed_youtube::EmbedYoutube,
+        video_player::VideoPlayer,
+    },
+    contexts::{
+        backend::use_backend,
+        video_player::{provide_video_player, VideoPlayer},
+    },
+};
+
+/// Only send the playback position when it moved at least this many seconds.
+const PLAYBACK_POSITION_UPDATE_INTERVAL: f64 = 1.0;
+
 #[derive(Default)]
 struct Heartbeat;
 
 // Simple example for usage with `FromToStringCodec`
 impl std::fmt::Display for Heartbeat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let msg = serde_json::to_string(&(
-            time::UtcDateTime::UNIX_EPOCH,
-            api::CrowdParticipantCommand::Ping,
-        ))
-        .unwrap();
+        let msg =
+            serde_json::to_string(&(time::UtcDateTime::UNIX_EPOCH, api::CrowdPlayerUpdate::Ping))
+                .unwrap();
         write!(f, "{msg}")
     }
 }
 
 #[component]
 pub fn CrowdPlayerPage() -> impl IntoView {
-    let (message, set_message) = signal("".to_string());
+    let video_player = VideoPlayer::default();
+    provide_video_player(video_player);
+
     let (name, set_name) = signal("".to_string());
+    let (password, set_password) = signal("".to_string());
+    let (private, set_private) = signal(false);
+    let (join_code_lifetime, set_join_code_lifetime) = signal("".to_string());
+    let (queue_mode, set_queue_mode) = signal(api::CrowdQueueMode::Manual);
+    let (max_entries, set_max_entries) = signal("".to_string());
+    let (repeat, set_repeat) = signal(api::CrowdRepeatMode::Off);
+    let (shuffle, set_shuffle) = signal(false);
+    let (autofill, set_autofill) = signal(api::CrowdAutofillMode::Off);
+    let (start_at, set_start_at) = signal("".to_string());
+    let (playlist_id, set_playlist_id) = signal(None::<api::PlaylistId>);
+    let scheduled_start = RwSignal::new(None::<time::UtcDateTime>);
+    let crowd = RwSignal::new(None::<api::CrowdCreated>);
+    let (show_reactions, set_show_reactions) = signal(true);
+    let reactions: Reactions = RwSignal::new(Vec::new());
+    let queue = RwSignal::new(api::CrowdQueue::default());
+    let currently_playing = RwSignal::new(None::<api::CrowdQueueId>);
+    let participants = RwSignal::new(Vec::<api::CrowdParticipant>::new());
+    let guests_blocked = RwSignal::new(false);
+    // Whether the next video should start playing as soon as it is loaded
+    let autoplay = StoredValue::new(false);
 
+    let (handshake_sent, set_handshake_sent) = signal(false);
+    let handshake_accepted = RwSignal::new(false);
     let (initial_message_sent, set_initial_message_sent) = signal(false);
+    let error = RwSignal::new(None::<String>);
+    let saved_playlist = RwSignal::new(None::<String>);
 
     let UseWebSocketReturn {
         ready_state,
@@ -35,8 +80,69 @@ pub fn CrowdPlayerPage() -> impl IntoView {
     } = use_websocket_with_options::<String, String, FromToStringCodec, Heartbeat, FromToStringCodec>(
         "/api/crowd/player",
         UseWebSocketOptions::default()
-            .on_message(|message| {
-                log::info!("Got message: {message:?}");
+            .on_message(move |message: &String| {
+                if !handshake_accepted.get_untracked() {
+                    match handshake_result(message) {
+                        Ok(()) => handshake_accepted.set(true),
+                        Err(reason) => error.set(Some(reason)),
+                    }
+                    return;
+                }
+                if let Ok(created) = serde_json::from_str::<api::CrowdCreated>(message) {
+                    crowd.set(Some(created));
+                    return;
+                }
+                let command =
+                    match serde_json::from_str::<(time::UtcDateTime, api::CrowdPlayerCommand)>(
+                        message,
+                    ) {
+                        Ok((_, command)) => command,
+                        Err(e) => {
+                            log::error!("Unexpected message {message:?}: {e:?}");
+                            return;
+                        }
+                    };
+                match command {
+                    api::CrowdPlayerCommand::Ping => {}
+                    api::CrowdPlayerCommand::Error(reason) => {
+                        log::error!("Crowd error: {reason}");
+                        error.set(Some(reason));
+                    }
+                    api::CrowdPlayerCommand::SetPlaybackPosition(position) => {
+                        video_player.seek(position)
+                    }
+                    api::CrowdPlayerCommand::SetIsPaused(is_paused) => {
+                        autoplay.set_value(!is_paused);
+                        video_player.set_paused(is_paused);
+                    }
+                    api::CrowdPlayerCommand::SetSpeed(speed) => video_player.set_speed(speed),
+                    api::CrowdPlayerCommand::Queue {
+                        currently_playing: new_currently_playing,
+                        queue: new_queue,
+                    } => {
+                        queue.set(new_queue);
+                        currently_playing.set(new_currently_playing);
+                    }
+                    api::CrowdPlayerCommand::Participants(new_participants) => {
+                        participants.set(new_participants)
+                    }
+                    api::CrowdPlayerCommand::GuestsBlocked(blocked) => guests_blocked.set(blocked),
+                    api::CrowdPlayerCommand::Start(started_at) => {
+                        scheduled_start.set(None);
+                        autoplay.set_value(true);
+                        // Catch up with the time it took the command to arrive
+                        let elapsed = (time::UtcDateTime::now() - started_at).as_seconds_f64();
+                        video_player.seek(elapsed.max(0.0));
+                        video_player.set_paused(false);
+                    }
+                    api::CrowdPlayerCommand::Chat(message) => {
+                        if let api::CrowdChatContent::Reaction(reaction) = message.content {
+                            if show_reactions.get_untracked() {
+                                push_reaction(reactions, reaction);
+                            }
+                        }
+                    }
+                }
             })
             .heartbeat(2000)
             .immediate(false),
@@ -44,64 +150,410 @@ pub fn CrowdPlayerPage() -> impl IntoView {
     let status = move || ready_state.get().to_string();
     let connected = move || ready_state.get() == ConnectionReadyState::Open;
 
-    let connect = move |_| {
+    let connect = Callback::new(move |_| {
+        set_handshake_sent.set(false);
+        handshake_accepted.set(false);
         set_initial_message_sent.set(false);
+        error.set(None);
+        crowd.set(None);
+        queue.set(Default::default());
+        currently_playing.set(None);
+        participants.set(Vec::new());
+        guests_blocked.set(false);
+        scheduled_start.set(None);
         open();
-    };
-    let disconnect = move |_| close();
+    });
+    let disconnect = Callback::new(move |_| close());
 
     {
         let send = send.clone();
         Effect::new(move || {
-            if connected() && !initial_message_sent.get() {
-                send(&name.get_untracked());
+            if connected() && !handshake_sent.get() {
+                send(&handshake());
+                set_handshake_sent.set(true);
+            }
+        });
+    }
+
+    {
+        let send = send.clone();
+        Effect::new(move || {
+            if handshake_accepted.get() && !initial_message_sent.get() {
+                let password = password.get_untracked();
+                let start = parse_local_datetime(&start_at.get_untracked())
+                    .filter(|start| *start > time::UtcDateTime::now());
+                scheduled_start.set(start);
+                let create_request = api::CrowdCreateRequest {
+                    name: name.get_untracked(),
+                    password: (!password.is_empty()).then_some(password),
+                    private: private.get_untracked(),
+                    join_code_lifetime_minutes: join_code_lifetime.get_untracked().parse().ok(),
+                    settings: api::CrowdSettings {
+                        queue_mode: queue_mode.get_untracked(),
+                        max_entries_per_participant: max_entries.get_untracked().parse().ok(),
+                        repeat: repeat.get_untracked(),
+                        shuffle: shuffle.get_untracked(),
+                        autofill: autofill.get_untracked(),
+                        ..Default::default()
+                    },
+                    scheduled_start: start,
+                    playlist_id: playlist_id.get_untracked(),
+                };
+                send(&serde_json::to_string(&create_request).unwrap());
                 set_initial_message_sent.set(true);
             }
         });
     }
 
-    let send_message = move |_| {
-        send(&message.get_untracked().to_string());
+    let send_update = {
+        let send = send.clone();
+        Callback::new(move |update: api::CrowdPlayerUpdate| {
+            send(&serde_json::to_string(&(time::UtcDateTime::now(), update)).unwrap());
+        })
     };
+    let is_running = move || connected() && crowd.with(Option::is_some);
+    let moderate = Callback::new(move |command: api::CrowdParticipantCommand| {
+        send_update.run(api::CrowdPlayerUpdate::Moderate(command));
+    });
+
+    Effect::new(move || {
+        let playing = video_player.playing.get();
+        if is_running() {
+            send_update.run(api::CrowdPlayerUpdate::IsPaused(!playing));
+        }
+    });
+
+    let last_sent_position = StoredValue::new(f64::NEG_INFINITY);
+    Effect::new(move || {
+        let position = video_player.current_time.get();
+        if is_running()
+            && (position - last_sent_position.get_value()).abs()
+                >= PLAYBACK_POSITION_UPDATE_INTERVAL
+        {
+            last_sent_position.set_value(position);
+            send_update.run(api::CrowdPlayerUpdate::PlaybackPosition(position));
+        }
+    });
+
+    Effect::new(move || {
+        let duration = video_player.duration.get();
+        if is_running() && duration.is_finite() {
+            send_update.run(api::CrowdPlayerUpdate::Duration(duration));
+        }
+    });
+
+    Effect::new(move || {
+        let speed = video_player.speed.get();
+        if is_running() && speed > 0.0 {
+            send_update.run(api::CrowdPlayerUpdate::Speed(speed));
+        }
+    });
+
+    let backend = use_backend();
+    let playlists = LocalResource::new(move || {
+        let backend = backend.clone();
+        // Guests have no playlists
+        async move {
+            match backend.list_playlists().await {
+                Ok(Ok(playlists)) => playlists,
+                _ => Vec::new(),
+            }
+        }
+    });
+
+    let current_video = Memo::new(move |_| {
+        let entry_id = currently_playing.get()?;
+        queue.with(|queue| {
+            queue
+                .get(&entry_id)
+                .map(|entry| (entry_id, entry.video.clone()))
+        })
+    });
+
+    Effect::new(move || {
+        if current_video.get().is_some() && autoplay.get_value() {
+            video_player.set_paused(false);
+        }
+    });
 
     view! {
-        <div>
-            <p>"Status:" {move || status()}</p>
-            <p>
-                <button on:click=send_message disabled=move || !connected()>
-                    "Send"
-                </button>
-            </p>
-            <p>
-                <button on:click=connect disabled=move || connected()>
-                    "Connect"
-                </button>
-            </p>
-            <p>
-                <button on:click=disconnect disabled=move || !connected()>
-                    "Disconnect"
-                </button>
-            </p>
-            <p>
-                "Name:"
-                <input
-                    type="text"
-                    on:input:target=move |ev| {
-                        set_name.set(ev.target().value());
-                    }
-                    prop:value=name
-                />
-            </p>
-            <p>
-                "Message:"
-                <input
-                    type="text"
-                    on:input:target=move |ev| {
-                        set_message.set(ev.target().value());
-                    }
-                    prop:value=message
-                />
-            </p>
-        </div>
+        <Show
+            when=move || crowd.with(Option::is_some)
+            fallback=move || {
+                view! {
+                    <div>
+                        <p>"Status:" {move || status()}</p>
+                        <p class="text-red-500">{move || error.get()}</p>
+                        <p>
+                            "Name:"
+                            <input
+                                type="text"
+                                on:input:target=move |ev| {
+                                    set_name.set(ev.target().value());
+                                }
+                                prop:value=name
+                            />
+                        </p>
+                        <p>
+                            "Password (optional):"
+                            <input
+                                type="password"
+                                on:input:target=move |ev| {
+                                    set_password.set(ev.target().value());
+                                }
+                                prop:value=password
+                            />
+                        </p>
+                        <p>
+                            "Hide from crowd list:"
+                            <input
+                                type="checkbox"
+                                on:change:target=move |ev| {
+                                    set_private.set(ev.target().checked());
+                                }
+                                prop:checked=private
+                            />
+                        </p>
+                        <p>
+                            "Join code valid for (minutes, optional):"
+                            <input
+                                type="number"
+                                min="1"
+                                on:input:target=move |ev| {
+                                    set_join_code_lifetime.set(ev.target().value());
+                                }
+                                prop:value=join_code_lifetime
+                            />
+                        </p>
+                        <p>
+                            "Queue order:"
+                            <select on:change:target=move |ev| {
+                                set_queue_mode
+                                    .set(
+                                        match ev.target().value().as_str() {
+                                            "voting" => api::CrowdQueueMode::Voting,
+                                            "round_robin" => api::CrowdQueueMode::RoundRobin,
+                                            _ => api::CrowdQueueMode::Manual,
+                                        },
+                                    );
+                            }>
+                                <option value="manual">"Manual"</option>
+                                <option value="voting">"By votes"</option>
+                                <option value="round_robin">"Take turns"</option>
+                            </select>
+                        </p>
+                        <p>
+                            "Max queued videos per participant (optional):"
+                            <input
+                                type="number"
+                                min="1"
+                                on:input:target=move |ev| {
+                                    set_max_entries.set(ev.target().value());
+                                }
+                                prop:value=max_entries
+                            />
+                        </p>
+                        <p>
+                            "Repeat:"
+                            <select on:change:target=move |ev| {
+                                set_repeat
+                                    .set(
+                                        match ev.target().value().as_str() {
+                                            "one" => api::CrowdRepeatMode::One,
+                                            "all" => api::CrowdRepeatMode::All,
+                                            _ => api::CrowdRepeatMode::Off,
+                                        },
+                                    );
+                            }>
+                                <option value="off">"Off"</option>
+                                <option value="one">"Current video"</option>
+                                <option value="all">"Whole queue"</option>
+                            </select>
+                        </p>
+                        <p>
+                            "Shuffle:"
+                            <input
+                                type="checkbox"
+                                on:change:target=move |ev| {
+                                    set_shuffle.set(ev.target().checked());
+                                }
+                                prop:checked=shuffle
+                            />
+                        </p>
+                        <p>
+                            "When the queue is empty:"
+                            <select on:change:target=move |ev| {
+                                set_autofill
+                                    .set(
+                                        match ev.target().value().as_str() {
+                                            "random" => api::CrowdAutofillMode::Random,
+                                            "least_recently_played" => {
+                                                api::CrowdAutofillMode::LeastRecentlyPlayed
+                                            }
+                                            _ => api::CrowdAutofillMode::Off,
+                                        },
+                                    );
+                            }>
+                                <option value="off">"Stop"</option>
+                                <option value="random">"Play a random video"</option>
+                                <option value="least_recently_played">
+                                    "Play the least recently played video"
+                                </option>
+                            </select>
+                        </p>
+                        <p>
+                            "Start playing at (optional):"
+                            <input
+                                type="datetime-local"
+                                on:input:target=move |ev| {
+                                    set_start_at.set(ev.target().value());
+                                }
+                                prop:value=start_at
+                            />
+                        </p>
+                        <p>
+                            "Start with playlist:"
+                            <select on:change:target=move |ev| {
+                                set_playlist_id.set(ev.target().value().parse().ok());
+                            }>
+                                <option value="">"None"</option>
+                                {move || {
+                                    playlists
+                                        .get()
+                                        .map(|playlists| {
+                                            playlists
+                                                .take()
+                                                .into_iter()
+                                                .map(|playlist| {
+                                                    view! {
+                                                        <option value=playlist
+                                                            .playlist_id
+                                                            .to_string()>{playlist.name}</option>
+                                                    }
+                                                })
+                                                .collect_view()
+                                        })
+                                }}
+                            </select>
+                        </p>
+                        <p>
+                            <button
+                                class="bg-blue-500 hover:bg-blue-700 text-white font-bold rounded py-2 px-4 mt-4"
+                                on:click=move |_| connect.run(())
+                                disabled=connected
+                            >
+                                "Start crowd"
+                            </button>
+                        </p>
+                    </div>
+                }
+            }
+        >
+            <div class="flex w-full min-h-screen">
+                <div class="relative flex flex-col w-full">
+                    {move || match current_video.get() {
+                        Some((entry_id, video)) => {
+                            let on_ended = Callback::new(move |_| {
+                                autoplay.set_value(true);
+                                send_update.run(api::CrowdPlayerUpdate::Ended(entry_id));
+                            });
+                            match video {
+                                api::CrowdVideo::Local(video_id) => {
+                                    view! { <VideoPlayer id=video_id on_ended /> }.into_any()
+                                }
+                                api::CrowdVideo::Youtube(youtube_id) => {
+                                    view! {
+                                        <EmbedYoutube
+                                            youtube_id
+                                            autoplay=autoplay.get_value()
+                                            on_ended
+                                        />
+                                    }
+                                        .into_any()
+                                }
+                            }
+                        }
+                        None => {
+                            view! {
+                                <div class="flex flex-1 items-center justify-center w-full bg-black text-gray-400">
+                                    <p>"Waiting for someone to queue a video"</p>
+                                </div>
+                            }
+                                .into_any()
+                        }
+                    }}
+                    <CrowdReactions reactions=reactions />
+                    <div class="absolute inset-0 flex items-center justify-center pointer-events-none text-white text-6xl">
+                        <CrowdCountdown scheduled_start />
+                    </div>
+                </div>
+                <div class="flex flex-col w-[300px] gap-2 p-2">
+                    {move || {
+                        crowd
+                            .get()
+                            .map(|crowd| {
+                                view! {
+                                    <div class="flex flex-col items-center">
+                                        <p>"Join code: " <b>{crowd.join_code.clone()}</b></p>
+                                        <img
+                                            src=format!("/api/crowd/join/{}/qr", crowd.join_code)
+                                            alt="QR code for joining the crowd"
+                                            class="w-64 h-64"
+                                        />
+                                    </div>
+                                }
+                            })
+                    }}
+                    <CrowdQueueList queue currently_playing />
+                    <CrowdPresence participants guests_blocked is_player=true on_command=moderate />
+                    <p>"Status:" {move || status()}</p>
+                    <p class="text-red-500">{move || error.get()}</p>
+                    <p>
+                        "Show reactions:"
+                        <input
+                            type="checkbox"
+                            on:change:target=move |ev| {
+                                set_show_reactions.set(ev.target().checked());
+                            }
+                            prop:checked=show_reactions
+                        />
+                    </p>
+                    <button
+                        class="bg-blue-500 hover:bg-blue-700 text-white font-bold rounded py-2 px-4 mt-4"
+                        on:click=move |_| disconnect.run(())
+                        disabled=move || !connected()
+                    >
+                        "End crowd"
+                    </button>
+                    <button
+                        class="bg-blue-500 hover:bg-blue-700 text-white font-bold rounded py-2 px-4 mt-4"
+                        on:click=move |_| {
+                            let Some(crowd) = crowd.get_untracked() else {
+                                return;
+                            };
+                            let backend = use_backend();
+                            leptos::task::spawn_local(async move {
+                                let request = api::PlaylistFromCrowdRequest {
+                                    name: None,
+                                    exclude_skipped: false,
+                                };
+                                let message = match backend
+                                    .create_playlist_from_crowd(crowd.crowd_id, &request)
+                                    .await
+                                {
+                                    Ok(Ok(playlist)) => format!("Saved playlist {}", playlist.name),
+                                    Ok(Err(e)) => format!("Could not save playlist: {e}"),
+                                    Err(e) => format!("Could not save playlist: {e}"),
+                                };
+                                saved_playlist.set(Some(message));
+                            });
+                        }
+                    >
+                        "Save played videos as playlist"
+                    </button>
+                    <p>{move || saved_playlist.get()}</p>
+                </div>
+            </div>
+        </Show>
     }
 }
@@ -0,0 +1,110 @@
+use leptos::prelude::*;
+
+/// Shows who is in the crowd. When `on_command` is given and `own_id` is the
+/// owner, or this is the player, the other participants get buttons for
+/// muting and kicking them, and guests can be blocked.
+#[component]
+pub fn CrowdPresence(
+    #[prop(into)] participants: Signal<Vec<api::CrowdParticipant>>,
+    #[prop(into, optional)] guests_blocked: Signal<bool>,
+    #[prop(into, optional)] own_id: Signal<Option<api::CrowdParticipantId>>,
+    /// The player acts as the owner.
+    #[prop(optional)]
+    is_player: bool,
+    #[prop(optional)] on_command: Option<Callback<api::CrowdParticipantCommand>>,
+) -> impl IntoView {
+    let is_owner = move || {
+        let Some(own_id) = own_id.get() else {
+            return false;
+        };
+        participants.with(|participants| {
+            participants
+                .iter()
+                .any(|participant| participant.participant_id == own_id && participant.is_owner)
+        })
+    };
+
+    let can_moderate = move || on_command.is_some() && (is_player || is_owner());
+
+    let entries = move || {
+        let can_moderate = can_moderate();
+        participants
+            .get()
+            .into_iter()
+            .map(|participant| {
+                let participant_id = participant.participant_id;
+                let muted = participant.muted;
+                let kick_title = if participant.user_id.is_some() {
+                    "They can not join again"
+                } else {
+                    "Guests can not be told apart, this also blocks guests"
+                };
+                let controls = on_command
+                    .filter(|_| {
+                        can_moderate
+                            && !participant.is_owner
+                            && own_id.get() != Some(participant_id)
+                    })
+                    .map(|on_command| {
+                        view! {
+                            <button on:click=move |_| {
+                                on_command
+                                    .run(api::CrowdParticipantCommand::Mute {
+                                        participant: participant_id,
+                                        muted: !muted,
+                                    })
+                            }>{if muted { "Unmute" } else { "Mute" }}</button>
+                            <button
+                                title=kick_title
+                                on:click=move |_| {
+                                    on_command
+                                        .run(api::CrowdParticipantCommand::Kick(participant_id))
+                                }
+                            >
+                                "Kick"
+                            </button>
+                        }
+                    });
+                view! {
+                    <div class=("text-gray-400", participant.idle) class="flex gap-2 items-center">
+                        {participant
+                            .profile_picture_url
+                            .map(|url| {
+                                view! { <img src=url alt="avatar" class="w-6 h-6 rounded-full" /> }
+                            })}
+                        <span class="flex-1">
+                            {participant.handle} {participant.is_owner.then_some(" 👑")}
+                            {participant.idle.then_some(" (idle)")}
+                            {participant.muted.then_some(" (muted)")}
+                        </span>
+                        {controls}
+                    </div>
+                }
+            })
+            .collect_view()
+    };
+
+    view! {
+        <div class="flex flex-col gap-1">
+            <p class="font-bold">
+                {move || format!("Participants ({})", participants.with(Vec::len))}
+            </p>
+            {entries}
+            {move || {
+                on_command
+                    .filter(|_| can_moderate())
+                    .map(|on_command| {
+                        let blocked = guests_blocked.get();
+                        view! {
+                            <button on:click=move |_| {
+                                on_command.run(api::CrowdParticipantCommand::BlockGuests(!blocked))
+                            }>{if blocked { "Allow guests" } else { "Block guests" }}</button>
+                        }
+                    })
+            }}
+            <Show when=move || guests_blocked.get()>
+                <p class="text-gray-400">"Only logged in users can join."</p>
+            </Show>
+        </div>
+    }
+}
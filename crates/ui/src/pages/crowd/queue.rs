@@ -0,0 +1,192 @@
+use leptos::prelude::*;
+
+use crate::contexts::video_store::{use_video_store, VideoStoreStoreFields};
+
+/// Shows the crowd queue. When `on_command` is given, the entries get controls
+/// for playing, reordering, deleting and voting.
+#[component]
+pub fn CrowdQueueList(
+    #[prop(into)] queue: Signal<api::CrowdQueue>,
+    #[prop(into)] currently_playing: Signal<Option<api::CrowdQueueId>>,
+    #[prop(into, default = api::CrowdQueueMode::Manual.into())] queue_mode: Signal<
+        api::CrowdQueueMode,
+    >,
+    #[prop(optional)] on_command: Option<Callback<api::CrowdParticipantCommand>>,
+) -> impl IntoView {
+    let dragging = RwSignal::new(None::<api::CrowdQueueId>);
+
+    let entries = move || {
+        let queue = queue.get();
+        let ids: Vec<_> = queue.keys().copied().collect();
+        queue
+            .into_iter()
+            .enumerate()
+            .map(|(index, (entry_id, entry))| {
+                let is_current = Signal::derive(move || currently_playing.get() == Some(entry_id));
+                let controls = on_command.map(|on_command| {
+                    let previous = index.checked_sub(1).map(|index| ids[index]);
+                    let next = ids.get(index + 1).copied();
+                    view! {
+                        <CrowdQueueEntryControls
+                            entry_id
+                            previous
+                            next
+                            queue_mode
+                            on_command
+                        />
+                    }
+                });
+                let can_drag =
+                    move || on_command.is_some() && queue_mode.get() == api::CrowdQueueMode::Manual;
+                view! {
+                    <div
+                        draggable=move || can_drag().to_string()
+                        on:dragstart=move |_| dragging.set(Some(entry_id))
+                        on:dragend=move |_| dragging.set(None)
+                        on:dragover=move |ev| {
+                            if can_drag() {
+                                ev.prevent_default();
+                            }
+                        }
+                        on:drop=move |ev| {
+                            ev.prevent_default();
+                            let Some(on_command) = on_command else {
+                                return;
+                            };
+                            let Some(entry_to_move) = dragging.get_untracked() else {
+                                return;
+                            };
+                            if entry_to_move != entry_id {
+                                on_command
+                                    .run(api::CrowdParticipantCommand::MoveInQueue {
+                                        entry_to_move,
+                                        position: api::CrowdQueuePosition::Before(entry_id),
+                                    });
+                            }
+                        }
+                    >
+                        <CrowdQueueListEntry entry is_current>
+                            {controls}
+                        </CrowdQueueListEntry>
+                    </div>
+                }
+            })
+            .collect_view()
+    };
+    // Dropping on an entry moves before it, so the end needs its own target
+    let drop_at_end = move |ev: leptos::ev::DragEvent| {
+        ev.prevent_default();
+        let (Some(on_command), Some(entry_to_move)) = (on_command, dragging.get_untracked()) else {
+            return;
+        };
+        let Some(last) = queue.with_untracked(|queue| queue.keys().last().copied()) else {
+            return;
+        };
+        if entry_to_move != last {
+            on_command.run(api::CrowdParticipantCommand::MoveInQueue {
+                entry_to_move,
+                position: api::CrowdQueuePosition::After(last),
+            });
+        }
+    };
+
+    view! {
+        <div class="flex flex-col gap-1">
+            <Show when=move || !queue.with(|queue| queue.is_empty())>
+                <p class="font-bold">"Queue"</p>
+            </Show>
+            {entries}
+            <Show when=move || dragging.get().is_some()>
+                <div
+                    class="px-2 border border-dashed text-gray-500"
+                    on:dragover=move |ev| ev.prevent_default()
+                    on:drop=drop_at_end
+                >
+                    "Move to the end"
+                </div>
+            </Show>
+        </div>
+    }
+}
+
+#[component]
+fn CrowdQueueListEntry(
+    entry: api::CrowdQueueEntry,
+    #[prop(into)] is_current: Signal<bool>,
+    #[prop(optional)] children: Option<Children>,
+) -> impl IntoView {
+    let video_store = use_video_store();
+    let video = entry.video.clone();
+    let title = move || match &video {
+        api::CrowdVideo::Local(video_id) => video_store
+            .videos()
+            .get()
+            .into_iter()
+            .find(|video| video.video_id == *video_id)
+            .map(|video| video.title)
+            .unwrap_or_else(|| video_id.to_string()),
+        api::CrowdVideo::Youtube(youtube_id) => format!("YouTube: {youtube_id}"),
+    };
+
+    view! {
+        <div class=("bg-green-400", is_current) class="flex gap-2 px-2 items-center">
+            <span class="flex-1">{title}</span>
+            <Show when=move || entry.added_by.is_none()>
+                <span class="text-gray-500">"(autofill)"</span>
+            </Show>
+            <span>{entry.score}</span>
+            {children.map(|children| children())}
+        </div>
+    }
+}
+
+#[component]
+fn CrowdQueueEntryControls(
+    entry_id: api::CrowdQueueId,
+    previous: Option<api::CrowdQueueId>,
+    next: Option<api::CrowdQueueId>,
+    queue_mode: Signal<api::CrowdQueueMode>,
+    on_command: Callback<api::CrowdParticipantCommand>,
+) -> impl IntoView {
+    let is_manual = move || queue_mode.get() == api::CrowdQueueMode::Manual;
+    let is_voting = move || queue_mode.get() == api::CrowdQueueMode::Voting;
+    let vote = move |vote| {
+        on_command.run(api::CrowdParticipantCommand::Vote {
+            entry: entry_id,
+            vote,
+        })
+    };
+    let move_to = move |position| {
+        on_command.run(api::CrowdParticipantCommand::MoveInQueue {
+            entry_to_move: entry_id,
+            position,
+        })
+    };
+
+    view! {
+        <button on:click=move |_| on_command.run(api::CrowdParticipantCommand::GoTo(entry_id))>
+            "▶"
+        </button>
+        <Show when=is_voting>
+            <button on:click=move |_| vote(api::CrowdVote::Up)>"👍"</button>
+            <button on:click=move |_| vote(api::CrowdVote::Down)>"👎"</button>
+        </Show>
+        <Show when=move || is_manual() && previous.is_some()>
+            <button on:click=move |_| {
+                if let Some(previous) = previous {
+                    move_to(api::CrowdQueuePosition::Before(previous));
+                }
+            }>"↑"</button>
+        </Show>
+        <Show when=move || is_manual() && next.is_some()>
+            <button on:click=move |_| {
+                if let Some(next) = next {
+                    move_to(api::CrowdQueuePosition::After(next));
+                }
+            }>"↓"</button>
+        </Show>
+        <button on:click=move |_| {
+            on_command.run(api::CrowdParticipantCommand::DeleteFromQueue(entry_id))
+        }>"✕"</button>
+    }
+}
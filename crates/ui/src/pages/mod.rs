@@ -1,10 +1,13 @@
 pub mod auth;
 
+mod admin;
 mod crowd;
 mod downloads;
 mod settings;
 mod videos;
 
+pub use admin::AdminPage;
+pub use crowd::join::CrowdJoinPage;
 pub use crowd::list::CrowdListPage;
 pub use crowd::participant::CrowdParticipantPage;
 pub use crowd::player::CrowdPlayerPage;
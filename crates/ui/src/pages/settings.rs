@@ -1,7 +1,11 @@
 use leptos::prelude::*;
 use leptos_router::{hooks::use_navigate, NavigateOptions};
+use strum::VariantArray;
 
-use crate::contexts::backend::use_backend;
+use crate::{
+    contexts::backend::use_backend,
+    errors::{map_gloo_net_error, AppError},
+};
 
 #[component]
 pub fn SettingsPage() -> impl IntoView {
@@ -39,7 +43,10 @@ pub fn SettingsPage() -> impl IntoView {
 
     view! {
         <div class="flex flex-col flex-1 justify-center items-center">
-            <Transition fallback=move || view! {}>{profile_view}</Transition>
+            <Transition fallback=move || ()>{profile_view}</Transition>
+            <LinkedIdentities />
+            <Sessions />
+            <ApiTokens />
             <button
                 class="bg-blue-500 hover:bg-blue-700 text-white font-bold rounded py-2 px-4 mt-4"
                 on:click=move |_| {
@@ -100,3 +107,344 @@ pub fn SettingsPage() -> impl IntoView {
         </div>
     }
 }
+
+/// Lists the identities the user can log in with, and lets them link and
+/// unlink providers.
+#[component]
+fn LinkedIdentities() -> impl IntoView {
+    let backend = use_backend();
+    let (error, set_error) = signal::<Result<(), AppError>>(Ok(()));
+
+    let identities = LocalResource::new({
+        let backend = backend.clone();
+        move || {
+            let backend = backend.clone();
+            async move { map_gloo_net_error(backend.list_identities().await) }
+        }
+    });
+    let providers = LocalResource::new(move || {
+        let backend = backend.clone();
+        async move { map_gloo_net_error(backend.auth_providers().await).unwrap_or_default() }
+    });
+
+    let unlink = move |identity: api::OidcMappingId| {
+        let backend = use_backend();
+        leptos::task::spawn_local(async move {
+            match map_gloo_net_error(backend.unlink_identity(identity).await) {
+                Ok(()) => identities.refetch(),
+                Err(e) => set_error(Err(e)),
+            }
+        });
+    };
+    let link = move |provider: String| {
+        let backend = use_backend();
+        let navigate = use_navigate();
+        leptos::task::spawn_local(async move {
+            let auth_url = backend
+                .link_identity(&api::AuthUrlRequest { provider })
+                .await;
+            match map_gloo_net_error(auth_url) {
+                Ok(auth_url) => {
+                    navigate(
+                        &auth_url.url,
+                        NavigateOptions {
+                            resolve: false,
+                            ..Default::default()
+                        },
+                    );
+                }
+                Err(e) => set_error(Err(e)),
+            }
+        });
+    };
+
+    let identities_view = move || {
+        identities.get().map(|identities| {
+            identities.take().map(|identities| {
+                let can_unlink = identities.len() > 1;
+                identities
+                    .into_iter()
+                    .map(|identity| {
+                        let oidc_mapping_id = identity.oidc_mapping_id;
+                        view! {
+                            <div class="flex flex-row gap-2 items-center">
+                                <span>
+                                    {identity
+                                        .provider_name
+                                        .unwrap_or(identity.oidc_issuer_url)}
+                                </span>
+                                <button
+                                    class="text-red-500 disabled:text-gray-400"
+                                    disabled=!can_unlink
+                                    on:click=move |_| unlink(oidc_mapping_id)
+                                >
+                                    "Unlink"
+                                </button>
+                            </div>
+                        }
+                    })
+                    .collect_view()
+            })
+        })
+    };
+
+    view! {
+        <div class="flex flex-col gap-2 mt-4">
+            <p class="font-bold">"Linked identities"</p>
+            <Transition fallback=move || ()>{identities_view}</Transition>
+            <div class="flex flex-row gap-2 items-center">
+                "Link another provider:"
+                {move || {
+                    providers
+                        .get()
+                        .map(|providers| {
+                            providers
+                                .take()
+                                .into_iter()
+                                .map(|provider| {
+                                    let id = provider.id;
+                                    view! {
+                                        <button
+                                            class="bg-blue-500 hover:bg-blue-700 text-white rounded py-1 px-2"
+                                            on:click=move |_| link(id.clone())
+                                        >
+                                            {provider.name}
+                                        </button>
+                                    }
+                                })
+                                .collect_view()
+                        })
+                }}
+            </div>
+            {move || error.get()}
+        </div>
+    }
+}
+
+/// Lists where the user is logged in, and lets them log out other devices.
+#[component]
+fn Sessions() -> impl IntoView {
+    let backend = use_backend();
+    let (error, set_error) = signal::<Result<(), AppError>>(Ok(()));
+
+    let sessions = LocalResource::new(move || {
+        let backend = backend.clone();
+        async move { map_gloo_net_error(backend.list_sessions().await) }
+    });
+
+    let revoke = move |session: api::UserSessionId| {
+        let backend = use_backend();
+        leptos::task::spawn_local(async move {
+            match map_gloo_net_error(backend.revoke_session(session).await) {
+                Ok(()) => sessions.refetch(),
+                Err(e) => set_error(Err(e)),
+            }
+        });
+    };
+
+    let sessions_view = move || {
+        sessions.get().map(|sessions| {
+            sessions.take().map(|sessions| {
+                sessions
+                    .into_iter()
+                    .map(|session| {
+                        let user_session_id = session.user_session_id;
+                        let last_active = session.last_active_at;
+                        let action = if session.current {
+                            view! { <span class="text-gray-500">"This device"</span> }.into_any()
+                        } else {
+                            view! {
+                                <button
+                                    class="text-red-500"
+                                    on:click=move |_| revoke(user_session_id)
+                                >
+                                    "Log out"
+                                </button>
+                            }
+                            .into_any()
+                        };
+                        view! {
+                            <div class="flex flex-row gap-2 items-center">
+                                <span class="flex-1 truncate max-w-md">
+                                    {session
+                                        .user_agent
+                                        .unwrap_or_else(|| "Unknown device".to_string())}
+                                </span>
+                                <span class="text-gray-500">
+                                    {format!(
+                                        "Last active {}-{:02}-{:02} {:02}:{:02}",
+                                        last_active.year(),
+                                        u8::from(last_active.month()),
+                                        last_active.day(),
+                                        last_active.hour(),
+                                        last_active.minute(),
+                                    )}
+                                </span>
+                                {action}
+                            </div>
+                        }
+                    })
+                    .collect_view()
+            })
+        })
+    };
+
+    view! {
+        <div class="flex flex-col gap-2 mt-4">
+            <p class="font-bold">"Sessions"</p>
+            <Transition fallback=move || ()>{sessions_view}</Transition>
+            {move || error.get()}
+        </div>
+    }
+}
+
+/// Lists the personal API tokens of the user, for scripts and bots, and lets
+/// them create and revoke tokens.
+#[component]
+fn ApiTokens() -> impl IntoView {
+    let backend = use_backend();
+    let (error, set_error) = signal::<Result<(), AppError>>(Ok(()));
+    let name = RwSignal::new(String::new());
+    let scopes = RwSignal::new(Vec::<api::ApiTokenScope>::new());
+    // Only available right after creating the token
+    let (created_token, set_created_token) = signal::<Option<String>>(None);
+
+    let api_tokens = LocalResource::new(move || {
+        let backend = backend.clone();
+        async move { map_gloo_net_error(backend.list_api_tokens().await) }
+    });
+
+    let create = move |_| {
+        let backend = use_backend();
+        leptos::task::spawn_local(async move {
+            let request = api::ApiTokenCreateRequest {
+                name: name.get_untracked(),
+                scopes: scopes.get_untracked(),
+            };
+            match map_gloo_net_error(backend.create_api_token(&request).await) {
+                Ok(created) => {
+                    set_created_token(Some(created.token));
+                    name.set(String::new());
+                    scopes.set(Vec::new());
+                    api_tokens.refetch();
+                }
+                Err(e) => set_error(Err(e)),
+            }
+        });
+    };
+    let revoke = move |api_token: api::ApiTokenId| {
+        let backend = use_backend();
+        leptos::task::spawn_local(async move {
+            match map_gloo_net_error(backend.revoke_api_token(api_token).await) {
+                Ok(()) => api_tokens.refetch(),
+                Err(e) => set_error(Err(e)),
+            }
+        });
+    };
+
+    let api_tokens_view = move || {
+        api_tokens.get().map(|api_tokens| {
+            api_tokens.take().map(|api_tokens| {
+                api_tokens
+                    .into_iter()
+                    .map(|api_token| {
+                        let api_token_id = api_token.api_token_id;
+                        let scopes = api_token
+                            .scopes
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        let last_used = match api_token.last_used_at {
+                            Some(last_used) => format!(
+                                "Last used {}-{:02}-{:02} {:02}:{:02}",
+                                last_used.year(),
+                                u8::from(last_used.month()),
+                                last_used.day(),
+                                last_used.hour(),
+                                last_used.minute(),
+                            ),
+                            None => "Never used".to_string(),
+                        };
+                        view! {
+                            <div class="flex flex-row gap-2 items-center">
+                                <span class="flex-1 truncate max-w-md">{api_token.name}</span>
+                                <span class="text-gray-500">{scopes}</span>
+                                <span class="text-gray-500">{last_used}</span>
+                                <button
+                                    class="text-red-500"
+                                    on:click=move |_| revoke(api_token_id)
+                                >
+                                    "Revoke"
+                                </button>
+                            </div>
+                        }
+                    })
+                    .collect_view()
+            })
+        })
+    };
+
+    view! {
+        <div class="flex flex-col gap-2 mt-4">
+            <p class="font-bold">"API tokens"</p>
+            <Transition fallback=move || ()>{api_tokens_view}</Transition>
+            {move || {
+                created_token
+                    .get()
+                    .map(|token| {
+                        view! {
+                            <div class="flex flex-col gap-1">
+                                "Copy the new token now, it won't be shown again:"
+                                <code class="bg-gray-100 p-1 select-all">{token}</code>
+                            </div>
+                        }
+                    })
+            }}
+            <div class="flex flex-row flex-wrap gap-2 items-center">
+                <input
+                    class="bg-gray-100 border"
+                    placeholder="Token name"
+                    type="text"
+                    prop:value=name
+                    on:input=move |e| name.set(event_target_value(&e))
+                />
+                {api::ApiTokenScope::VARIANTS
+                    .iter()
+                    .copied()
+                    .map(|scope| {
+                        view! {
+                            <label class="flex flex-row gap-1 items-center">
+                                <input
+                                    type="checkbox"
+                                    prop:checked=move || scopes.read().contains(&scope)
+                                    on:change=move |_| {
+                                        scopes
+                                            .update(|scopes| {
+                                                if let Some(index) = scopes
+                                                    .iter()
+                                                    .position(|s| *s == scope)
+                                                {
+                                                    scopes.remove(index);
+                                                } else {
+                                                    scopes.push(scope);
+                                                }
+                                            })
+                                    }
+                                />
+                                {scope.to_string()}
+                            </label>
+                        }
+                    })
+                    .collect_view()}
+                <button
+                    class="bg-blue-500 hover:bg-blue-700 text-white rounded py-1 px-2"
+                    on:click=create
+                >
+                    "Create token"
+                </button>
+            </div>
+            {move || error.get()}
+        </div>
+    }
+}
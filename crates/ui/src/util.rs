@@ -8,7 +8,6 @@ where
     let element = document()
         .get_element_by_id(id)?
         .dyn_into::<T>()
-        .ok()
         .expect("wrong type");
     Some(element)
 }
@@ -0,0 +1,9 @@
+DROP TABLE IF EXISTS playlist_entries;
+
+DROP INDEX IF EXISTS idx_playlists_user_id;
+DROP TABLE IF EXISTS playlists;
+
+DROP INDEX IF EXISTS idx_crowd_history_crowd_id;
+DROP TABLE IF EXISTS crowd_history;
+
+DROP TABLE IF EXISTS crowds;
@@ -0,0 +1,63 @@
+CREATE TABLE crowds (
+    crowd_id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    owner_id UUID,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ended_at TIMESTAMPTZ,
+    -- Only the owner can see the history of private crowds
+    private BOOLEAN NOT NULL DEFAULT false,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    FOREIGN KEY (owner_id) REFERENCES users(user_id) ON DELETE SET NULL
+);
+
+CREATE TABLE crowd_history (
+    crowd_history_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    crowd_id UUID NOT NULL,
+    -- NULL for YouTube videos that were not downloaded
+    video_id UUID,
+    youtube_id TEXT,
+    -- User who queued the video, NULL for guests, autofill and deleted users
+    added_by UUID,
+    -- Handle of who queued the video, kept for guests and deleted users
+    added_by_handle TEXT,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ended_at TIMESTAMPTZ,
+    skipped BOOLEAN NOT NULL DEFAULT false,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    FOREIGN KEY (crowd_id) REFERENCES crowds(crowd_id) ON DELETE CASCADE,
+    FOREIGN KEY (video_id) REFERENCES videos(video_id) ON DELETE CASCADE,
+    FOREIGN KEY (added_by) REFERENCES users(user_id) ON DELETE SET NULL,
+    CONSTRAINT crowd_history_video_or_youtube
+        CHECK (video_id IS NOT NULL OR youtube_id IS NOT NULL)
+);
+
+CREATE INDEX idx_crowd_history_crowd_id ON crowd_history(crowd_id);
+
+CREATE TABLE playlists (
+    playlist_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL,
+    user_id UUID NOT NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_playlists_user_id ON playlists(user_id);
+
+CREATE TABLE playlist_entries (
+    playlist_id UUID NOT NULL,
+    position INTEGER NOT NULL,
+    video_id UUID NOT NULL,
+
+    PRIMARY KEY (playlist_id, position),
+    FOREIGN KEY (playlist_id) REFERENCES playlists(playlist_id) ON DELETE CASCADE,
+    FOREIGN KEY (video_id) REFERENCES videos(video_id) ON DELETE CASCADE
+);
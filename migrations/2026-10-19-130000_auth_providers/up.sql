@@ -0,0 +1 @@
+ALTER TABLE user_sessions ADD COLUMN auth_provider_id VARCHAR(255);
@@ -0,0 +1 @@
+ALTER TABLE user_sessions ADD COLUMN login_started_at TIMESTAMPTZ;
@@ -53,6 +53,7 @@ with lib;
           RUST_LOG="info";
           DATABASE_URL="postgres://postgres:postgres@db/listen";
           VIDEOS_DIR="/videos";
+          PUBLIC_URL="https://${cfg.domain}";
         };
         volumes = [
           "/var/lib/listen/videos:/videos"